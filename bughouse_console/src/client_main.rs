@@ -29,6 +29,7 @@ pub struct ClientConfig {
     // TODO: Allow to create new contests from the console client.
     pub contest_id: String,
     pub player_name: String,
+    pub spectator: bool,
 }
 
 enum IncomingEvent {
@@ -46,6 +47,30 @@ fn writeln_raw(stdout: &mut io::Stdout, v: impl fmt::Display) -> io::Result<()>
     Ok(())
 }
 
+fn render_chat(stdout: &mut io::Stdout, chat: &[ChatMessage]) -> io::Result<()> {
+    const MAX_CHAT_LINES: usize = 5;
+    for message in chat.iter().skip(chat.len().saturating_sub(MAX_CHAT_LINES)) {
+        let line = format!("{}: {}", message.sender, message.text);
+        match message.channel {
+            ChatChannel::All => writeln_raw(stdout, line)?,
+            ChatChannel::Team => writeln_raw(stdout, format!("[team] {}", line).with(style::Color::Cyan))?,
+        }
+    }
+    if !chat.is_empty() {
+        writeln_raw(stdout, "")?;
+    }
+    Ok(())
+}
+
+fn contest_result_message(result: &ContestResult) -> String {
+    let outcome = if result.winners.is_empty() {
+        "draw".to_owned()
+    } else {
+        format!("{} won", result.winners.join(" & "))
+    };
+    format!("Contest over after {} games: {} ({})", result.games_played, outcome, result.scores.to_human_readable())
+}
+
 fn render(
     stdout: &mut io::Stdout, app_start_time: Instant, client_state: &ClientState,
     keyboard_input: &str, command_error: &Option<String>
@@ -55,14 +80,18 @@ fn render(
     let now = Instant::now();
     execute!(stdout, cursor::MoveTo(0, 0))?;
     let mut highlight_input = false;
-    let mut additional_message = None;
+    let mut additional_messages = vec![];
     if let Some(contest) = client_state.contest() {
-        if let Some(GameState{ ref alt_game, time_pair, .. }) = contest.game_state {
+        if let Some(GameState{ ref alt_game, time_pair, ref partner_messages, .. }) = contest.game_state {
             // TODO: Show scores
             let my_id = alt_game.my_id();
             let game_now = GameInstant::from_pair_game_maybe_active(time_pair, now);
             let game = alt_game.local_game();
-            writeln_raw(stdout, format!("{}\n", tui::render_bughouse_game(&game, my_id, game_now)))?;
+            writeln_raw(stdout, format!("{}\n", tui::render_bughouse_game(&game, &contest.players, my_id, game_now)))?;
+            if !contest.spectators.is_empty() {
+                writeln_raw(stdout, format!("Spectators: {}\n", contest.spectators.len()))?;
+            }
+            render_chat(stdout, &contest.chat)?;
             // Note. Don't clear the board to avoid blinking.
             // TODO: Show last turn by opponent.
             execute!(stdout, terminal::Clear(terminal::ClearType::FromCursorDown))?;
@@ -70,8 +99,29 @@ fn render(
                 if let BughouseParticipantId::Player(my_player_id) = my_id {
                     highlight_input = game.player_is_active(my_player_id);
                 }
+                let partner_message = partner_messages.iter().rev()
+                    .find(|r| BughouseParticipantId::Player(r.player_id) != my_id);
+                if let Some(record) = partner_message {
+                    additional_messages.push(
+                        format!("Partner says: {}", record.message.to_log_entry()).with(style::Color::Cyan)
+                    );
+                }
+                if let Some(piece_kind) = client_state.partner_hint() {
+                    additional_messages.push(
+                        format!("Partner needs: {}", piece_kind.to_full_algebraic()).with(style::Color::Yellow)
+                    );
+                }
+                if client_state.is_game_paused() {
+                    additional_messages.push(
+                        "Game paused: waiting for all players to rejoin".to_owned().with(style::Color::Yellow)
+                    );
+                }
+            } else if let Some(ref result) = contest.result {
+                additional_messages.push(
+                    contest_result_message(result).with(style::Color::Magenta)
+                );
             } else {
-                additional_message = Some(
+                additional_messages.push(
                     format!("Game over: {:?}", game.status()).with(style::Color::Magenta)
                 );
             }
@@ -103,10 +153,20 @@ fn render(
                 Teaming::IndividualMode => {
                     for p in players {
                         assert!(p.fixed_team.is_none());
-                        writeln_raw(stdout, format!("  {} {}", "•", p.name))?;
+                        let owner_mark = if contest.owner.as_ref() == Some(&p.name) { " (owner)" } else { "" };
+                        let name = tui::render_player_name(&p.name, p.presence);
+                        writeln_raw(stdout, format!("  {} {}{}", "•", name, owner_mark))?;
+                    }
+                    if contest.next_up.len() > TOTAL_PLAYERS {
+                        writeln_raw(stdout, format!("\nWaiting: {}", contest.next_up[TOTAL_PLAYERS..].join(", ")))?;
                     }
                 },
             }
+            if !contest.spectators.is_empty() {
+                writeln_raw(stdout, format!("\nSpectators: {}", contest.spectators.join(", ")))?;
+            }
+            writeln_raw(stdout, "")?;
+            render_chat(stdout, &contest.chat)?;
         }
     } else {
         execute!(stdout, terminal::Clear(terminal::ClearType::All))?;
@@ -122,7 +182,7 @@ fn render(
     // Improvement potential. Show input on a fixed line regardless of client_status.
     writeln_raw(stdout, format!("{}\n", input_with_cursor.with(input_style)))?;
 
-    if let Some(msg) = additional_message {
+    for msg in additional_messages {
         writeln_raw(stdout, msg)?;
     }
     if let Some(ref err) = command_error {
@@ -134,6 +194,7 @@ fn render(
 pub fn run(config: ClientConfig) -> io::Result<()> {
     let contest_id = config.contest_id.trim().to_owned();
     let my_name = config.player_name.trim().to_owned();
+    let spectator = config.spectator;
     let server_addr = (config.server_address.as_str(), network::PORT).to_socket_addrs().unwrap().collect_vec();
     println!("Connecting to {:?}...", server_addr);
     let stream = TcpStream::connect(&server_addr[..])?;
@@ -146,7 +207,7 @@ pub fn run(config: ClientConfig) -> io::Result<()> {
 
     let std_panic_hook = panic::take_hook();
     panic::set_hook(Box::new(move |panic_info| {
-        // TODO: Also report the error via `BughouseClientEvent::ReportError`.
+        // TODO: Also report the error via `BughouseClientEvent::ReportErrorWithContext`.
         _ = terminal::disable_raw_mode();
         _ = execute!(io::stdout(), terminal::LeaveAlternateScreen);
         std_panic_hook(panic_info);
@@ -197,7 +258,11 @@ pub fn run(config: ClientConfig) -> io::Result<()> {
     let mut client_state = ClientState::new(user_agent, time_zone, server_tx);
     let mut keyboard_input = String::new();
     let mut command_error = None;
-    client_state.join(contest_id, my_name.to_owned());
+    if spectator {
+        client_state.spectate(contest_id, my_name.to_owned());
+    } else {
+        client_state.join(contest_id, my_name.to_owned());
+    }
     for event in rx {
         match event {
             IncomingEvent::Network(event) => {
@@ -224,7 +289,31 @@ pub fn run(config: ClientConfig) -> io::Result<()> {
                                         client_state.resign();
                                     },
                                     _ => {
-                                        command_error = Some(format!("Unknown command: '{}'", cmd));
+                                        let args = cmd.split_whitespace().collect_vec();
+                                        match args.as_slice() {
+                                            ["kick", name] => client_state.kick_player((*name).to_owned(), false),
+                                            ["ban", name] => client_state.kick_player((*name).to_owned(), true),
+                                            ["owner", name] => client_state.transfer_ownership((*name).to_owned()),
+                                            ["votekick", name] => client_state.vote_kick((*name).to_owned()),
+                                            ["chat", _, ..] => client_state.send_chat_message(
+                                                ChatChannel::All, cmd["chat".len()..].trim().to_owned()
+                                            ),
+                                            ["teamchat", _, ..] => client_state.send_chat_message(
+                                                ChatChannel::Team, cmd["teamchat".len()..].trim().to_owned()
+                                            ),
+                                            _ => {
+                                                let message = match args.as_slice() {
+                                                    [command] => PartnerMessage::parse(command, None),
+                                                    [command, piece] => PartnerMessage::parse(command, Some(piece)),
+                                                    _ => None,
+                                                };
+                                                if let Some(message) = message {
+                                                    client_state.send_partner_message(message);
+                                                } else {
+                                                    command_error = Some(format!("Unknown command: '{}'", cmd));
+                                                }
+                                            },
+                                        }
                                     },
                                 }
                             } else {
@@ -261,7 +350,13 @@ pub fn run(config: ClientConfig) -> io::Result<()> {
                 NotableEvent::GameStarted => {
                     execute!(stdout, terminal::Clear(terminal::ClearType::All))?;
                 },
+                NotableEvent::GamePaused | NotableEvent::GameResumed => {
+                    // Shown by `render`.
+                },
                 NotableEvent::GameOver(..) => {},
+                NotableEvent::ContestOver => {
+                    // Shown by `render`.
+                },
                 NotableEvent::MyTurnMade => {},
                 NotableEvent::OpponentTurnMade => {},
                 NotableEvent::MyReserveRestocked => {},
@@ -269,6 +364,19 @@ pub fn run(config: ClientConfig) -> io::Result<()> {
                 NotableEvent::GameExportReady(..) => {
                     // Improvement potential: Implement.
                 },
+                NotableEvent::PartnerNeedsPiece(..) => {
+                    // Shown by `render`.
+                },
+                NotableEvent::PartnerMessageReceived(..) => {
+                    // Shown by `render`.
+                },
+                NotableEvent::Kicked{ banned } => {
+                    command_error = Some(if banned {
+                        "You have been banned from the contest".to_owned()
+                    } else {
+                        "You have been kicked from the contest".to_owned()
+                    });
+                },
             }
         }
         render(&mut stdout, app_start_time, &client_state, &keyboard_input, &command_error)?;
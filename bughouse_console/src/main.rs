@@ -25,6 +25,7 @@ pub mod network;
 pub mod tui;
 
 mod client_main;
+mod metrics;
 mod sqlx_server_hooks;
 mod server_main;
 mod stress_test;
@@ -53,6 +54,7 @@ fn main() -> io::Result<()> {
                 .about("Run as server")
                 .arg(arg!(--"sqlite-db" [DB] "Path to an sqlite database file"))
                 .arg(arg!(--"postgres-db" [DB] "Address of a postgres database"))
+                .arg(arg!(--"metrics-address" [ADDRESS] "Serve Prometheus metrics at http://<ADDRESS>/metrics, e.g. 127.0.0.1:14363"))
         )
         .subcommand(
             Command::new("client")
@@ -60,6 +62,7 @@ fn main() -> io::Result<()> {
                 .arg(arg!(<server_address> "Server address"))
                 .arg(arg!(<contest_id> "Contest ID"))
                 .arg(arg!(<player_name> "Player name"))
+                .arg(arg!(--spectator "Watch the contest without participating"))
         )
         .subcommand(
             Command::new("stress-test")
@@ -81,7 +84,8 @@ fn main() -> io::Result<()> {
                 (None, Some(db)) => DatabaseOptions::Postgres(db.clone()),
             };
             server_main::run(server_main::ServerConfig {
-                database_options
+                database_options,
+                metrics_address: sub_matches.get_one::<String>("metrics-address").cloned(),
             });
             Ok(())
         },
@@ -90,6 +94,7 @@ fn main() -> io::Result<()> {
                 server_address: sub_matches.get_one::<String>("server_address").unwrap().clone(),
                 contest_id: sub_matches.get_one::<String>("contest_id").unwrap().clone(),
                 player_name: sub_matches.get_one::<String>("player_name").unwrap().clone(),
+                spectator: sub_matches.get_flag("spectator"),
             })
         },
         Some(("stress-test", sub_matches)) => {
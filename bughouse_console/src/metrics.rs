@@ -0,0 +1,138 @@
+// Server-side meters export: a Prometheus endpoint and a periodic log summary.
+//
+// The endpoint is a bare-bones HTTP responder on top of `TcpListener`: it is only
+// meant to be scraped by a monitoring system, so there is no need for a web framework.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+use itertools::Itertools;
+use log::{info, warn};
+
+use bughouse_chess::meter::{MeterBuckets, SyncMeterBox};
+
+
+const METRIC_PREFIX: &str = "bughouse_";
+const LOG_SUMMARY_INTERVAL: Duration = Duration::from_secs(300);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Renders meters in Prometheus text exposition format. Each meter becomes a histogram, so that
+// quantiles over any time window can be computed on the monitoring side, e.g.
+//   histogram_quantile(0.99, rate(bughouse_tick_lag_us_bucket[5m]))
+pub fn prometheus_text(meters: &HashMap<String, MeterBuckets>) -> String {
+    let mut text = String::new();
+    for (name, meter) in meters.iter().sorted_by_key(|(name, _)| *name) {
+        let name = metric_name(name);
+        text.push_str(&format!("# TYPE {name} histogram\n"));
+        for (bound, count) in meter.buckets.iter() {
+            text.push_str(&format!("{name}_bucket{{le=\"{bound}\"}} {count}\n"));
+        }
+        text.push_str(&format!("{name}_bucket{{le=\"+Inf\"}} {}\n", meter.num_values));
+        text.push_str(&format!("{name}_sum {}\n", meter.sum));
+        text.push_str(&format!("{name}_count {}\n", meter.num_values));
+    }
+    text
+}
+
+fn metric_name(meter_name: &str) -> String {
+    let sanitized: String = meter_name.chars()
+        .map(|ch| if ch.is_ascii_alphanumeric() || ch == '_' { ch } else { '_' })
+        .collect();
+    format!("{METRIC_PREFIX}{sanitized}")
+}
+
+// Serves `GET /metrics` on the given address. Never returns.
+pub fn serve_metrics(address: &str, meter_box: SyncMeterBox) {
+    let listener = TcpListener::bind(address)
+        .unwrap_or_else(|err| panic!("Cannot bind metrics endpoint to {address}:\n{err}"));
+    info!("Serving metrics on {}...", listener.local_addr().unwrap());
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                if let Err(err) = handle_metrics_request(stream, &meter_box) {
+                    warn!("Cannot serve metrics: {}", err);
+                }
+            }
+            Err(err) => {
+                warn!("Cannot establish metrics connection: {}", err);
+            }
+        }
+    }
+    panic!("Unexpected end of metrics TcpListener::incoming");
+}
+
+fn handle_metrics_request(stream: TcpStream, meter_box: &SyncMeterBox) -> io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Skip headers: the request is answered the same way regardless of them.
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+    }
+    let (status, content_type, body) = match request_line.split_whitespace().collect_vec()[..] {
+        ["GET", "/metrics", ..] => (
+            "200 OK",
+            "text/plain; version=0.0.4",
+            prometheus_text(&meter_box.read_buckets()),
+        ),
+        _ => ("404 Not Found", "text/plain", "Not found\n".to_owned()),
+    };
+    let mut stream = reader.into_inner();
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\
+            Connection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()
+}
+
+// Periodically logs stats for meters that got new values since the previous summary.
+// Never returns.
+pub fn log_metrics_summary(meter_box: SyncMeterBox) {
+    loop {
+        thread::sleep(LOG_SUMMARY_INTERVAL);
+        let stats = meter_box.consume_recent_stats();
+        for (name, stats) in stats.iter().sorted_by_key(|(name, _)| *name) {
+            if stats.num_values > 0 {
+                info!("Meter {}: {}", name, stats);
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prometheus_text_format() {
+        let meters = HashMap::from([
+            ("tick_lag_us".to_owned(), MeterBuckets{ buckets: vec![(1, 3), (2, 9)], sum: 25, num_values: 10 }),
+            ("apply-event".to_owned(), MeterBuckets{ buckets: vec![(1, 20), (2, 20)], sum: 20, num_values: 20 }),
+        ]);
+        assert_eq!(prometheus_text(&meters), "\
+# TYPE bughouse_apply_event histogram
+bughouse_apply_event_bucket{le=\"1\"} 20
+bughouse_apply_event_bucket{le=\"2\"} 20
+bughouse_apply_event_bucket{le=\"+Inf\"} 20
+bughouse_apply_event_sum 20
+bughouse_apply_event_count 20
+# TYPE bughouse_tick_lag_us histogram
+bughouse_tick_lag_us_bucket{le=\"1\"} 3
+bughouse_tick_lag_us_bucket{le=\"2\"} 9
+bughouse_tick_lag_us_bucket{le=\"+Inf\"} 10
+bughouse_tick_lag_us_sum 25
+bughouse_tick_lag_us_count 10
+");
+    }
+}
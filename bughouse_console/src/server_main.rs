@@ -6,15 +6,17 @@
 use std::net::{TcpStream, TcpListener};
 use std::sync::{Arc, Mutex, mpsc};
 use std::thread;
-use std::time::Duration;
+use std::time::Instant;
 
 use log::{info, warn};
 use tungstenite::protocol;
 
 use bughouse_chess::*;
+use bughouse_chess::meter::{SyncMeter, SyncMeterBox};
 use bughouse_chess::server::*;
 use bughouse_chess::server_hooks::ServerHooks;
 
+use crate::metrics;
 use crate::network::{self, CommunicationError};
 use crate::sqlx_server_hooks::*;
 
@@ -26,14 +28,17 @@ pub enum DatabaseOptions {
 
 pub struct ServerConfig {
     pub database_options: DatabaseOptions,
+    pub metrics_address: Option<String>,
 }
 
 fn to_debug_string<T: std::fmt::Debug>(v: T) -> String {
     format!("{v:?}")
 }
 
-fn handle_connection(stream: TcpStream, clients: &Arc<Mutex<Clients>>, tx: mpsc::Sender<IncomingEvent>)
-    -> Result<(), String>
+fn handle_connection(
+    stream: TcpStream, clients: &Arc<Mutex<Clients>>, tx: mpsc::Sender<IncomingEvent>,
+    network_write_meter: SyncMeter,
+) -> Result<(), String>
 {
     let peer_addr = stream.peer_addr().map_err(to_debug_string)?;
     info!("Client connected: {}", peer_addr);
@@ -66,8 +71,9 @@ fn handle_connection(stream: TcpStream, clients: &Arc<Mutex<Clients>>, tx: mpsc:
     });
     thread::spawn(move || {
         for ev in client_rx {
+            let write_start = Instant::now();
             match network::write_obj(&mut socket_out, &ev) {
-                Ok(()) => {},
+                Ok(()) => network_write_meter.record_duration_micros(write_start.elapsed()),
                 Err(err) => {
                     if let Some(logging_id) = clients_remover2.lock().unwrap().remove_client(client_id) {
                         warn!("Client {} disconnected due to write error: {:?}", logging_id, err);
@@ -81,11 +87,20 @@ fn handle_connection(stream: TcpStream, clients: &Arc<Mutex<Clients>>, tx: mpsc:
 }
 
 pub fn run(config: ServerConfig) {
+    let meter_box = SyncMeterBox::new();
+    let network_write_meter = meter_box.meter("network_write_us".to_owned());
+    if let Some(metrics_address) = config.metrics_address {
+        let meter_box = meter_box.clone();
+        thread::spawn(move || metrics::serve_metrics(&metrics_address, meter_box));
+    }
+    let meter_box_copy = meter_box.clone();
+    thread::spawn(move || metrics::log_metrics_summary(meter_box_copy));
+
     let (tx, rx) = mpsc::channel();
     let tx_tick = tx.clone();
     thread::spawn(move || {
         loop {
-            thread::sleep(Duration::from_millis(100));
+            thread::sleep(TICK_INTERVAL);
             tx_tick.send(IncomingEvent::Tick).unwrap();
         }
     });
@@ -110,7 +125,8 @@ pub fn run(config: ServerConfig) {
         };
         let mut server_state = ServerState::new(
             clients_copy,
-            hooks
+            hooks,
+            &meter_box,
         );
 
         for event in rx {
@@ -125,7 +141,7 @@ pub fn run(config: ServerConfig) {
     for stream in listener.incoming() {
         match stream {
             Ok(stream) => {
-                match handle_connection(stream, &clients, tx.clone()) {
+                match handle_connection(stream, &clients, tx.clone(), network_write_meter.clone()) {
                     Ok(()) => {},
                     Err(err) => {
                         warn!("{}", err);
@@ -4,21 +4,32 @@
 // TODO: Benchmark SQL write speed.
 // TODO: More structured way to map data between Rust types and SQL;
 //   consider https://crates.io/crates/sea-orm.
-// TODO: insert a consistent row id, not to rely on implementation-specific
-//   columns, such as ROWID in sqlite.
 
-use log::error;
+use std::collections::HashSet;
+
+use itertools::Itertools;
+use log::{error, warn};
 use sqlx::prelude::*;
 use time::OffsetDateTime;
 
 use bughouse_chess::persistence::*;
+use bughouse_chess::rating;
 use bughouse_chess::server::*;
 use bughouse_chess::server_hooks::ServerHooks;
+use bughouse_chess::sqlx_migrations::apply_sqlx_migrations;
 use bughouse_chess::*;
 
+// Performance reports come from the client, so they are capped: meters with longer names and
+// meters beyond the limit are dropped, other fields are truncated (in characters).
+const MAX_PERFORMANCE_METERS: usize = 100;
+const MAX_PERFORMANCE_METER_NAME_LENGTH: usize = 100;
+const MAX_PERFORMANCE_TAG_LENGTH: usize = 200;  // user agent, time zone
+
 pub struct SqlxServerHooks<DB: sqlx::Database> {
     invocation_id: String,
     game_start_time: Option<time::OffsetDateTime>,
+    // Contests already in the `contests` table.
+    recorded_contests: HashSet<String>,
     pool: sqlx::Pool<DB>,
 }
 
@@ -30,10 +41,12 @@ impl SqlxServerHooks<sqlx::Sqlite>
             .create_if_missing(true);
         let pool =
             async_std::task::block_on(sqlx::SqlitePool::connect_with(options))?;
-        Self::create_tables(&pool)?;
+        apply_sqlx_migrations(&pool, DatabaseDialect::Sqlite)?;
+        Self::backfill_ratings(&pool)?;
         Ok(Self {
             invocation_id: uuid::Uuid::new_v4().to_string(),
             game_start_time: None,
+            recorded_contests: HashSet::new(),
             pool,
         })
     }
@@ -44,10 +57,12 @@ impl SqlxServerHooks<sqlx::Postgres>
     pub fn new(address: &str) -> Result<Self, Box<dyn std::error::Error>> {
         let pool =
             async_std::task::block_on(sqlx::Pool::<sqlx::Postgres>::connect(&format!("{address}")))?;
-        Self::create_tables(&pool)?;
+        apply_sqlx_migrations(&pool, DatabaseDialect::Postgres)?;
+        Self::backfill_ratings(&pool)?;
         Ok(Self {
             invocation_id: uuid::Uuid::new_v4().to_string(),
             game_start_time: None,
+            recorded_contests: HashSet::new(),
             pool,
         })
     }
@@ -55,20 +70,30 @@ impl SqlxServerHooks<sqlx::Postgres>
 
 impl<DB: sqlx::Database> ServerHooks for SqlxServerHooks<DB>
 where
-    String: Type<DB> + for<'q>  Encode<'q, DB>,
-    i64: Type<DB> + for<'q> Encode<'q, DB>,
+    String: Type<DB> + for<'q>  Encode<'q, DB> + for<'r> Decode<'r, DB>,
+    i64: Type<DB> + for<'q> Encode<'q, DB> + for<'r> Decode<'r, DB>,
+    f64: Type<DB> + for<'q> Encode<'q, DB> + for<'r> Decode<'r, DB>,
+    bool: Type<DB> + for<'q> Encode<'q, DB> + for<'r> Decode<'r, DB>,
     Option<i64>: Type<DB> + for<'q> Encode<'q, DB>,
-    Option<OffsetDateTime>: Type<DB> + for<'q> Encode<'q, DB>,
+    Option<String>: Type<DB> + for<'q> Encode<'q, DB> + for<'r> Decode<'r, DB>,
+    Option<OffsetDateTime>: Type<DB> + for<'q> Encode<'q, DB> + for<'r> Decode<'r, DB>,
     for<'c> &'c mut DB::Connection: sqlx::Executor<'c, Database = DB>,
     for<'a> <DB as sqlx::database::HasArguments<'a>>::Arguments: sqlx::IntoArguments<'a, DB>,
+    for<'s> &'s str: sqlx::ColumnIndex<DB::Row>,
 {
     fn on_client_event(&mut self, event: &BughouseClientEvent) {
         if let BughouseClientEvent::ReportPerformace(performance) = event {
             self.record_client_performance(&performance)
         }
     }
+    fn on_client_error(&mut self, report: &ClientErrorReportRow) {
+        if let Err(e) = self.insert_client_error(report) {
+            error!("Error persisting client error report: {}", e);
+        }
+    }
     fn on_server_broadcast_event(
         &mut self,
+        contest_id: &str,
         event: &BughouseServerEvent,
         maybe_game: Option<&GameState>,
         round: usize,
@@ -76,71 +101,119 @@ where
         if let BughouseServerEvent::GameStarted { .. } = event {
             self.game_start_time = Some(time::OffsetDateTime::now_utc());
         }
-        self.record_game_finish(event, maybe_game, round);
+        self.record_game_finish(contest_id, event, maybe_game, round);
+    }
+    fn on_contest_updated(&mut self, contest: &ContestSnapshot) {
+        self.record_contest(contest);
+        self.save_contest_snapshot(contest);
+    }
+    fn on_contest_removed(&mut self, contest_id: &str) {
+        self.delete_contest_snapshot(contest_id);
+    }
+    fn on_contest_over(
+        &mut self, contest_id: &str, rules: &BughouseRules, result: &ContestResult, games: &[BughouseGame]
+    ) {
+        self.record_match_finish(contest_id, rules, result, games);
+    }
+    fn load_contests(&mut self) -> Vec<ContestSnapshot> {
+        self.load_contest_snapshots()
     }
 }
 
 impl<DB: sqlx::Database> SqlxServerHooks<DB>
 where
-    String: Type<DB> + for<'q>  Encode<'q, DB>,
-    i64: Type<DB> + for<'q> Encode<'q, DB>,
+    String: Type<DB> + for<'q>  Encode<'q, DB> + for<'r> Decode<'r, DB>,
+    i64: Type<DB> + for<'q> Encode<'q, DB> + for<'r> Decode<'r, DB>,
+    f64: Type<DB> + for<'q> Encode<'q, DB> + for<'r> Decode<'r, DB>,
+    bool: Type<DB> + for<'q> Encode<'q, DB> + for<'r> Decode<'r, DB>,
     Option<i64>: Type<DB> + for<'q> Encode<'q, DB>,
-    Option<OffsetDateTime>: Type<DB> + for<'q> Encode<'q, DB>,
+    Option<String>: Type<DB> + for<'q> Encode<'q, DB> + for<'r> Decode<'r, DB>,
+    Option<OffsetDateTime>: Type<DB> + for<'q> Encode<'q, DB> + for<'r> Decode<'r, DB>,
     for<'c> &'c mut DB::Connection: sqlx::Executor<'c, Database = DB>,
     for<'a> <DB as sqlx::database::HasArguments<'a>>::Arguments: sqlx::IntoArguments<'a, DB>,
+    for<'s> &'s str: sqlx::ColumnIndex<DB::Row>,
 {
-    fn create_tables(pool: &sqlx::Pool<DB>) -> Result<(), Box<dyn std::error::Error>> {
-        // TODO: Include contest_id in finished_games.
-        async_std::task::block_on(
-            sqlx::query(
-                "CREATE TABLE IF NOT EXISTS finished_games (
-                git_version TEXT,
-                invocation_id TEXT,
-                game_start_time TIMESTAMP,
-                game_end_time TIMESTAMP,
-                player_red_a TEXT,
-                player_red_b TEXT,
-                player_blue_a TEXT,
-                player_blue_b TEXT,
-                result TEXT,
-                game_pgn TEXT)",
-            )
-            .execute(pool),
+    // Computes ratings from scratch if there are finished games, but no ratings yet: either
+    // the database predates ratings or the rating tables were dropped in order to recompute.
+    fn backfill_ratings(pool: &sqlx::Pool<DB>) -> Result<(), Box<dyn std::error::Error>> {
+        let existing_rating = async_std::task::block_on(
+            sqlx::query("SELECT player_name FROM player_ratings LIMIT 1").fetch_optional(pool)
         )?;
-        async_std::task::block_on(
+        if existing_rating.is_some() {
+            return Ok(());
+        }
+        let rows = async_std::task::block_on(
             sqlx::query(
-                "CREATE TABLE IF NOT EXISTS client_performance (
-                git_version TEXT,
-                invocation_id TEXT,
-                user_agent TEXT,
-                time_zone TEXT,
-                turn_confirmation_p50 INTEGER,
-                turn_confirmation_p90 INTEGER,
-                turn_confirmation_p99 INTEGER,
-                turn_confirmation_n INTEGER,
-                process_outgoing_events_p99 INTEGER,
-                process_notable_events_p99 INTEGER,
-                refresh_p99 INTEGER,
-                update_state_p50 INTEGER,
-                update_state_p90 INTEGER,
-                update_state_p99 INTEGER,
-                update_state_n INTEGER,
-                update_clock_p99 INTEGER,
-                update_drag_state_p99 INTEGER)",
+                "SELECT
+                game_end_time,
+                player_red_a,
+                player_red_b,
+                player_blue_a,
+                player_blue_b,
+                result
+                FROM finished_games
+                ORDER BY game_end_time",
             )
-            .execute(pool))?;
+            .fetch_all(pool))?;
+        let games = rows.iter().map(|row| -> Result<_, sqlx::Error> {
+            Ok(GameResultRow {
+                git_version: String::new(),
+                invocation_id: String::new(),
+                game_start_time: None,
+                game_end_time: sqlx::Row::try_get(row, "game_end_time")?,
+                player_red_a: sqlx::Row::try_get(row, "player_red_a")?,
+                player_red_b: sqlx::Row::try_get(row, "player_red_b")?,
+                player_blue_a: sqlx::Row::try_get(row, "player_blue_a")?,
+                player_blue_b: sqlx::Row::try_get(row, "player_blue_b")?,
+                result: sqlx::Row::try_get(row, "result")?,
+                game_pgn: String::new(),
+                contest_id: None,
+                game_record: None,
+                variant: None,
+                starting_time_sec: None,
+                drop_aggression: None,
+            })
+        }).filter_map(|game| match game {
+            Ok(game) => Some(game),
+            Err(e) => {
+                warn!("Skipping game in rating computation: {}", e);
+                None
+            }
+        }).collect_vec();
+        let (ratings, history) = rating::compute_ratings(&games);
+        let mut tx = async_std::task::block_on(pool.begin())?;
+        for entry in history {
+            Self::insert_rating_history(&mut tx, entry)?;
+        }
+        for (player_name, player_rating) in ratings.per_player {
+            Self::save_player_rating(&mut tx, player_name, player_rating)?;
+        }
+        async_std::task::block_on(tx.commit())?;
         Ok(())
     }
+
     fn record_game_finish(
         &mut self,
+        contest_id: &str,
         event: &BughouseServerEvent,
         maybe_game: Option<&GameState>,
         round: usize,
     ) {
-        let Some(row) = self.game_result(event, maybe_game, round) else {
+        let Some(row) = self.game_result(contest_id, event, maybe_game, round) else {
             return;
         };
-        let execute_result = async_std::task::block_on(
+        // `game_result` returns `None` if there is no game.
+        let turns = server::finished_game_turns(maybe_game.unwrap().game());
+        if let Err(e) = self.insert_game_result(&row, &turns) {
+            error!("Error persisting game result: {}", e);
+        }
+    }
+
+    // Inserts the game and its turns and updates player ratings in one transaction, so that
+    // ratings are always in sync with `finished_games`. Returns game ID.
+    fn insert_game_result(&mut self, row: &GameResultRow, turns: &[TurnRow]) -> Result<i64, sqlx::Error> {
+        let mut tx = async_std::task::block_on(self.pool.begin())?;
+        let game_row = async_std::task::block_on(
             sqlx::query(
                 "INSERT INTO finished_games (
                 git_version,
@@ -152,80 +225,397 @@ where
                 player_blue_a,
                 player_blue_b,
                 result,
-                game_pgn)
+                game_pgn,
+                contest_id,
+                game_record,
+                variant,
+                starting_time_sec,
+                drop_aggression)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)
+                RETURNING game_id",
+            )
+            .bind(row.git_version.clone())
+            .bind(row.invocation_id.clone())
+            .bind(row.game_start_time)
+            .bind(row.game_end_time)
+            .bind(row.player_red_a.clone())
+            .bind(row.player_red_b.clone())
+            .bind(row.player_blue_a.clone())
+            .bind(row.player_blue_b.clone())
+            .bind(row.result.clone())
+            .bind(row.game_pgn.clone())
+            .bind(row.contest_id.clone())
+            .bind(row.game_record.clone())
+            .bind(row.variant.clone())
+            .bind(row.starting_time_sec)
+            .bind(row.drop_aggression.clone())
+            .fetch_one(&mut *tx),
+        )?;
+        let game_id: i64 = sqlx::Row::try_get(&game_row, "game_id")?;
+        // Optimization potential: Insert all turns in one statement.
+        for turn in turns {
+            async_std::task::block_on(
+                sqlx::query(
+                    "INSERT INTO turns (
+                    game_id,
+                    turn_index,
+                    board,
+                    force,
+                    player_name,
+                    algebraic,
+                    game_time_ms,
+                    capture,
+                    drop_piece,
+                    clock_remaining_ms)
+                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+                )
+                .bind(game_id)
+                .bind(turn.turn_index)
+                .bind(turn.board.clone())
+                .bind(turn.force.clone())
+                .bind(turn.player_name.clone())
+                .bind(turn.algebraic.clone())
+                .bind(turn.game_time_ms)
+                .bind(turn.capture.clone())
+                .bind(turn.drop_piece.clone())
+                .bind(turn.clock_remaining_ms)
+                .execute(&mut *tx),
+            )?;
+        }
+        Self::update_ratings(&mut tx, row)?;
+        async_std::task::block_on(tx.commit())?;
+        Ok(game_id)
+    }
+
+    fn update_ratings(conn: &mut DB::Connection, game: &GameResultRow) -> Result<(), sqlx::Error> {
+        let players = [&game.player_red_a, &game.player_red_b, &game.player_blue_a, &game.player_blue_b];
+        let mut ratings = rating::Ratings::new();
+        for player_name in players {
+            let row = async_std::task::block_on(
+                sqlx::query("SELECT rating, games_played FROM player_ratings WHERE player_name = $1")
+                    .bind(player_name.clone())
+                    .fetch_optional(&mut *conn)
+            )?;
+            if let Some(row) = row {
+                let rating: f64 = sqlx::Row::try_get(&row, "rating")?;
+                let games_played: i64 = sqlx::Row::try_get(&row, "games_played")?;
+                ratings.per_player.insert(player_name.clone(), rating::PlayerRating {
+                    rating,
+                    games_played: games_played as u32,
+                });
+            }
+        }
+        for entry in ratings.apply_game(game) {
+            let player_rating = rating::PlayerRating {
+                rating: entry.rating,
+                games_played: entry.games_played as u32,
+            };
+            Self::save_player_rating(conn, entry.player_name.clone(), player_rating)?;
+            Self::insert_rating_history(conn, entry)?;
+        }
+        Ok(())
+    }
+
+    fn save_player_rating(
+        conn: &mut DB::Connection, player_name: String, player_rating: rating::PlayerRating
+    ) -> Result<(), sqlx::Error> {
+        async_std::task::block_on(
+            sqlx::query(
+                "INSERT INTO player_ratings (player_name, rating, games_played, update_time)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (player_name) DO UPDATE SET
+                rating = excluded.rating,
+                games_played = excluded.games_played,
+                update_time = excluded.update_time",
+            )
+            .bind(player_name)
+            .bind(player_rating.rating)
+            .bind(i64::from(player_rating.games_played))
+            .bind(Some(time::OffsetDateTime::now_utc()))
+            .execute(conn),
+        )?;
+        Ok(())
+    }
+
+    fn insert_rating_history(conn: &mut DB::Connection, entry: RatingHistoryRow) -> Result<(), sqlx::Error> {
+        async_std::task::block_on(
+            sqlx::query(
+                "INSERT INTO rating_history (
+                player_name,
+                game_end_time,
+                rating,
+                rating_change,
+                games_played)
+                VALUES ($1, $2, $3, $4, $5)",
+            )
+            .bind(entry.player_name)
+            .bind(entry.game_end_time)
+            .bind(entry.rating)
+            .bind(entry.rating_change)
+            .bind(entry.games_played)
+            .execute(conn),
+        )?;
+        Ok(())
+    }
+
+    fn record_match_finish(
+        &mut self, contest_id: &str, rules: &BughouseRules, result: &ContestResult, games: &[BughouseGame]
+    ) {
+        let players = games.iter()
+            .flat_map(|game| game.players().into_iter().map(|p| p.name))
+            .sorted()
+            .dedup()
+            .join(", ");
+        let row = MatchResultRow {
+            git_version: my_git_version!().to_owned(),
+            invocation_id: self.invocation_id.clone(),
+            contest_id: contest_id.to_owned(),
+            match_end_time: Some(time::OffsetDateTime::now_utc()),
+            match_length: rules.match_length.to_human_readable(),
+            games_played: result.games_played as i64,
+            players,
+            winners: result.winners.join(", "),
+            scores: result.scores.to_human_readable(),
+            match_pgn: pgn::export_match_to_bpgn(pgn::BughouseExportFormat {}, games),
+        };
+        let execute_result = async_std::task::block_on(
+            sqlx::query(
+                "INSERT INTO finished_matches (
+                git_version,
+                invocation_id,
+                contest_id,
+                match_end_time,
+                match_length,
+                games_played,
+                players,
+                winners,
+                scores,
+                match_pgn)
                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
             )
             .bind(row.git_version)
             .bind(row.invocation_id)
-            .bind(row.game_start_time)
-            .bind(row.game_end_time)
-            .bind(row.player_red_a)
-            .bind(row.player_red_b)
-            .bind(row.player_blue_a)
-            .bind(row.player_blue_b)
-            .bind(row.result)
-            .bind(row.game_pgn)
+            .bind(row.contest_id)
+            .bind(row.match_end_time)
+            .bind(row.match_length)
+            .bind(row.games_played)
+            .bind(row.players)
+            .bind(row.winners)
+            .bind(row.scores)
+            .bind(row.match_pgn)
             .execute(&self.pool),
         );
         if let Err(e) = execute_result {
-            error!("Error persisting game result: {}", e);
+            error!("Error persisting match result: {}", e);
+        }
+    }
+
+    // Records contest rules when the contest is first seen. Contests restored after restart
+    // are already in the table, so the original creation time is kept.
+    fn record_contest(&mut self, contest: &ContestSnapshot) {
+        if self.recorded_contests.contains(&contest.contest_id) {
+            return;
+        }
+        let row = server::contest_row(contest, Some(time::OffsetDateTime::now_utc()));
+        let execute_result = async_std::task::block_on(
+            sqlx::query(
+                "INSERT INTO contests (
+                contest_id,
+                create_time,
+                owner,
+                starting_position,
+                starting_time_sec,
+                teaming,
+                seat_rotation,
+                min_pawn_drop_row,
+                max_pawn_drop_row,
+                drop_aggression,
+                partner_hints,
+                live_chalk,
+                disconnect_policy,
+                disconnect_timeout_sec,
+                match_length)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)
+                ON CONFLICT (contest_id) DO NOTHING",
+            )
+            .bind(row.contest_id)
+            .bind(row.create_time)
+            .bind(row.owner)
+            .bind(row.starting_position)
+            .bind(row.starting_time_sec)
+            .bind(row.teaming)
+            .bind(row.seat_rotation)
+            .bind(row.min_pawn_drop_row)
+            .bind(row.max_pawn_drop_row)
+            .bind(row.drop_aggression)
+            .bind(row.partner_hints)
+            .bind(row.live_chalk)
+            .bind(row.disconnect_policy)
+            .bind(row.disconnect_timeout_sec)
+            .bind(row.match_length)
+            .execute(&self.pool),
+        );
+        match execute_result {
+            Ok(_) => {
+                self.recorded_contests.insert(contest.contest_id.clone());
+            }
+            Err(e) => error!("Error persisting contest: {}", e),
         }
     }
 
+    fn save_contest_snapshot(&mut self, contest: &ContestSnapshot) {
+        let snapshot = match serde_json::to_string(contest) {
+            Ok(s) => s,
+            Err(e) => {
+                error!("Error serializing contest {}: {}", contest.contest_id, e);
+                return;
+            }
+        };
+        let execute_result = async_std::task::block_on(
+            sqlx::query(
+                "INSERT INTO contest_snapshots (contest_id, update_time, snapshot)
+                VALUES ($1, $2, $3)
+                ON CONFLICT (contest_id) DO UPDATE SET
+                update_time = excluded.update_time,
+                snapshot = excluded.snapshot",
+            )
+            .bind(contest.contest_id.clone())
+            .bind(Some(time::OffsetDateTime::now_utc()))
+            .bind(snapshot)
+            .execute(&self.pool),
+        );
+        if let Err(e) = execute_result {
+            error!("Error persisting contest snapshot: {}", e);
+        }
+    }
+
+    fn delete_contest_snapshot(&mut self, contest_id: &str) {
+        let execute_result = async_std::task::block_on(
+            sqlx::query("DELETE FROM contest_snapshots WHERE contest_id = $1")
+                .bind(contest_id.to_owned())
+                .execute(&self.pool),
+        );
+        if let Err(e) = execute_result {
+            error!("Error deleting contest snapshot: {}", e);
+        }
+    }
+
+    fn load_contest_snapshots(&mut self) -> Vec<ContestSnapshot> {
+        let rows = async_std::task::block_on(
+            sqlx::query("SELECT contest_id, snapshot FROM contest_snapshots")
+                .fetch_all(&self.pool),
+        );
+        let rows = match rows {
+            Ok(rows) => rows,
+            Err(e) => {
+                error!("Error loading contest snapshots: {}", e);
+                return Vec::new();
+            }
+        };
+        rows.iter().filter_map(|row| {
+            let contest_id: String = sqlx::Row::try_get(row, "contest_id").ok()?;
+            let snapshot: String = sqlx::Row::try_get(row, "snapshot").ok()?;
+            match serde_json::from_str(&snapshot) {
+                Ok(snapshot) => Some(snapshot),
+                Err(e) => {
+                    // Likely the format has changed since the snapshot was saved.
+                    warn!("Cannot parse snapshot for contest {}: {}", contest_id, e);
+                    None
+                }
+            }
+        }).collect()
+    }
+
     fn record_client_performance(&mut self, perf: &BughouseClientPerformance) {
-        let stats = &perf.stats;
-        let turn_confirmation = stats.get("turn_confirmation");
-        let process_outgoing_events = stats.get("process_outgoing_events");
-        let process_notable_events = stats.get("process_notable_events");
-        let refresh = stats.get("refresh");
-        let update_state = stats.get("update_state");
-        let update_clock = stats.get("update_clock");
-        let update_drag_state = stats.get("update_drag_state");
-        let execute_result = async_std::task::block_on(sqlx::query(
-            "INSERT INTO client_performance (
+        if let Err(e) = self.insert_client_performance(perf) {
+            error!("Error persisting client performance: {}", e);
+        }
+    }
+
+    fn insert_client_performance(&mut self, perf: &BughouseClientPerformance) -> Result<(), sqlx::Error> {
+        let mut tx = async_std::task::block_on(self.pool.begin())?;
+        let report_row = async_std::task::block_on(
+            sqlx::query(
+                "INSERT INTO client_performance_reports (
+                report_time,
                 git_version,
                 invocation_id,
                 user_agent,
-                time_zone,
-                turn_confirmation_p50,
-                turn_confirmation_p90,
-                turn_confirmation_p99,
-                turn_confirmation_n,
-                process_outgoing_events_p99,
-                process_notable_events_p99,
-                refresh_p99,
-                update_state_p50,
-                update_state_p90,
-                update_state_p99,
-                update_state_n,
-                update_clock_p99,
-                update_drag_state_p99)
-                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17)")
-                .bind(my_git_version!().to_owned())
-                .bind(self.invocation_id.clone())
-                .bind(perf.user_agent.clone())
-                .bind(perf.time_zone.clone())
-                .bind(turn_confirmation.map(|s| s.p50 as i64))
-                .bind(turn_confirmation.map(|s| s.p90 as i64))
-                .bind(turn_confirmation.map(|s| s.p99 as i64))
-                .bind(turn_confirmation.map(|s| s.num_values as i64))
-                .bind(process_outgoing_events.map(|s| s.p99 as i64))
-                .bind(process_notable_events.map(|s| s.p99 as i64))
-                .bind(refresh.map(|s| s.p99 as i64))
-                .bind(update_state.map(|s| s.p50 as i64))
-                .bind(update_state.map(|s| s.p90 as i64))
-                .bind(update_state.map(|s| s.p99 as i64))
-                .bind(update_state.map(|s| s.num_values as i64))
-                .bind(update_clock.map(|s| s.p99 as i64))
-                .bind(update_drag_state.map(|s| s.p99 as i64))
-            .execute(&self.pool));
-        if let Err(e) = execute_result {
-            error!("Error persisting client performance: {}", e);
+                time_zone)
+                VALUES ($1, $2, $3, $4, $5)
+                RETURNING report_id",
+            )
+            .bind(Some(time::OffsetDateTime::now_utc()))
+            .bind(my_git_version!().to_owned())
+            .bind(self.invocation_id.clone())
+            .bind(perf.user_agent.chars().take(MAX_PERFORMANCE_TAG_LENGTH).collect::<String>())
+            .bind(perf.time_zone.chars().take(MAX_PERFORMANCE_TAG_LENGTH).collect::<String>())
+            .fetch_one(&mut *tx),
+        )?;
+        let report_id: i64 = sqlx::Row::try_get(&report_row, "report_id")?;
+        let stats = perf.stats.iter()
+            .filter(|(meter, _)| meter.chars().count() <= MAX_PERFORMANCE_METER_NAME_LENGTH)
+            .sorted_by_key(|(meter, _)| *meter)
+            .take(MAX_PERFORMANCE_METERS);
+        for (meter, stats) in stats {
+            async_std::task::block_on(
+                sqlx::query(
+                    "INSERT INTO client_performance_stats (
+                    report_id,
+                    meter,
+                    p50,
+                    p90,
+                    p99,
+                    num_values)
+                    VALUES ($1, $2, $3, $4, $5, $6)",
+                )
+                .bind(report_id)
+                .bind(meter.clone())
+                .bind(stats.p50 as i64)
+                .bind(stats.p90 as i64)
+                .bind(stats.p99 as i64)
+                .bind(stats.num_values as i64)
+                .execute(&mut *tx),
+            )?;
         }
+        async_std::task::block_on(tx.commit())?;
+        Ok(())
+    }
+
+    fn insert_client_error(&mut self, row: &ClientErrorReportRow) -> Result<(), sqlx::Error> {
+        async_std::task::block_on(
+            sqlx::query(
+                "INSERT INTO client_error_reports (
+                report_time,
+                git_version,
+                server_git_version,
+                user_agent,
+                kind,
+                message,
+                backtrace,
+                contest_id,
+                game_number,
+                player_name)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+            )
+            .bind(row.report_time)
+            .bind(row.git_version.clone())
+            .bind(row.server_git_version.clone())
+            .bind(row.user_agent.clone())
+            .bind(row.kind.clone())
+            .bind(row.message.clone())
+            .bind(row.backtrace.clone())
+            .bind(row.contest_id.clone())
+            .bind(row.game_number)
+            .bind(row.player_name.clone())
+            .execute(&self.pool),
+        )?;
+        Ok(())
     }
 
     fn game_result(
         &self,
+        contest_id: &str,
         event: &BughouseServerEvent,
         maybe_game: Option<&GameState>,
         round: usize,
@@ -242,6 +632,15 @@ where
                 return None;
             }
         };
+        let chess_rules = game.game().chess_rules();
+        let record = server::finished_game_record(game.game());
+        let game_record = match serde_json::to_string(&record) {
+            Ok(s) => Some(s),
+            Err(e) => {
+                error!("Error serializing game record: {}", e);
+                None
+            }
+        };
         Some(GameResultRow {
             git_version: my_git_version!().to_owned(),
             invocation_id: self.invocation_id.to_string(),
@@ -253,6 +652,11 @@ where
             player_blue_b: players.3,
             result,
             game_pgn: pgn::export_to_bpgn(pgn::BughouseExportFormat {}, game.game(), round),
+            contest_id: Some(contest_id.to_owned()),
+            game_record,
+            variant: Some(format!("{:?}", chess_rules.starting_position)),
+            starting_time_sec: Some(chess_rules.time_control.starting_time.as_secs() as i64),
+            drop_aggression: Some(format!("{:?}", game.game().bughouse_rules().drop_aggression)),
         })
     }
 }
@@ -290,3 +694,304 @@ fn players(game: &GameState) -> Option<(String, String, String, String)> {
     }
     Some((red_a?, red_b?, blue_a?, blue_b?))
 }
+
+
+#[cfg(test)]
+mod tests {
+    use bughouse_chess::meter::MeterStats;
+
+    use super::*;
+
+    // Postgres tests are ignored by default. To run them, set this variable to a database address,
+    // e.g. "postgres://postgres@localhost:5432/bughouse_test", and run `cargo test -- --ignored`.
+    // All tables in the database are dropped! To get a database quickly:
+    //   docker run -d -p 5432:5432 -e POSTGRES_HOST_AUTH_METHOD=trust postgres
+    //   createdb -h localhost -U postgres bughouse_test
+    const POSTGRES_ENV_VAR: &str = "BUGHOUSE_TEST_POSTGRES_DB";
+
+    fn game(red: [&str; 2], blue: [&str; 2], result: &str) -> GameResultRow {
+        GameResultRow {
+            git_version: "test".to_owned(),
+            invocation_id: "test".to_owned(),
+            game_start_time: Some(OffsetDateTime::now_utc()),
+            game_end_time: Some(OffsetDateTime::now_utc()),
+            player_red_a: red[0].to_owned(),
+            player_red_b: red[1].to_owned(),
+            player_blue_a: blue[0].to_owned(),
+            player_blue_b: blue[1].to_owned(),
+            result: result.to_owned(),
+            game_pgn: "[Event \"test\"]".to_owned(),
+            contest_id: Some("contest".to_owned()),
+            game_record: Some("{}".to_owned()),
+            variant: Some("Classic".to_owned()),
+            starting_time_sec: Some(300),
+            drop_aggression: Some("NoChessMate".to_owned()),
+        }
+    }
+
+    fn turn(turn_index: i64, algebraic: &str) -> TurnRow {
+        TurnRow {
+            turn_index,
+            board: "A".to_owned(),
+            force: if turn_index % 2 == 0 { "White" } else { "Black" }.to_owned(),
+            player_name: "p1".to_owned(),
+            algebraic: algebraic.to_owned(),
+            game_time_ms: turn_index * 1000,
+            capture: None,
+            drop_piece: None,
+            clock_remaining_ms: 300_000,
+        }
+    }
+
+    fn contest(contest_id: &str) -> ContestSnapshot {
+        ContestSnapshot {
+            contest_id: contest_id.to_owned(),
+            chess_rules: ChessRules::classic_blitz(),
+            bughouse_rules: BughouseRules::chess_com(),
+            players: vec![],
+            owner: None,
+            banned_names: vec![],
+            scores: Scores::new(),
+            match_history: vec![],
+            current_game: None,
+            chat: vec![],
+            contest_result: None,
+        }
+    }
+
+    // Runs the same checks against any backend. `reopen` must connect to the same database
+    // again, e.g. to simulate server restart.
+    fn check_hooks<DB: sqlx::Database>(mut reopen: impl FnMut() -> SqlxServerHooks<DB>)
+    where
+        String: Type<DB> + for<'q>  Encode<'q, DB> + for<'r> Decode<'r, DB>,
+        i64: Type<DB> + for<'q> Encode<'q, DB> + for<'r> Decode<'r, DB>,
+        f64: Type<DB> + for<'q> Encode<'q, DB> + for<'r> Decode<'r, DB>,
+        bool: Type<DB> + for<'q> Encode<'q, DB> + for<'r> Decode<'r, DB>,
+        Option<i64>: Type<DB> + for<'q> Encode<'q, DB>,
+        Option<String>: Type<DB> + for<'q> Encode<'q, DB> + for<'r> Decode<'r, DB>,
+        Option<OffsetDateTime>: Type<DB> + for<'q> Encode<'q, DB> + for<'r> Decode<'r, DB>,
+        for<'c> &'c mut DB::Connection: sqlx::Executor<'c, Database = DB>,
+        for<'a> <DB as sqlx::database::HasArguments<'a>>::Arguments: sqlx::IntoArguments<'a, DB>,
+        for<'s> &'s str: sqlx::ColumnIndex<DB::Row>,
+    {
+        let mut hooks = reopen();
+        let turns = vec![turn(0, "e4"), turn(1, "e5")];
+        for (row, turns) in [
+            (game(["p1", "p2"], ["p3", "p4"], "VICTORY_RED"), turns.as_slice()),
+            (game(["p1", "p3"], ["p2", "p4"], "DRAW"), &[]),
+        ] {
+            hooks.insert_game_result(&row, turns).unwrap();
+        }
+        hooks.record_contest(&contest("contest"));
+        hooks.record_contest(&contest("contest"));
+        let meter_stats = |p50, num_values| MeterStats { p50, p90: p50 * 2, p99: p50 * 3, num_values };
+        hooks.on_client_event(&BughouseClientEvent::ReportPerformace(BughouseClientPerformance {
+            user_agent: "Terminal".to_owned(),
+            time_zone: "UTC".to_owned(),
+            stats: [
+                ("update_state".to_owned(), meter_stats(5, 100)),
+                ("custom_meter".to_owned(), meter_stats(7, 1)),
+                ("x".repeat(MAX_PERFORMANCE_METER_NAME_LENGTH + 1), meter_stats(1, 1)),
+            ].into_iter().collect(),
+        }));
+        hooks.on_client_event(&BughouseClientEvent::ReportPerformace(BughouseClientPerformance {
+            user_agent: "Flood".to_owned(),
+            time_zone: "UTC".to_owned(),
+            stats: (0..1000).map(|i| (format!("meter_{i}"), meter_stats(1, 1))).collect(),
+        }));
+        hooks.on_client_error(&ClientErrorReportRow {
+            report_time: Some(OffsetDateTime::now_utc()),
+            git_version: "client".to_owned(),
+            server_git_version: "server".to_owned(),
+            user_agent: "Terminal".to_owned(),
+            kind: "RustPanic".to_owned(),
+            message: "panicked at 'oops'".to_owned(),
+            backtrace: Some("main".to_owned()),
+            contest_id: Some("contest".to_owned()),
+            game_number: Some(1),
+            player_name: None,
+        });
+        hooks.record_match_finish("contest", &BughouseRules::chess_com(), &ContestResult {
+            scores: Scores::new(),
+            games_played: 0,
+            winners: vec![],
+        }, &[]);
+
+        // Tables are not re-created and ratings are not recomputed on restart.
+        let mut hooks = reopen();
+        hooks.record_contest(&contest("contest"));
+        let contests = async_std::task::block_on(
+            sqlx::query("SELECT contest_id, teaming, partner_hints, match_length FROM contests").fetch_all(&hooks.pool)
+        ).unwrap();
+        assert_eq!(contests.len(), 1);
+        assert_eq!(sqlx::Row::try_get::<String, _>(&contests[0], "teaming").unwrap(), "FixedTeams");
+        assert!(!sqlx::Row::try_get::<bool, _>(&contests[0], "partner_hints").unwrap());
+        let turn_rows = async_std::task::block_on(
+            sqlx::query("SELECT game_id, algebraic FROM turns ORDER BY game_id, turn_index").fetch_all(&hooks.pool)
+        ).unwrap();
+        let turn_rows = turn_rows.iter().map(|row| (
+            sqlx::Row::try_get::<i64, _>(row, "game_id").unwrap(),
+            sqlx::Row::try_get::<String, _>(row, "algebraic").unwrap(),
+        )).collect_vec();
+        assert_eq!(turn_rows, vec![(1, "e4".to_owned()), (1, "e5".to_owned())]);
+        let perf_rows = async_std::task::block_on(
+            sqlx::query(
+                "SELECT r.user_agent, s.meter, s.p99, s.num_values
+                 FROM client_performance_stats AS s
+                 JOIN client_performance_reports AS r ON r.report_id = s.report_id
+                 WHERE r.user_agent = 'Terminal'
+                 ORDER BY s.meter"
+            ).fetch_all(&hooks.pool)
+        ).unwrap();
+        let perf_rows = perf_rows.iter().map(|row| (
+            sqlx::Row::try_get::<String, _>(row, "user_agent").unwrap(),
+            sqlx::Row::try_get::<String, _>(row, "meter").unwrap(),
+            sqlx::Row::try_get::<i64, _>(row, "p99").unwrap(),
+            sqlx::Row::try_get::<i64, _>(row, "num_values").unwrap(),
+        )).collect_vec();
+        assert_eq!(perf_rows, vec![
+            ("Terminal".to_owned(), "custom_meter".to_owned(), 21, 1),
+            ("Terminal".to_owned(), "update_state".to_owned(), 15, 100),
+        ]);
+        let flood_rows = async_std::task::block_on(
+            sqlx::query(
+                "SELECT s.meter
+                 FROM client_performance_stats AS s
+                 JOIN client_performance_reports AS r ON r.report_id = s.report_id
+                 WHERE r.user_agent = 'Flood'"
+            ).fetch_all(&hooks.pool)
+        ).unwrap();
+        assert_eq!(flood_rows.len(), MAX_PERFORMANCE_METERS);
+        let error_row = async_std::task::block_on(
+            sqlx::query("SELECT message, game_number, player_name FROM client_error_reports").fetch_one(&hooks.pool)
+        ).unwrap();
+        assert_eq!(sqlx::Row::try_get::<String, _>(&error_row, "message").unwrap(), "panicked at 'oops'");
+        assert_eq!(sqlx::Row::try_get::<Option<i64>, _>(&error_row, "game_number").unwrap(), Some(1));
+        assert_eq!(sqlx::Row::try_get::<Option<String>, _>(&error_row, "player_name").unwrap(), None);
+        let games = async_std::task::block_on(
+            sqlx::query(
+                "SELECT game_id, result, game_end_time, contest_id, game_record FROM finished_games ORDER BY game_id"
+            )
+                .fetch_all(&hooks.pool)
+        ).unwrap();
+        let game_ids = games.iter().map(|row| sqlx::Row::try_get::<i64, _>(row, "game_id").unwrap()).collect_vec();
+        assert_eq!(game_ids, vec![1, 2]);
+        let end_time: Option<OffsetDateTime> = sqlx::Row::try_get(&games[0], "game_end_time").unwrap();
+        assert!(end_time.is_some());
+        let game_record: Option<String> = sqlx::Row::try_get(&games[0], "game_record").unwrap();
+        assert_eq!(game_record.as_deref(), Some("{}"));
+        let contest_id: Option<String> = sqlx::Row::try_get(&games[0], "contest_id").unwrap();
+        assert_eq!(contest_id.as_deref(), Some("contest"));
+        let versions = async_std::task::block_on(
+            sqlx::query("SELECT version FROM schema_migrations ORDER BY version").fetch_all(&hooks.pool)
+        ).unwrap();
+        let versions = versions.iter().map(|row| sqlx::Row::try_get::<i64, _>(row, "version").unwrap()).collect_vec();
+        assert_eq!(versions, migrations(DatabaseDialect::Sqlite).iter().map(|m| m.version).collect_vec());
+        let match_row = async_std::task::block_on(
+            sqlx::query("SELECT match_id FROM finished_matches WHERE contest_id = $1")
+                .bind("contest".to_owned())
+                .fetch_one(&hooks.pool)
+        ).unwrap();
+        let match_id: i64 = sqlx::Row::try_get(&match_row, "match_id").unwrap();
+        assert_eq!(match_id, 1);
+        let ratings = async_std::task::block_on(
+            sqlx::query("SELECT player_name, rating, games_played FROM player_ratings ORDER BY player_name")
+                .fetch_all(&hooks.pool)
+        ).unwrap();
+        assert_eq!(ratings.len(), 4);
+        let p1_rating: f64 = sqlx::Row::try_get(&ratings[0], "rating").unwrap();
+        let p1_games: i64 = sqlx::Row::try_get(&ratings[0], "games_played").unwrap();
+        assert_eq!(p1_games, 2);
+        assert!(p1_rating > rating::INITIAL_RATING);
+        let history = async_std::task::block_on(
+            sqlx::query("SELECT player_name FROM rating_history").fetch_all(&hooks.pool)
+        ).unwrap();
+        assert_eq!(history.len(), 8);
+
+        // A game is not recorded if ratings cannot be updated, and vice versa.
+        async_std::task::block_on(sqlx::query("DROP TABLE rating_history").execute(&hooks.pool)).unwrap();
+        assert!(hooks.insert_game_result(&game(["p1", "p2"], ["p3", "p4"], "DRAW"), &[]).is_err());
+        let num_games = async_std::task::block_on(
+            sqlx::query("SELECT COUNT(*) AS num_games FROM finished_games").fetch_one(&hooks.pool)
+        ).unwrap();
+        assert_eq!(sqlx::Row::try_get::<i64, _>(&num_games, "num_games").unwrap(), 2);
+        let p1_games = async_std::task::block_on(
+            sqlx::query("SELECT games_played FROM player_ratings WHERE player_name = $1")
+                .bind("p1".to_owned())
+                .fetch_one(&hooks.pool)
+        ).unwrap();
+        assert_eq!(sqlx::Row::try_get::<i64, _>(&p1_games, "games_played").unwrap(), 2);
+    }
+
+    // The game server and the webserver apply migrations on start, possibly simultaneously.
+    fn check_concurrent_start<DB: sqlx::Database>(open: impl Fn() -> SqlxServerHooks<DB> + Sync) {
+        std::thread::scope(|scope| {
+            let threads = (0..4).map(|_| scope.spawn(|| { open(); })).collect_vec();
+            for thread in threads {
+                thread.join().unwrap();
+            }
+        });
+    }
+
+    #[test]
+    fn sqlite() {
+        let path = std::env::temp_dir().join(format!("bughouse_test_{}.db", uuid::Uuid::new_v4()));
+        let path = path.to_str().unwrap().to_owned();
+        check_concurrent_start(|| SqlxServerHooks::<sqlx::Sqlite>::new(&path).unwrap());
+        check_hooks(|| SqlxServerHooks::<sqlx::Sqlite>::new(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn sqlite_legacy_tables() {
+        let path = std::env::temp_dir().join(format!("bughouse_test_{}.db", uuid::Uuid::new_v4()));
+        let path = path.to_str().unwrap().to_owned();
+        async_std::task::block_on(async {
+            let options = sqlx::sqlite::SqliteConnectOptions::new().filename(&path).create_if_missing(true);
+            let pool = sqlx::SqlitePool::connect_with(options).await.unwrap();
+            sqlx::query(
+                "CREATE TABLE finished_games (
+                git_version TEXT,
+                invocation_id TEXT,
+                game_start_time TIMESTAMPTZ,
+                game_end_time TIMESTAMPTZ,
+                player_red_a TEXT,
+                player_red_b TEXT,
+                player_blue_a TEXT,
+                player_blue_b TEXT,
+                result TEXT,
+                game_pgn TEXT)"
+            ).execute(&pool).await.unwrap();
+            sqlx::query("INSERT INTO finished_games (result, game_pgn) VALUES ('DRAW', '[Variant \"Bughouse Chess960\"]')")
+                .execute(&pool).await.unwrap();
+            pool.close().await;
+        });
+        let hooks = SqlxServerHooks::<sqlx::Sqlite>::new(&path).unwrap();
+        let row = async_std::task::block_on(
+            sqlx::query("SELECT game_id, game_pgn, contest_id, game_record, variant FROM finished_games")
+                .fetch_one(&hooks.pool)
+        ).unwrap();
+        assert_eq!(sqlx::Row::try_get::<i64, _>(&row, "game_id").unwrap(), 1);
+        assert_eq!(sqlx::Row::try_get::<String, _>(&row, "game_pgn").unwrap(), "[Variant \"Bughouse Chess960\"]");
+        assert_eq!(sqlx::Row::try_get::<Option<String>, _>(&row, "contest_id").unwrap(), None);
+        assert_eq!(sqlx::Row::try_get::<Option<String>, _>(&row, "game_record").unwrap(), None);
+        assert_eq!(sqlx::Row::try_get::<Option<String>, _>(&row, "variant").unwrap().as_deref(), Some("FischerRandom"));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[ignore = "requires Postgres, see POSTGRES_ENV_VAR"]
+    fn postgres() {
+        let address = std::env::var(POSTGRES_ENV_VAR)
+            .unwrap_or_else(|_| panic!("{POSTGRES_ENV_VAR} must be set to run Postgres tests"));
+        let pool = async_std::task::block_on(sqlx::PgPool::connect(&address)).unwrap();
+        async_std::task::block_on(sqlx::query(
+            "DROP TABLE IF EXISTS
+            finished_games, client_performance, finished_matches,
+            player_ratings, rating_history, contest_snapshots, schema_migrations, turns, contests,
+            client_error_reports, client_performance_reports, client_performance_stats"
+        ).execute(&pool)).unwrap();
+        check_concurrent_start(|| SqlxServerHooks::<sqlx::Postgres>::new(&address).unwrap());
+        check_hooks(|| SqlxServerHooks::<sqlx::Postgres>::new(&address).unwrap());
+    }
+}
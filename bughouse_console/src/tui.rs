@@ -36,15 +36,30 @@ fn render_clock(clock: &Clock, force: Force, now: GameInstant) -> (String, usize
     (clock_str, clock_str_len)
 }
 
-fn render_player(player_name: &str) -> (String, usize) {
-    (player_name.to_owned(), player_name.len())
+// Note. Using “ϟ” for “disconnected”. There is a plug emoji “🔌” that works much better,
+// but it's not supported by console fonts.
+pub fn render_player_name(player_name: &str, presence: PlayerPresence) -> String {
+    match presence {
+        PlayerPresence::Online => player_name.to_owned(),
+        PlayerPresence::TemporarilyLost => format!("{} ϟ", player_name),
+        PlayerPresence::Offline => format!("{} ϟϟ", player_name),
+    }
+}
+
+fn render_player(player_name: &str, players: &[Player]) -> (String, usize) {
+    let presence = players.iter().find(|p| p.name == player_name)
+        .map_or(PlayerPresence::Online, |p| p.presence);
+    let player_str = render_player_name(player_name, presence);
+    let player_str_len = player_str.chars().count();
+    (player_str, player_str_len)
 }
 
 fn render_header(
-    clock: &Clock, player_name: &str, force: Force, now: GameInstant, view_board: DisplayBoard
+    clock: &Clock, player_name: &str, players: &[Player], force: Force, now: GameInstant,
+    view_board: DisplayBoard
 ) -> String {
     let (clock_str, clock_str_len) = render_clock(clock, force, now);
-    let (player_str, player_str_len) = render_player(player_name);
+    let (player_str, player_str_len) = render_player(player_name, players);
     let space = String::from(' ').repeat(BOARD_WIDTH - clock_str_len - player_str_len);
     match view_board {
         DisplayBoard::Primary => format!("{}{}{}\n", clock_str, space, player_str),
@@ -67,27 +82,31 @@ fn render_reserve(reserve: &Reserve, force: Force) -> String {
 }
 
 fn render_bughouse_board(
-    board: &Board, now: GameInstant, view_board: DisplayBoard, perspective: Perspective
+    board: &Board, players: &[Player], now: GameInstant, view_board: DisplayBoard,
+    perspective: Perspective
 ) -> String {
     use self::Force::*;
     let orientation = get_board_orientation(view_board, perspective);
     format!(
         "{}\n{}{}{}\n{}",
-        render_header(board.clock(), board.player_name(Black), Black, now, view_board),
+        render_header(board.clock(), board.player_name(Black), players, Black, now, view_board),
         render_reserve(board.reserve(Black), Black),
         render_grid(board.grid(), orientation),
         render_reserve(board.reserve(White), White),
-        render_header(board.clock(), board.player_name(White), White, now, view_board),
+        render_header(board.clock(), board.player_name(White), players, White, now, view_board),
     )
 }
 
-pub fn render_bughouse_game(game: &BughouseGame, my_id: BughouseParticipantId, now: GameInstant) -> String {
+// `players` are used to show connection status. Players that are not found are considered online.
+pub fn render_bughouse_game(
+    game: &BughouseGame, players: &[Player], my_id: BughouseParticipantId, now: GameInstant
+) -> String {
     use DisplayBoard::*;
     let perspective = Perspective::for_force(my_id.visual_force());
     let primary_idx = get_board_index(Primary, my_id);
     let secondary_idx = get_board_index(Secondary, my_id);
-    let board_primary = render_bughouse_board(game.board(primary_idx), now, Primary, perspective);
-    let board_secondary = render_bughouse_board(game.board(secondary_idx), now, Secondary, perspective);
+    let board_primary = render_bughouse_board(game.board(primary_idx), players, now, Primary, perspective);
+    let board_secondary = render_bughouse_board(game.board(secondary_idx), players, now, Secondary, perspective);
     board_primary.lines().zip(board_secondary.lines()).map(
         |(l1, l2)| format!("{}      {}", l1, l2)
     ).join("\n")
@@ -21,6 +21,7 @@ use wasm_bindgen::prelude::*;
 use bughouse_chess::*;
 use bughouse_chess::client::*;
 use bughouse_chess::meter::*;
+use bughouse_chess::persistence::FinishedGameRecord;
 
 
 type JsResult<T> = Result<T, JsValue>;
@@ -31,6 +32,16 @@ const RESERVE_PADDING: f64 = 0.25;  // padding between board and reserve, in squ
 // The client is single-threaded, so wrapping all mutable singletons in `thread_local!` seems ok.
 thread_local! {
     static LAST_PANIC: RefCell<String> = RefCell::new(String::new());
+    // Set when the client is created. Error reports may be generated before that.
+    static USER_AGENT: RefCell<String> = RefCell::new(String::new());
+}
+
+fn error_report_event(report: BughouseClientErrorReport) -> BughouseClientEvent {
+    BughouseClientEvent::ReportErrorWithContext {
+        report,
+        git_version: my_git_version!().to_owned(),
+        user_agent: USER_AGENT.with(|cell| cell.borrow().clone()),
+    }
 }
 
 // Copied from console_error_panic_hook
@@ -58,7 +69,7 @@ pub fn set_panic_hook() {
             // Generate error report to be sent to the server.
             let js_error = Error::new();
             let backtrace = js_error.stack();
-            let event = BughouseClientEvent::ReportError(BughouseClientErrorReport::RustPanic {
+            let event = error_report_event(BughouseClientErrorReport::RustPanic {
                 panic_info: panic_info.to_string(),
                 backtrace,
             });
@@ -89,7 +100,7 @@ macro_rules! rust_error {
 
 #[wasm_bindgen]
 pub fn make_rust_error_event(error: RustError) -> String {
-    let event = BughouseClientEvent::ReportError(BughouseClientErrorReport::RustError{
+    let event = error_report_event(BughouseClientErrorReport::RustError{
         message: error.message
     });
     serde_json::to_string(&event).unwrap()
@@ -97,7 +108,7 @@ pub fn make_rust_error_event(error: RustError) -> String {
 
 #[wasm_bindgen]
 pub fn make_unknown_error_event(message: String) -> String {
-    let event = BughouseClientEvent::ReportError(BughouseClientErrorReport::UnknownError { message });
+    let event = error_report_event(BughouseClientErrorReport::UnknownError { message });
     serde_json::to_string(&event).unwrap()
 }
 
@@ -163,15 +174,19 @@ pub struct WebClient {
     //   `ContestState::Game`. Could move `chalk_canvas` there, for example.
     state: ClientState,
     server_rx: mpsc::Receiver<BughouseClientEvent>,
+    // Finished game shown by the game viewer.
+    game_record: Option<FinishedGameRecord>,
 }
 
 #[wasm_bindgen]
 impl WebClient {
     pub fn new_client(user_agent: String, time_zone: String) -> JsResult<WebClient> {
         let (server_tx, server_rx) = mpsc::channel();
+        USER_AGENT.with(|cell| *cell.borrow_mut() = user_agent.clone());
         Ok(WebClient {
             state: ClientState::new(user_agent, time_zone, server_tx),
             server_rx,
+            game_record: None,
         })
     }
 
@@ -183,16 +198,28 @@ impl WebClient {
         &mut self,
         player_name: &str,
         teaming: &str,
+        seat_rotation: &str,
         starting_position: &str,
         starting_time: &str,
         drop_aggression: &str,
         pawn_drop_rows: &str,
+        partner_hints: &str,
+        live_chalk: &str,
+        disconnect_policy: &str,
+        match_length: &str,
     ) -> JsResult<()> {
         let teaming = match teaming {
             "fixed-teams" => Teaming::FixedTeams,
             "individual-mode" => Teaming::IndividualMode,
             _ => return Err(format!("Invalid teaming: {teaming}").into()),
         };
+        let seat_rotation = match seat_rotation {
+            "random" => SeatRotation::Random,
+            "alternate-boards" => SeatRotation::AlternateBoards,
+            "alternate-colors" => SeatRotation::AlternateColors,
+            "fixed-seats" => SeatRotation::FixedSeats,
+            _ => return Err(format!("Invalid seat rotation: {seat_rotation}").into()),
+        };
         let starting_position = match starting_position {
             "classic" => StartingPosition::Classic,
             "fischer-random" => StartingPosition::FischerRandom,
@@ -206,6 +233,35 @@ impl WebClient {
             _ => return Err(format!("Invalid drop aggression: {drop_aggression}").into()),
         };
 
+        let partner_hints = match partner_hints {
+            "off" => false,
+            "on" => true,
+            _ => return Err(format!("Invalid partner hints: {partner_hints}").into()),
+        };
+        let live_chalk = match live_chalk {
+            "off" => false,
+            "team" => true,
+            _ => return Err(format!("Invalid live chalk: {live_chalk}").into()),
+        };
+        let disconnect_policy = match disconnect_policy {
+            "ignore" => DisconnectPolicy::Ignore,
+            "pause" => DisconnectPolicy::Pause{ max_pause: Duration::from_secs(180) },
+            "forfeit" => DisconnectPolicy::Forfeit{ grace_period: Duration::from_secs(60) },
+            _ => return Err(format!("Invalid disconnect policy: {disconnect_policy}").into()),
+        };
+        let match_length = match match_length.split_once(':') {
+            None if match_length == "unlimited" => MatchLength::Unlimited,
+            Some(("first-to", points)) => match points.parse() {
+                Ok(points) if points > 0 => MatchLength::FirstTo{ points },
+                _ => return Err(format!("Invalid match length: {match_length}").into()),
+            },
+            Some(("best-of", games)) => match games.parse() {
+                Ok(games) if games > 0 => MatchLength::BestOf{ games },
+                _ => return Err(format!("Invalid match length: {match_length}").into()),
+            },
+            _ => return Err(format!("Invalid match length: {match_length}").into()),
+        };
+
         let Some((Ok(starting_minutes), Ok(starting_seconds))) = starting_time
             .split(':')
             .map(|v| v.parse::<u64>())
@@ -234,9 +290,14 @@ impl WebClient {
         };
         let bughouse_rules = BughouseRules {
             teaming,
+            seat_rotation,
             min_pawn_drop_row: SubjectiveRow::from_one_based(min_pawn_drop_row),
             max_pawn_drop_row: SubjectiveRow::from_one_based(max_pawn_drop_row),
             drop_aggression,
+            partner_hints,
+            live_chalk,
+            disconnect_policy,
+            match_length,
         };
         self.state.new_contest(chess_rules, bughouse_rules, player_name.to_owned());
         Ok(())
@@ -245,6 +306,9 @@ impl WebClient {
     pub fn join(&mut self, contest_id: String, my_name: String) {
         self.state.join(contest_id, my_name);
     }
+    pub fn spectate(&mut self, contest_id: String, my_name: String) {
+        self.state.spectate(contest_id, my_name);
+    }
     pub fn set_team(&mut self, team: &str) -> JsResult<()> {
         let team = match team {
             "red" => Team::Red,
@@ -269,6 +333,33 @@ impl WebClient {
     pub fn leave(&mut self) {
         self.state.leave();
     }
+    // Accepts messages like "need N", "dontgive Q", "sit", "go".
+    pub fn send_partner_message(&mut self, command: &str, piece: Option<String>) -> JsResult<()> {
+        let Some(message) = PartnerMessage::parse(command, piece.as_deref()) else {
+            return Err(rust_error!("Invalid partner message: {command} {piece:?}"));
+        };
+        self.state.send_partner_message(message);
+        Ok(())
+    }
+    pub fn kick_player(&mut self, player_name: String, ban: bool) {
+        self.state.kick_player(player_name, ban);
+    }
+    pub fn transfer_ownership(&mut self, player_name: String) {
+        self.state.transfer_ownership(player_name);
+    }
+    pub fn vote_kick(&mut self, player_name: String) {
+        self.state.vote_kick(player_name);
+    }
+    pub fn send_chat_message(&mut self, channel: &str, text: String) -> JsResult<()> {
+        let channel = match channel {
+            "all" => ChatChannel::All,
+            "team" => ChatChannel::Team,
+            _ => return Err(rust_error!("Invalid chat channel: {channel}")),
+        };
+        self.state.send_chat_message(channel, text);
+        Ok(())
+    }
+
     pub fn request_export(&mut self) -> JsResult<()> {
         let format = pgn::BughouseExportFormat{};
         self.state.request_export(format);
@@ -377,8 +468,7 @@ impl WebClient {
         self.state.chalk_canvas().map_or(false, |c| c.is_painting())
     }
     pub fn chalk_down(&mut self, board_node: &str, x: f64, y: f64, alternative_mode: bool) -> JsResult<()> {
-        let Some(GameState{ alt_game, .. }) = self.state.game_state() else { return Ok(()); };
-        if alt_game.status() == BughouseGameStatus::Active {
+        if !self.state.can_draw_chalk() {
             return Ok(());
         }
         let Some(canvas) = self.state.chalk_canvas_mut() else { return Ok(()); };
@@ -449,6 +539,53 @@ impl WebClient {
         Ok(())
     }
 
+    // Game viewer: loads a JSON-serialized `FinishedGameRecord` and shows the game start.
+    pub fn load_game_record(&mut self, record: &str) -> JsResult<()> {
+        let record = serde_json::from_str(record).map_err(|err| {
+            rust_error!("Cannot parse game record: {err}")
+        })?;
+        self.game_record = Some(record);
+        self.show_game_record_at(0.0)?;
+        let Some(GameState{ ref alt_game, .. }) = self.state.game_state() else {
+            return Err(rust_error!("No game in progress"));
+        };
+        render_grids(alt_game.perspective())?;
+        setup_participation_mode(true)?;
+        Ok(())
+    }
+
+    // Game viewer: times of all turns, in milliseconds since game start.
+    pub fn game_record_turn_times(&self) -> Vec<f64> {
+        let Some(record) = &self.game_record else {
+            return vec![];
+        };
+        record.game.turn_log.iter()
+            .map(|t| t.time.elapsed_since_start().as_secs_f64() * 1000.0)
+            .collect()
+    }
+
+    // Game viewer: game duration in milliseconds.
+    pub fn game_record_duration(&self) -> f64 {
+        let Some(record) = &self.game_record else {
+            return 0.0;
+        };
+        record.game.time.elapsed_since_start().as_secs_f64() * 1000.0
+    }
+
+    // Game viewer: shows the game as it was `time_ms` milliseconds after game start.
+    // Call `update_state` afterwards.
+    pub fn show_game_record_at(&mut self, time_ms: f64) -> JsResult<()> {
+        let Some(record) = &self.game_record else {
+            return Err(rust_error!("No game record loaded"));
+        };
+        let time = GameInstant::from_duration(Duration::from_secs_f64(time_ms.max(0.0) / 1000.0));
+        self.state.show_game_record(record, time).map_err(|err| rust_error!("{:?}", err))?;
+        // Game result is shown by `update_state`, but only if the game is over.
+        let info_string = web_document().get_existing_element_by_id("info-string")?;
+        info_string.set_text_content(None);
+        Ok(())
+    }
+
     pub fn process_server_event(&mut self, event: &str) -> JsResult<()> {
         let server_event = serde_json::from_str(event).unwrap();
         self.state.process_server_event(server_event).map_err(|err| {
@@ -464,13 +601,27 @@ impl WebClient {
                     return Err(rust_error!("No game in progress"));
                 };
                 let info_string = web_document().get_existing_element_by_id("info-string")?;
-                info_string.set_text_content(None);
+                if self.state.is_game_paused() {
+                    info_string.set_text_content(Some("Game paused: waiting for all players to rejoin"));
+                } else {
+                    info_string.set_text_content(None);
+                }
                 let my_id = alt_game.my_id();
                 let is_observer = matches!(my_id, BughouseParticipantId::Observer(_));
                 render_grids(alt_game.perspective())?;
                 setup_participation_mode(is_observer)?;
                 Ok(JsEventMyNoop{}.into())
             },
+            Some(NotableEvent::GamePaused) => {
+                let info_string = web_document().get_existing_element_by_id("info-string")?;
+                info_string.set_text_content(Some("Game paused: waiting for all players to rejoin"));
+                Ok(JsEventMyNoop{}.into())
+            },
+            Some(NotableEvent::GameResumed) => {
+                let info_string = web_document().get_existing_element_by_id("info-string")?;
+                info_string.set_text_content(None);
+                Ok(JsEventMyNoop{}.into())
+            },
             Some(NotableEvent::GameOver(game_status)) => {
                 match game_status {
                     SubjectiveGameResult::Victory => Ok(JsEventVictory{}.into()),
@@ -478,11 +629,44 @@ impl WebClient {
                     SubjectiveGameResult::Draw => Ok(JsEventDraw{}.into()),
                 }
             },
+            Some(NotableEvent::ContestOver) => {
+                let Some(contest) = self.state.contest() else {
+                    return Err(rust_error!("No contest in progress"));
+                };
+                let Some(ref result) = contest.result else {
+                    return Err(rust_error!("Contest is not over"));
+                };
+                let outcome = if result.winners.is_empty() {
+                    "draw".to_owned()
+                } else {
+                    format!("{} won", result.winners.join(" & "))
+                };
+                let info_string = web_document().get_existing_element_by_id("info-string")?;
+                info_string.set_text_content(Some(&format!(
+                    "Contest over after {} games: {}\n{}",
+                    result.games_played, outcome, result.scores.to_human_readable()
+                )));
+                Ok(JsEventMyNoop{}.into())
+            },
             Some(NotableEvent::MyTurnMade) => Ok(JsEventTurnMade{}.into()),
             Some(NotableEvent::OpponentTurnMade) => Ok(JsEventTurnMade{}.into()),
             Some(NotableEvent::MyReserveRestocked) => Ok(JsEventMyReserveRestocked{}.into()),
             Some(NotableEvent::LowTime) => Ok(JsEventLowTime{}.into()),
             Some(NotableEvent::GameExportReady(content)) => Ok(JsEventGameExportReady{ content }.into()),
+            // Rendered by `update_state`.
+            Some(NotableEvent::PartnerNeedsPiece(..)) => Ok(JsEventMyNoop{}.into()),
+            // Rendered by `update_state`.
+            Some(NotableEvent::PartnerMessageReceived(..)) => Ok(JsEventMyNoop{}.into()),
+            Some(NotableEvent::Kicked{ banned }) => {
+                let info_string = web_document().get_existing_element_by_id("info-string")?;
+                let message = if banned {
+                    "You have been banned from the contest"
+                } else {
+                    "You have been kicked from the contest"
+                };
+                info_string.set_text_content(Some(message));
+                Ok(JsEventMyNoop{}.into())
+            },
             None => Ok(JsValue::NULL),
         }
     }
@@ -512,7 +696,9 @@ impl WebClient {
             return Ok(());
         };
         update_scores(&contest.scores, contest.bughouse_rules.teaming, contest.my_team)?;
-        let Some(GameState{ ref alt_game, .. }) = contest.game_state else {
+        update_spectator_count(&contest.spectators)?;
+        update_chat(&contest.chat)?;
+        let Some(GameState{ ref alt_game, ref partner_messages, .. }) = contest.game_state else {
             update_lobby(&contest)?;
             return Ok(());
         };
@@ -569,14 +755,28 @@ impl WebClient {
                 )?;
                 let player_name = board.player_name(force);
                 let player = contest.players.iter().find(|p| p.name == *player_name).unwrap();
-                let player_string = if game.status() == BughouseGameStatus::Active {
+                let player_string = if game.status() == BughouseGameStatus::Active || contest.is_replay {
                     player_string(&player)
                 } else {
-                    player_string_with_readiness(&player)
+                    player_string_with_readiness(&player, &contest.owner)
                 };
                 name_node.set_text_content(Some(&player_string));
+                let latest_message = partner_messages.iter().rev()
+                    .find(|r| r.player_id == BughousePlayerId{ board_idx, force });
+                if let Some(record) = latest_message {
+                    if game.status() == BughouseGameStatus::Active {
+                        render_partner_message_badge(&name_node, record)?;
+                    }
+                }
                 let is_draggable = is_piece_draggable(force);
                 update_reserve(board.reserve(force), force, display_board_idx, player_idx, is_draggable)?;
+                if let BughouseParticipantId::Player(my_player_id) = my_id {
+                    if BughousePlayerId{ board_idx, force } == my_player_id.partner() {
+                        if let Some(piece_kind) = self.state.partner_hint() {
+                            render_partner_hint(piece_kind, force, display_board_idx, player_idx)?;
+                        }
+                    }
+                }
             }
             let latest_turn = game.turn_log().iter().rev()
                 .find(|record| record.player_id.board_idx == board_idx);
@@ -790,7 +990,7 @@ fn update_lobby(contest: &Contest) -> JsResult<()> {
             let mut teamless = vec![];
             let mut teams: EnumMap<Team, Vec<String>> = enum_map!{ _ => vec![] };
             for p in &contest.players {
-                let s = format!("{}\n", player_string_with_readiness(p));
+                let s = format!("{}\n", player_string_with_readiness(p, &contest.owner));
                 if let Some(fixed_team) = p.fixed_team {
                     teams[fixed_team].push(s);
                 } else {
@@ -808,12 +1008,75 @@ fn update_lobby(contest: &Contest) -> JsResult<()> {
         Teaming::IndividualMode => {
             contest.players.iter().map(|p| {
                 assert!(p.fixed_team.is_none());
-                player_string_with_readiness(p)
+                player_string_with_readiness(p, &contest.owner)
             }).join("\n")
         },
     };
+    let seating_info = match &contest.upcoming_seating {
+        None => String::new(),
+        Some(seating) => format!("\nseating:\n{}", seating_string(seating)),
+    };
+    let waiting_info = if contest.next_up.len() > TOTAL_PLAYERS {
+        format!("\nwaiting:\n{}", contest.next_up[TOTAL_PLAYERS..].join("\n"))
+    } else {
+        String::new()
+    };
+    let spectator_info = if contest.spectators.is_empty() {
+        String::new()
+    } else {
+        format!("\nspectators:\n{}", contest.spectators.join("\n"))
+    };
     let contest_id = &contest.contest_id;
-    info_string.set_text_content(Some(&format!("Contest {contest_id}\n{player_info}")));
+    info_string.set_text_content(Some(&format!("Contest {contest_id}\n{player_info}{seating_info}{waiting_info}{spectator_info}")));
+    Ok(())
+}
+
+// Optimization potential. Append new messages instead of re-creating the entire log.
+fn update_chat(chat: &[ChatMessage]) -> JsResult<()> {
+    let document = web_document();
+    let chat_log = document.get_existing_element_by_id("chat-log")?;
+    if chat_log.child_element_count() as usize == chat.len() {
+        return Ok(());
+    }
+    chat_log.set_text_content(None);
+    for message in chat {
+        let node = document.create_element("div")?;
+        let class = match message.channel {
+            ChatChannel::All => "chat-message",
+            ChatChannel::Team => "chat-message chat-message-team",
+        };
+        node.set_attribute("class", class)?;
+        node.set_text_content(Some(&format!("{}: {}", message.sender, message.text)));
+        chat_log.append_child(&node)?;
+    }
+    chat_log.set_scroll_top(chat_log.scroll_height());
+    Ok(())
+}
+
+// E.g. "A: p1 (white) vs p3 (black)".
+fn seating_string(seating: &[PlayerInGame]) -> String {
+    BughouseBoard::iter().map(|board_idx| {
+        let player = |force| {
+            seating.iter()
+                .find(|p| p.id == BughousePlayerId{ board_idx, force })
+                .map_or("?", |p| p.name.as_str())
+        };
+        format!(
+            "{:?}: {} (white) vs {} (black)",
+            board_idx, player(Force::White), player(Force::Black)
+        )
+    }).join("\n")
+}
+
+fn update_spectator_count(spectators: &[String]) -> JsResult<()> {
+    let node = web_document().get_existing_element_by_id("spectator-count")?;
+    let text = if spectators.is_empty() {
+        String::new()
+    } else {
+        format!("👁 {}", spectators.len())
+    };
+    node.set_text_content(Some(&text));
+    node.set_attribute("title", &spectators.join("\n"))?;
     Ok(())
 }
 
@@ -850,20 +1113,26 @@ fn reset_square_highlight(id: &str) -> JsResult<()> {
     Ok(())
 }
 
-// Improvement potential: Find a better icon for connection problems.
 // Improvement potential: Add a tooltip explaining the meaning of the icon.
+fn presence_icon(presence: PlayerPresence) -> Option<&'static str> {
+    match presence {
+        PlayerPresence::Online => None,
+        PlayerPresence::TemporarilyLost => Some("📶 "),
+        PlayerPresence::Offline => Some("🔌 "),
+    }
+}
+
 fn player_string(p: &Player) -> String {
-    let icon = if p.is_online { "" } else { "⚠️ " };
+    let icon = presence_icon(p.presence).unwrap_or("");
     format!("{}{}", icon, p.name)
 }
 
-fn player_string_with_readiness(p: &Player) -> String {
-    let icon = if p.is_online {
+fn player_string_with_readiness(p: &Player, owner: &Option<String>) -> String {
+    let icon = presence_icon(p.presence).unwrap_or(
         if p.is_ready { "☑ " } else { "☐ " }
-    } else {
-        "⚠️ "
-    };
-    format!("{}{}", icon, p.name)
+    );
+    let owner_mark = if owner.as_ref() == Some(&p.name) { " 👑" } else { "" };
+    format!("{}{}{}", icon, p.name, owner_mark)
 }
 
 // Renders reserve.
@@ -931,6 +1200,36 @@ fn update_reserve(
     render_reserve(force, board_idx, player_idx, is_draggable, piece_kind_sep, reserve_iter)
 }
 
+fn render_partner_message_badge(name_node: &web_sys::Element, record: &PartnerMessageRecord) -> JsResult<()> {
+    let text = match record.message {
+        PartnerMessage::Need(kind) => format!("need {}", piece_to_pictogram(kind, record.player_id.force)),
+        PartnerMessage::DontGive(kind) => format!("don't give {}", piece_to_pictogram(kind, record.player_id.force.opponent())),
+        PartnerMessage::Sit => "sit".to_owned(),
+        PartnerMessage::Go => "go".to_owned(),
+    };
+    let badge = web_document().create_element("span")?;
+    badge.set_attribute("class", "partner-message-badge")?;
+    badge.set_text_content(Some(&text));
+    name_node.append_child(&badge)?;
+    Ok(())
+}
+
+// Shows a semi-transparent piece next to the partner's reserve: this piece would allow them
+// to deliver a drop mate.
+fn render_partner_hint(
+    piece_kind: PieceKind, force: Force, board_idx: DisplayBoard, player_idx: DisplayPlayer
+) -> JsResult<()> {
+    let document = web_document();
+    let reserve_node = document.get_existing_element_by_id(&reserve_node_id(board_idx, player_idx))?;
+    let node = document.create_svg_element("use")?;
+    node.set_attribute("href", piece_path(piece_kind, force))?;
+    node.set_attribute("x", &(NUM_COLS as f64 - 1.0).to_string())?;
+    node.set_attribute("y", &reserve_y_pos(player_idx).to_string())?;
+    node.set_attribute("class", "partner-hint")?;
+    reserve_node.append_child(&node)?;
+    Ok(())
+}
+
 fn render_starting() -> JsResult<()> {
     use PieceKind::*;
     use Force::*;
@@ -1016,8 +1315,14 @@ fn update_scores(scores: &Scores, teaming: Teaming, my_team: Option<Team>) -> Js
         },
         Teaming::IndividualMode => {
             assert!(scores.per_team.is_empty());
+            // Players may join mid-contest, so show average score per game alongside the total.
             let mut score_vec: Vec<_> = scores.per_player.iter().map(|(player, score)| {
-                format!("{}: {}", player, normalize(*score))
+                match scores.per_player_average(player) {
+                    Some(average) => format!(
+                        "{}: {} ({:.2}/game)", player, normalize(*score), average / 2.0
+                    ),
+                    None => format!("{}: {}", player, normalize(*score)),
+                }
             }).collect();
             score_vec.sort();
             team_node.set_text_content(None);
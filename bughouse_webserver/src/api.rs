@@ -0,0 +1,294 @@
+// Versioned JSON API for dashboards and bots. All endpoints live under `API_PREFIX` and are
+// described by `openapi_description`.
+//
+// Compatibility: within a version fields may be added, but not removed or changed. Breaking
+// changes require a new version prefix.
+
+use serde::Serialize;
+use serde_json::json;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+use bughouse_chess::persistence::{ContestSnapshot, GameResultRow};
+use bughouse_chess::pgn::BpgnTurn;
+use bughouse_chess::BughouseGameStatus;
+
+pub const API_PREFIX: &str = "/dyn/api/v1";
+
+// Values of `GameResultRow::result`.
+pub const GAME_RESULTS: [&str; 3] = ["VICTORY_RED", "VICTORY_BLUE", "DRAW"];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Seats {
+    pub red_a: String,
+    pub red_b: String,
+    pub blue_a: String,
+    pub blue_b: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GameSummary {
+    pub id: i64,
+    pub start_time: Option<String>,  // RFC 3339
+    pub end_time: Option<String>,    // RFC 3339
+    pub players: Seats,
+    pub result: String,  // one of `GAME_RESULTS`
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GameDetails {
+    #[serde(flatten)]
+    pub summary: GameSummary,
+    pub pgn: String,
+    // Empty if the PGN could not be parsed; `turns_error` explains why.
+    pub turns: Vec<BpgnTurn>,
+    pub turns_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlayerStats {
+    pub name: String,
+    pub rating: Option<f64>,  // current rating regardless of the time range
+    pub games: usize,
+    pub wins: usize,
+    pub losses: usize,
+    pub draws: usize,
+    pub points: f64,
+    pub pointrate: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TeamStats {
+    pub players: [String; 2],  // sorted
+    pub games: usize,
+    pub wins: usize,
+    pub losses: usize,
+    pub draws: usize,
+    pub points: f64,
+    pub pointrate: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LiveContest {
+    pub contest_id: String,
+    pub update_time: Option<String>,  // RFC 3339
+    pub teaming: String,
+    pub players: Vec<String>,
+    pub owner: Option<String>,
+    pub games_finished: usize,
+    pub game_in_progress: bool,
+    pub contest_over: bool,
+}
+
+pub fn format_time(t: Option<OffsetDateTime>) -> Option<String> {
+    t.and_then(|t| t.format(&Rfc3339).ok())
+}
+
+pub fn parse_time(s: &str) -> Result<OffsetDateTime, String> {
+    OffsetDateTime::parse(s, &Rfc3339)
+        .map_err(|e| format!("Cannot parse time \"{s}\" (expected RFC 3339): {e}"))
+}
+
+pub fn game_summary(id: i64, game: &GameResultRow) -> GameSummary {
+    GameSummary {
+        id,
+        start_time: format_time(game.game_start_time),
+        end_time: format_time(game.game_end_time),
+        players: Seats {
+            red_a: game.player_red_a.clone(),
+            red_b: game.player_red_b.clone(),
+            blue_a: game.player_blue_a.clone(),
+            blue_b: game.player_blue_b.clone(),
+        },
+        result: game.result.clone(),
+    }
+}
+
+pub fn live_contest(update_time: Option<OffsetDateTime>, snapshot: &ContestSnapshot) -> LiveContest {
+    let game_in_progress = matches!(
+        &snapshot.current_game, Some(g) if g.status == BughouseGameStatus::Active
+    );
+    // The current game is added to the history only when the next one starts.
+    let games_finished = snapshot.match_history.len()
+        + usize::from(snapshot.current_game.is_some() && !game_in_progress);
+    LiveContest {
+        contest_id: snapshot.contest_id.clone(),
+        update_time: format_time(update_time),
+        teaming: format!("{:?}", snapshot.bughouse_rules.teaming),
+        players: snapshot.players.iter().map(|p| p.name.clone()).collect(),
+        owner: snapshot.owner.clone(),
+        games_finished,
+        game_in_progress,
+        contest_over: snapshot.contest_result.is_some(),
+    }
+}
+
+// OpenAPI 3.0 description of the API.
+pub fn openapi_description() -> serde_json::Value {
+    let time_range_params = json!([
+        {
+            "name": "since",
+            "in": "query",
+            "description": "Only include games started at or after this time (RFC 3339).",
+            "schema": { "type": "string", "format": "date-time" }
+        },
+        {
+            "name": "until",
+            "in": "query",
+            "description": "Only include games started before this time (RFC 3339).",
+            "schema": { "type": "string", "format": "date-time" }
+        }
+    ]);
+    let stats_properties = json!({
+        "games": { "type": "integer" },
+        "wins": { "type": "integer" },
+        "losses": { "type": "integer" },
+        "draws": { "type": "integer" },
+        "points": { "type": "number", "description": "Wins plus half of draws." },
+        "pointrate": { "type": "number", "description": "Points per game." }
+    });
+    let mut player_stats_properties = stats_properties.clone();
+    player_stats_properties["name"] = json!({ "type": "string" });
+    player_stats_properties["rating"] = json!({ "type": "number", "nullable": true });
+    let mut team_stats_properties = stats_properties;
+    team_stats_properties["players"] = json!({ "type": "array", "items": { "type": "string" } });
+    let array_of = |schema: &str| json!({
+        "type": "array",
+        "items": { "$ref": format!("#/components/schemas/{schema}") }
+    });
+    let json_response = |description: &str, schema: serde_json::Value| json!({
+        "200": {
+            "description": description,
+            "content": { "application/json": { "schema": schema } }
+        }
+    });
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Bughouse API",
+            "version": "1",
+            "description": "Finished games, player and team statistics, and live contests."
+        },
+        "paths": {
+            format!("{API_PREFIX}/games"): {
+                "get": {
+                    "summary": "Finished games, newest first.",
+                    "parameters": time_range_params.as_array().unwrap().iter().cloned().chain([
+                        json!({
+                            "name": "player",
+                            "in": "query",
+                            "description": "Only include games with this player.",
+                            "schema": { "type": "string" }
+                        }),
+                        json!({
+                            "name": "result",
+                            "in": "query",
+                            "schema": { "type": "string", "enum": GAME_RESULTS }
+                        }),
+                    ]).collect::<Vec<_>>(),
+                    "responses": json_response("Game list.", array_of("GameSummary"))
+                }
+            },
+            format!("{API_PREFIX}/games/{{id}}"): {
+                "get": {
+                    "summary": "A single game with PGN and parsed turns.",
+                    "parameters": [{
+                        "name": "id",
+                        "in": "path",
+                        "required": true,
+                        "schema": { "type": "integer" }
+                    }],
+                    "responses": json_response(
+                        "Game details.",
+                        json!({ "$ref": "#/components/schemas/GameDetails" })
+                    )
+                }
+            },
+            format!("{API_PREFIX}/stats/players"): {
+                "get": {
+                    "summary": "Per-player statistics, sorted by pointrate.",
+                    "parameters": time_range_params.clone(),
+                    "responses": json_response("Player statistics.", array_of("PlayerStats"))
+                }
+            },
+            format!("{API_PREFIX}/stats/teams"): {
+                "get": {
+                    "summary": "Per-team statistics, sorted by pointrate.",
+                    "parameters": time_range_params,
+                    "responses": json_response("Team statistics.", array_of("TeamStats"))
+                }
+            },
+            format!("{API_PREFIX}/contests"): {
+                "get": {
+                    "summary": "Contests that are currently live on the game server.",
+                    "responses": json_response("Live contests.", array_of("LiveContest"))
+                }
+            }
+        },
+        "components": {
+            "schemas": {
+                "Seats": {
+                    "type": "object",
+                    "properties": {
+                        "red_a": { "type": "string" },
+                        "red_b": { "type": "string" },
+                        "blue_a": { "type": "string" },
+                        "blue_b": { "type": "string" }
+                    }
+                },
+                "GameSummary": {
+                    "type": "object",
+                    "properties": {
+                        "id": { "type": "integer" },
+                        "start_time": { "type": "string", "format": "date-time", "nullable": true },
+                        "end_time": { "type": "string", "format": "date-time", "nullable": true },
+                        "players": { "$ref": "#/components/schemas/Seats" },
+                        "result": { "type": "string", "enum": GAME_RESULTS }
+                    }
+                },
+                "Turn": {
+                    "type": "object",
+                    "properties": {
+                        "player_id": {
+                            "type": "object",
+                            "properties": {
+                                "board_idx": { "type": "string", "enum": ["A", "B"] },
+                                "force": { "type": "string", "enum": ["White", "Black"] }
+                            }
+                        },
+                        "full_turn_idx": { "type": "integer" },
+                        "algebraic": { "type": "string" }
+                    }
+                },
+                "GameDetails": {
+                    "allOf": [
+                        { "$ref": "#/components/schemas/GameSummary" },
+                        {
+                            "type": "object",
+                            "properties": {
+                                "pgn": { "type": "string" },
+                                "turns": array_of("Turn"),
+                                "turns_error": { "type": "string", "nullable": true }
+                            }
+                        }
+                    ]
+                },
+                "PlayerStats": { "type": "object", "properties": player_stats_properties },
+                "TeamStats": { "type": "object", "properties": team_stats_properties },
+                "LiveContest": {
+                    "type": "object",
+                    "properties": {
+                        "contest_id": { "type": "string" },
+                        "update_time": { "type": "string", "format": "date-time", "nullable": true },
+                        "teaming": { "type": "string", "enum": ["FixedTeams", "IndividualMode"] },
+                        "players": { "type": "array", "items": { "type": "string" } },
+                        "owner": { "type": "string", "nullable": true },
+                        "games_finished": { "type": "integer" },
+                        "game_in_progress": { "type": "boolean" },
+                        "contest_over": { "type": "boolean" }
+                    }
+                }
+            }
+        }
+    })
+}
@@ -0,0 +1,379 @@
+// Filtering, sorting and pagination for `SqlxApp::finished_games`. Everything is translated
+// into SQL, so that the database does the work and only one page of games is loaded.
+
+use std::ops::Range;
+
+use time::{Date, OffsetDateTime};
+
+use crate::api::GAME_RESULTS;
+
+pub const DEFAULT_PAGE_SIZE: usize = 100;
+pub const MAX_PAGE_SIZE: usize = 1000;
+
+// Values stored in `finished_games.variant` and `finished_games.drop_aggression`: names of
+// `StartingPosition` and `DropAggression` variants.
+pub const VARIANTS: [&str; 2] = ["Classic", "FischerRandom"];
+pub const DROP_AGGRESSIONS: [&str; 4] = ["NoCheck", "NoChessMate", "NoBughouseMate", "MateAllowed"];
+
+// Query parameter. Parameters are bound in order: `$N` refers to the N-th parameter.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SqlArg {
+    Text(String),
+    Int(i64),
+    Time(OffsetDateTime),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct GameFilter {
+    // Game start time range: `since` inclusive, `until` exclusive.
+    pub since: Option<OffsetDateTime>,
+    pub until: Option<OffsetDateTime>,
+    // Player in any seat, exact match.
+    pub player: Option<String>,
+    // Player in any seat, case-insensitive substring match.
+    pub player_search: Option<String>,
+    // Both players on the same team, in any order.
+    pub team: Option<[String; 2]>,
+    pub result: Option<String>,
+    pub variant: Option<String>,
+    pub starting_time_sec: Option<i64>,
+    pub drop_aggression: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GamesSort {
+    StartTime,
+    RedTeam,
+    BlueTeam,
+    Result,
+    Variant,
+    TimeControl,
+    DropAggression,
+}
+
+#[derive(Clone, Debug)]
+pub struct GamesQuery {
+    pub filter: GameFilter,
+    pub sort: GamesSort,
+    pub descending: bool,
+    // 1-based page number and page size. All games if `None`.
+    pub page: Option<(usize, usize)>,
+}
+
+impl GameFilter {
+    // Returns SQL condition (empty if there are no restrictions) and appends its parameters
+    // to `args`.
+    pub fn where_clause(&self, args: &mut Vec<SqlArg>) -> String {
+        let mut conditions = vec![];
+        let arg = |args: &mut Vec<SqlArg>, value: SqlArg| {
+            args.push(value);
+            format!("${}", args.len())
+        };
+        if let Some(since) = self.since {
+            conditions.push(format!("game_start_time >= {}", arg(args, SqlArg::Time(since))));
+        }
+        if let Some(until) = self.until {
+            conditions.push(format!("game_start_time < {}", arg(args, SqlArg::Time(until))));
+        }
+        if let Some(player) = &self.player {
+            let p = arg(args, SqlArg::Text(player.clone()));
+            conditions.push(format!(
+                "(player_red_a = {p} OR player_red_b = {p} OR player_blue_a = {p} OR player_blue_b = {p})"
+            ));
+        }
+        if let Some(search) = &self.player_search {
+            // LIKE is case-insensitive in SQLite, but not in Postgres, hence LOWER.
+            let pattern = format!("%{}%", escape_like(&search.to_lowercase()));
+            let p = arg(args, SqlArg::Text(pattern));
+            conditions.push(format!(
+                "(LOWER(player_red_a) LIKE {p} ESCAPE '\\' OR LOWER(player_red_b) LIKE {p} ESCAPE '\\'
+                  OR LOWER(player_blue_a) LIKE {p} ESCAPE '\\' OR LOWER(player_blue_b) LIKE {p} ESCAPE '\\')"
+            ));
+        }
+        if let Some([a, b]) = &self.team {
+            let a = arg(args, SqlArg::Text(a.clone()));
+            let b = arg(args, SqlArg::Text(b.clone()));
+            conditions.push(format!(
+                "((player_red_a = {a} AND player_red_b = {b}) OR (player_red_a = {b} AND player_red_b = {a})
+                  OR (player_blue_a = {a} AND player_blue_b = {b}) OR (player_blue_a = {b} AND player_blue_b = {a}))"
+            ));
+        }
+        for (column, value) in [
+            ("result", &self.result),
+            ("variant", &self.variant),
+            ("drop_aggression", &self.drop_aggression),
+        ] {
+            if let Some(value) = value {
+                conditions.push(format!("{column} = {}", arg(args, SqlArg::Text(value.clone()))));
+            }
+        }
+        if let Some(starting_time_sec) = self.starting_time_sec {
+            conditions.push(format!("starting_time_sec = {}", arg(args, SqlArg::Int(starting_time_sec))));
+        }
+        if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        }
+    }
+}
+
+impl GamesSort {
+    pub fn from_param(s: &str) -> Option<Self> {
+        match s {
+            "time" => Some(GamesSort::StartTime),
+            "red" => Some(GamesSort::RedTeam),
+            "blue" => Some(GamesSort::BlueTeam),
+            "result" => Some(GamesSort::Result),
+            "variant" => Some(GamesSort::Variant),
+            "control" => Some(GamesSort::TimeControl),
+            "drop" => Some(GamesSort::DropAggression),
+            _ => None,
+        }
+    }
+
+    pub fn to_param(self) -> &'static str {
+        match self {
+            GamesSort::StartTime => "time",
+            GamesSort::RedTeam => "red",
+            GamesSort::BlueTeam => "blue",
+            GamesSort::Result => "result",
+            GamesSort::Variant => "variant",
+            GamesSort::TimeControl => "control",
+            GamesSort::DropAggression => "drop",
+        }
+    }
+
+    pub fn title(self) -> &'static str {
+        match self {
+            GamesSort::StartTime => "Date & Time (UTC)",
+            GamesSort::RedTeam => "Red team",
+            GamesSort::BlueTeam => "Blue team",
+            GamesSort::Result => "Result",
+            GamesSort::Variant => "Variant",
+            GamesSort::TimeControl => "Time control",
+            GamesSort::DropAggression => "Drop aggression",
+        }
+    }
+
+    fn columns(self) -> &'static [&'static str] {
+        match self {
+            GamesSort::StartTime => &["game_start_time"],
+            GamesSort::RedTeam => &["player_red_a", "player_red_b"],
+            GamesSort::BlueTeam => &["player_blue_a", "player_blue_b"],
+            GamesSort::Result => &["result"],
+            GamesSort::Variant => &["variant"],
+            GamesSort::TimeControl => &["starting_time_sec"],
+            GamesSort::DropAggression => &["drop_aggression"],
+        }
+    }
+}
+
+impl GamesQuery {
+    // All games started within the range, newest first.
+    pub fn time_range(range: Range<OffsetDateTime>) -> Self {
+        GamesQuery {
+            filter: GameFilter {
+                since: Some(range.start),
+                until: Some(range.end),
+                ..GameFilter::default()
+            },
+            sort: GamesSort::StartTime,
+            descending: true,
+            page: None,
+        }
+    }
+
+    // Everything after "FROM finished_games". Appends query parameters to `args`.
+    pub fn sql_suffix(&self, args: &mut Vec<SqlArg>) -> String {
+        let direction = if self.descending { "DESC" } else { "ASC" };
+        // NULLS LAST makes the order the same in SQLite and Postgres. Game ID is a tie-breaker
+        // that keeps pages stable.
+        let order = self
+            .sort
+            .columns()
+            .iter()
+            .chain(&["game_id"])
+            .map(|column| format!("{column} {direction} NULLS LAST"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut sql = format!("{} ORDER BY {order}", self.filter.where_clause(args));
+        if let Some((page, page_size)) = self.page {
+            let offset = page.saturating_sub(1) * page_size;
+            sql.push_str(&format!(" LIMIT {page_size} OFFSET {offset}"));
+        }
+        sql
+    }
+
+    // Parses "/dyn/games" query parameters:
+    //   - `player`: substring of a player name, case-insensitive;
+    //   - `team`: two comma-separated player names, exact;
+    //   - `result`: one of `GAME_RESULTS`;
+    //   - `since`, `until`: game start date, "YYYY-MM-DD", both inclusive;
+    //   - `variant`: one of `VARIANTS`;
+    //   - `control`: starting time in seconds;
+    //   - `drop`: one of `DROP_AGGRESSIONS`;
+    //   - `sort`: column to sort by, see `GamesSort`; `order`: "asc" or "desc";
+    //   - `page`: 1-based page number; `page_size`: games per page.
+    // Empty parameters are ignored, so that an unfilled search form means "any".
+    pub fn from_params(param: impl Fn(&str) -> Option<String>) -> Result<Self, String> {
+        let param = |key: &str| param(key).filter(|v| !v.trim().is_empty());
+        let one_of = |key: &str, allowed: &[&str]| match param(key) {
+            Some(v) if !allowed.contains(&v.as_str()) => Err(format!("Unknown {key}: {v}")),
+            v => Ok(v),
+        };
+        let date = |key: &str| {
+            param(key)
+                .map(|v| {
+                    Date::parse(v.trim(), &time::macros::format_description!("[year]-[month]-[day]"))
+                        .map(|d| d.midnight().assume_utc())
+                        .map_err(|e| format!("Cannot parse {key} \"{v}\" (expected YYYY-MM-DD): {e}"))
+                })
+                .transpose()
+        };
+        let team = match param("team") {
+            None => None,
+            Some(t) => match t.split(',').map(|p| p.trim().to_owned()).collect::<Vec<_>>()[..] {
+                [ref a, ref b] if !a.is_empty() && !b.is_empty() => Some([a.clone(), b.clone()]),
+                _ => return Err(format!("Invalid team: {t} (expected two comma-separated names)")),
+            },
+        };
+        let filter = GameFilter {
+            since: date("since")?,
+            until: date("until")?.map(|d| d + time::Duration::DAY),
+            player: None,
+            player_search: param("player").map(|p| p.trim().to_owned()),
+            team,
+            result: one_of("result", &GAME_RESULTS)?,
+            variant: one_of("variant", &VARIANTS)?,
+            starting_time_sec: parse_number("control", param("control"))?,
+            drop_aggression: one_of("drop", &DROP_AGGRESSIONS)?,
+        };
+        let sort = match param("sort") {
+            None => GamesSort::StartTime,
+            Some(s) => GamesSort::from_param(&s).ok_or_else(|| format!("Unknown sort column: {s}"))?,
+        };
+        let descending = match param("order").as_deref() {
+            None | Some("desc") => true,
+            Some("asc") => false,
+            Some(o) => return Err(format!("Unknown order: {o}")),
+        };
+        let page = parse_number("page", param("page"))?.unwrap_or(1);
+        let page_size = parse_number("page_size", param("page_size"))?.unwrap_or(DEFAULT_PAGE_SIZE);
+        if page == 0 || page_size == 0 || page_size > MAX_PAGE_SIZE {
+            return Err(format!("Page must be positive and page size must be in [1, {MAX_PAGE_SIZE}]"));
+        }
+        Ok(GamesQuery {
+            filter,
+            sort,
+            descending,
+            page: Some((page, page_size)),
+        })
+    }
+
+    // Inverse of `from_params`, for building links. Only non-default parameters are included.
+    pub fn to_params(&self) -> Vec<(&'static str, String)> {
+        let date_param = |t: OffsetDateTime| t.date().to_string();
+        let f = &self.filter;
+        let mut params = vec![];
+        params.extend(f.player_search.clone().map(|v| ("player", v)));
+        params.extend(f.team.as_ref().map(|[a, b]| ("team", format!("{a},{b}"))));
+        params.extend(f.result.clone().map(|v| ("result", v)));
+        params.extend(f.since.map(|t| ("since", date_param(t))));
+        params.extend(f.until.map(|t| ("until", date_param(t - time::Duration::DAY))));
+        params.extend(f.variant.clone().map(|v| ("variant", v)));
+        params.extend(f.starting_time_sec.map(|v| ("control", v.to_string())));
+        params.extend(f.drop_aggression.clone().map(|v| ("drop", v)));
+        if self.sort != GamesSort::StartTime {
+            params.push(("sort", self.sort.to_param().to_owned()));
+        }
+        if !self.descending {
+            params.push(("order", "asc".to_owned()));
+        }
+        if let Some((page, page_size)) = self.page {
+            if page != 1 {
+                params.push(("page", page.to_string()));
+            }
+            if page_size != DEFAULT_PAGE_SIZE {
+                params.push(("page_size", page_size.to_string()));
+            }
+        }
+        params
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(key: &str, value: Option<String>) -> Result<Option<T>, String> {
+    value.map(|v| v.trim().parse().map_err(|_| format!("Invalid {key}: {v}"))).transpose()
+}
+
+pub fn escape_like(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(query: &str) -> Result<GamesQuery, String> {
+        let pairs = query
+            .split('&')
+            .filter(|kv| !kv.is_empty())
+            .map(|kv| kv.split_once('=').unwrap())
+            .map(|(k, v)| (k.to_owned(), v.to_owned()))
+            .collect::<Vec<_>>();
+        GamesQuery::from_params(|key| pairs.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone()))
+    }
+
+    #[test]
+    fn default_query() {
+        let query = parse("").unwrap();
+        let mut args = vec![];
+        assert_eq!(
+            query.sql_suffix(&mut args),
+            " ORDER BY game_start_time DESC NULLS LAST, game_id DESC NULLS LAST LIMIT 100 OFFSET 0"
+        );
+        assert!(args.is_empty());
+        assert!(query.to_params().is_empty());
+    }
+
+    #[test]
+    fn params_roundtrip() {
+        let s = "player=Al_&team=p1,p2&result=DRAW&since=2023-01-01&until=2023-01-31&variant=Classic\
+            &control=300&drop=NoCheck&sort=red&order=asc&page=3&page_size=20";
+        let query = parse(s).unwrap();
+        let roundtrip = query
+            .to_params()
+            .into_iter()
+            .map(|(k, v)| format!("{k}={v}"))
+            .collect::<Vec<_>>()
+            .join("&");
+        assert_eq!(roundtrip, s);
+
+        let mut args = vec![];
+        let sql = query.sql_suffix(&mut args);
+        assert!(sql.ends_with(
+            "ORDER BY player_red_a ASC NULLS LAST, player_red_b ASC NULLS LAST, game_id ASC NULLS LAST \
+            LIMIT 20 OFFSET 40"
+        ));
+        assert!(sql.contains("variant = $7"));
+        assert_eq!(args[2], SqlArg::Text("%al\\_%".to_owned()));
+        assert_eq!(args[1], SqlArg::Time(time::macros::datetime!(2023-02-01 0:00 UTC)));
+        assert_eq!(args.len(), 9);
+    }
+
+    #[test]
+    fn empty_params_are_ignored() {
+        let query = parse("player=&team=&since=&sort=").unwrap();
+        assert!(query.filter.where_clause(&mut vec![]).is_empty());
+    }
+
+    #[test]
+    fn invalid_params() {
+        assert!(parse("result=WIN").is_err());
+        assert!(parse("team=p1").is_err());
+        assert!(parse("since=yesterday").is_err());
+        assert!(parse("sort=elo").is_err());
+        assert!(parse("page=0").is_err());
+        assert!(parse("page_size=100000").is_err());
+    }
+}
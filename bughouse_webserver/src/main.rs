@@ -1,9 +1,13 @@
-// TODO: streaming support + APIs.
+// TODO: streaming support.
+mod api;
+mod games_query;
+mod performance;
+
 use std::collections::HashMap;
 use std::ops::Range;
 
 use clap::Parser;
-use log::error;
+use log::{error, warn};
 use sqlx::prelude::*;
 use tide::http::Mime;
 use tide::{Request, Response, StatusCode};
@@ -11,6 +15,12 @@ use tide_jsx::*;
 use time::OffsetDateTime;
 
 use bughouse_chess::persistence::*;
+use bughouse_chess::pgn;
+use bughouse_chess::{get_bughouse_force, BughouseBoard, Force, Team};
+use bughouse_chess::rating::{PlayerRating, Ratings, INITIAL_RATING};
+use bughouse_chess::sqlx_migrations::apply_sqlx_migrations;
+use games_query::{GameFilter, GamesQuery, GamesSort, SqlArg};
+use performance::{PerfFilter, PerfRow, PerfSummary};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -41,19 +51,16 @@ async fn main() -> Result<(), anyhow::Error> {
             SqlxApp::<sqlx::Sqlite>::register_handlers(&mut app);
             app.listen(args.bind_address).await?;
         }
-        (_, Some(_db)) => {
-            // TODO: SQL needs to be adjusted. rowid column does not exist in postgresql.
-            return Err(anyhow::Error::msg(
-                "Postgresql reader is not implemented yet.",
-            ));
-            // let mut app = tide::with_state(SqlxApp::<sqlx::Postgres>::new(&db)?);
-            // SqlxApp::<sqlx::Postgres>::register_handlers(&mut app);
-            // app.listen(args.bind_address).await?;
+        (_, Some(db)) => {
+            let mut app = tide::with_state(SqlxApp::<sqlx::Postgres>::new(&db)?);
+            SqlxApp::<sqlx::Postgres>::register_handlers(&mut app);
+            app.listen(args.bind_address).await?;
         }
     }
     Ok(())
 }
 
+// Primary key in `finished_games` or `finished_matches`.
 #[derive(Copy, Clone, Debug)]
 struct RowId {
     id: i64,
@@ -75,15 +82,17 @@ impl SqlxApp<sqlx::Sqlite> {
     pub fn new(db_address: &str) -> Result<Self, anyhow::Error> {
         let options = sqlx::sqlite::SqlitePoolOptions::new();
         let pool = async_std::task::block_on(options.connect(&format!("{db_address}")))?;
+        // The webserver may start before the game server, so it brings the schema up to date too.
+        apply_sqlx_migrations(&pool, DatabaseDialect::Sqlite)?;
         Ok(Self { pool })
     }
 }
 
-#[allow(dead_code)]
 impl SqlxApp<sqlx::Postgres> {
     pub fn new(db_address: &str) -> Result<Self, anyhow::Error> {
         let options = sqlx::postgres::PgPoolOptions::new();
         let pool = async_std::task::block_on(options.connect(&format!("{db_address}")))?;
+        apply_sqlx_migrations(&pool, DatabaseDialect::Postgres)?;
         Ok(Self { pool })
     }
 }
@@ -92,6 +101,7 @@ impl<DB> SqlxApp<DB>
 where
     DB: sqlx::Database,
     for<'q> i64: sqlx::Type<DB> + sqlx::Encode<'q, DB> + sqlx::Decode<'q, DB>,
+    for<'q> f64: sqlx::Type<DB> + sqlx::Encode<'q, DB> + sqlx::Decode<'q, DB>,
     for<'q> String: sqlx::Type<DB> + sqlx::Encode<'q, DB> + sqlx::Decode<'q, DB>,
     for<'q> OffsetDateTime: sqlx::Type<DB> + sqlx::Encode<'q, DB> + sqlx::Decode<'q, DB>,
     for<'c> &'c mut DB::Connection: sqlx::Executor<'c, Database = DB>,
@@ -101,11 +111,12 @@ where
 {
     pub async fn finished_games(
         &self,
-        game_start_time_range: Range<OffsetDateTime>,
+        query: &GamesQuery,
     ) -> Result<Vec<(RowId, GameResultRow)>, anyhow::Error> {
-        let rows = sqlx::query::<DB>(
+        let mut args = vec![];
+        let sql = format!(
             "SELECT
-                rowid,
+                game_id,
                 git_version,
                 invocation_id,
                 game_start_time,
@@ -114,22 +125,22 @@ where
                 player_red_b,
                 player_blue_a,
                 player_blue_b,
-                result
+                result,
+                contest_id,
+                variant,
+                starting_time_sec,
+                drop_aggression
              FROM finished_games
-             WHERE game_start_time >= ?1 AND game_start_time < ?2
-             ORDER BY game_start_time DESC",
-        )
-        .bind(game_start_time_range.start)
-        .bind(game_start_time_range.end)
-        .fetch_all(&self.pool)
-        .await?;
+             {}",
+            query.sql_suffix(&mut args)
+        );
+        let rows = bind_args(sqlx::query::<DB>(&sql), args).fetch_all(&self.pool).await?;
         let (oks, errs): (Vec<_>, _) = rows
             .into_iter()
             .map(|row| -> Result<_, anyhow::Error> {
                 Ok((
                     RowId {
-                        // Turns out rowid is sensitive for sqlx.
-                        id: row.try_get("rowid")?,
+                        id: row.try_get("game_id")?,
                     },
                     GameResultRow {
                         git_version: row.try_get("git_version")?,
@@ -142,6 +153,11 @@ where
                         player_blue_b: row.try_get("player_blue_b")?,
                         result: row.try_get("result")?,
                         game_pgn: String::new(),
+                        contest_id: row.try_get("contest_id")?,
+                        game_record: None,
+                        variant: row.try_get("variant")?,
+                        starting_time_sec: row.try_get("starting_time_sec")?,
+                        drop_aggression: row.try_get("drop_aggression")?,
                     },
                 ))
             })
@@ -163,8 +179,327 @@ where
         }
     }
 
+    pub async fn count_finished_games(&self, filter: &GameFilter) -> Result<i64, anyhow::Error> {
+        let mut args = vec![];
+        let sql = format!("SELECT COUNT(*) FROM finished_games {}", filter.where_clause(&mut args));
+        let row = bind_args(sqlx::query::<DB>(&sql), args).fetch_one(&self.pool).await?;
+        Ok(row.try_get(0)?)
+    }
+
+    pub async fn finished_matches(&self) -> Result<Vec<(RowId, MatchResultRow)>, anyhow::Error> {
+        let rows = sqlx::query::<DB>(
+            "SELECT
+                match_id,
+                git_version,
+                invocation_id,
+                contest_id,
+                match_end_time,
+                match_length,
+                games_played,
+                players,
+                winners,
+                scores
+             FROM finished_matches
+             ORDER BY match_end_time DESC",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        rows.into_iter()
+            .map(|row| -> Result<_, anyhow::Error> {
+                Ok((
+                    RowId {
+                        id: row.try_get("match_id")?,
+                    },
+                    MatchResultRow {
+                        git_version: row.try_get("git_version")?,
+                        invocation_id: row.try_get("invocation_id")?,
+                        contest_id: row.try_get("contest_id")?,
+                        match_end_time: row.try_get("match_end_time")?,
+                        match_length: row.try_get("match_length")?,
+                        games_played: row.try_get("games_played")?,
+                        players: row.try_get("players")?,
+                        winners: row.try_get("winners")?,
+                        scores: row.try_get("scores")?,
+                        match_pgn: String::new(),
+                    },
+                ))
+            })
+            .collect()
+    }
+
+    pub async fn player_ratings(&self) -> Result<Vec<(String, PlayerRating)>, anyhow::Error> {
+        let rows = sqlx::query::<DB>(
+            "SELECT player_name, rating, games_played
+             FROM player_ratings
+             ORDER BY rating DESC",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        rows.into_iter()
+            .map(|row| -> Result<_, anyhow::Error> {
+                let games_played: i64 = row.try_get("games_played")?;
+                Ok((
+                    row.try_get("player_name")?,
+                    PlayerRating {
+                        rating: row.try_get("rating")?,
+                        games_played: games_played.try_into()?,
+                    },
+                ))
+            })
+            .collect()
+    }
+
+    pub async fn rating_history(&self, player_name: &str) -> Result<Vec<RatingHistoryRow>, anyhow::Error> {
+        let rows = sqlx::query::<DB>(
+            "SELECT player_name, game_end_time, rating, rating_change, games_played
+             FROM rating_history
+             WHERE player_name = $1
+             ORDER BY game_end_time DESC",
+        )
+        .bind(player_name.to_owned())
+        .fetch_all(&self.pool)
+        .await?;
+        rows.into_iter()
+            .map(|row| -> Result<_, anyhow::Error> {
+                Ok(RatingHistoryRow {
+                    player_name: row.try_get("player_name")?,
+                    game_end_time: row.try_get("game_end_time")?,
+                    rating: row.try_get("rating")?,
+                    rating_change: row.try_get("rating_change")?,
+                    games_played: row.try_get("games_played")?,
+                })
+            })
+            .collect()
+    }
+
+    // Think time is the difference between consecutive clock readings of the same player on
+    // the same board. The first turn of each player has no reading to compare to.
+    pub async fn turn_stats(&self, range: Range<OffsetDateTime>) -> Result<Vec<TurnStats>, anyhow::Error> {
+        let rows = sqlx::query::<DB>(
+            "SELECT
+                player_name,
+                COUNT(DISTINCT game_id) AS games,
+                COUNT(*) AS turns,
+                COUNT(capture) AS captures,
+                COUNT(drop_piece) AS drops,
+                CAST(AVG(think_ms) AS DOUBLE PRECISION) AS avg_think_ms
+             FROM (
+                SELECT
+                    turns.*,
+                    LAG(clock_remaining_ms) OVER (
+                        PARTITION BY turns.game_id, board, force ORDER BY turn_index
+                    ) - clock_remaining_ms AS think_ms
+                FROM turns
+                JOIN finished_games ON finished_games.game_id = turns.game_id
+                WHERE game_start_time >= $1 AND game_start_time < $2
+             ) AS player_turns
+             GROUP BY player_name
+             ORDER BY turns DESC, player_name",
+        )
+        .bind(range.start)
+        .bind(range.end)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.into_iter()
+            .map(|row| -> Result<_, anyhow::Error> {
+                Ok(TurnStats {
+                    player_name: row.try_get("player_name")?,
+                    games: row.try_get("games")?,
+                    turns: row.try_get("turns")?,
+                    captures: row.try_get("captures")?,
+                    drops: row.try_get("drops")?,
+                    avg_think_ms: row.try_get("avg_think_ms")?,
+                })
+            })
+            .collect()
+    }
+
+    // Contests without any finished games are counted too: they show which rules people
+    // try, not only which ones they end up playing.
+    pub async fn rules_stats(&self, range: Range<OffsetDateTime>) -> Result<Vec<RulesStats>, anyhow::Error> {
+        let rows = sqlx::query::<DB>(
+            "SELECT
+                contests.starting_position,
+                contests.starting_time_sec,
+                contests.teaming,
+                contests.drop_aggression,
+                COUNT(DISTINCT contests.contest_id) AS contests,
+                COUNT(finished_games.game_id) AS games
+             FROM contests
+             LEFT JOIN finished_games ON finished_games.contest_id = contests.contest_id
+             WHERE create_time >= $1 AND create_time < $2
+             GROUP BY
+                contests.starting_position, contests.starting_time_sec,
+                contests.teaming, contests.drop_aggression
+             ORDER BY games DESC, contests DESC",
+        )
+        .bind(range.start)
+        .bind(range.end)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.into_iter()
+            .map(|row| -> Result<_, anyhow::Error> {
+                Ok(RulesStats {
+                    starting_position: row.try_get("starting_position")?,
+                    starting_time_sec: row.try_get("starting_time_sec")?,
+                    teaming: row.try_get("teaming")?,
+                    drop_aggression: row.try_get("drop_aggression")?,
+                    contests: row.try_get("contests")?,
+                    games: row.try_get("games")?,
+                })
+            })
+            .collect()
+    }
+
+    // Stats of each meter from each client report, oldest first. Meters without values are
+    // skipped.
+    //
+    // Optimization potential: Aggregate by day in SQL.
+    pub async fn client_performance(&self, filter: &PerfFilter) -> Result<Vec<PerfRow>, anyhow::Error> {
+        let mut args = vec![];
+        let sql = format!(
+            "SELECT
+                r.report_time,
+                r.git_version,
+                r.user_agent,
+                s.meter,
+                s.p50,
+                s.p90,
+                s.p99,
+                s.num_values
+             FROM client_performance_stats AS s
+             JOIN client_performance_reports AS r ON r.report_id = s.report_id
+             WHERE s.num_values > 0 AND {}
+             ORDER BY r.report_time, s.meter",
+            filter.where_clause(OffsetDateTime::now_utc(), &mut args)
+        );
+        let rows = bind_args(sqlx::query::<DB>(&sql), args).fetch_all(&self.pool).await?;
+        rows.into_iter()
+            .map(|row| -> Result<_, anyhow::Error> {
+                Ok(PerfRow {
+                    report_time: row.try_get("report_time")?,
+                    git_version: row.try_get("git_version")?,
+                    user_agent: row.try_get("user_agent")?,
+                    meter: row.try_get("meter")?,
+                    p50: row.try_get("p50")?,
+                    p90: row.try_get("p90")?,
+                    p99: row.try_get("p99")?,
+                    num_values: row.try_get("num_values")?,
+                })
+            })
+            .collect()
+    }
+
+    // Reports with the same message from the same client version are likely the same bug.
+    pub async fn client_error_groups(&self) -> Result<Vec<ClientErrorGroup>, anyhow::Error> {
+        let rows = sqlx::query::<DB>(
+            "SELECT
+                kind,
+                message,
+                git_version,
+                COUNT(*) AS reports,
+                COUNT(DISTINCT user_agent) AS user_agents,
+                MIN(report_time) AS first_seen,
+                MAX(report_time) AS last_seen
+             FROM client_error_reports
+             GROUP BY kind, message, git_version
+             ORDER BY last_seen DESC",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        rows.into_iter()
+            .map(|row| -> Result<_, anyhow::Error> {
+                Ok(ClientErrorGroup {
+                    kind: row.try_get("kind")?,
+                    message: row.try_get("message")?,
+                    git_version: row.try_get("git_version")?,
+                    reports: row.try_get("reports")?,
+                    user_agents: row.try_get("user_agents")?,
+                    first_seen: row.try_get("first_seen")?,
+                    last_seen: row.try_get("last_seen")?,
+                })
+            })
+            .collect()
+    }
+
+    pub async fn finished_game(&self, rowid: RowId) -> Result<GameResultRow, anyhow::Error> {
+        let row = sqlx::query::<DB>(
+            "SELECT
+                git_version,
+                invocation_id,
+                game_start_time,
+                game_end_time,
+                player_red_a,
+                player_red_b,
+                player_blue_a,
+                player_blue_b,
+                result,
+                game_pgn,
+                contest_id,
+                game_record,
+                variant,
+                starting_time_sec,
+                drop_aggression
+             FROM finished_games
+             WHERE game_id = $1",
+        )
+        .bind(rowid.id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(GameResultRow {
+            git_version: row.try_get("git_version")?,
+            invocation_id: row.try_get("invocation_id")?,
+            game_start_time: row.try_get("game_start_time")?,
+            game_end_time: row.try_get("game_end_time")?,
+            player_red_a: row.try_get("player_red_a")?,
+            player_red_b: row.try_get("player_red_b")?,
+            player_blue_a: row.try_get("player_blue_a")?,
+            player_blue_b: row.try_get("player_blue_b")?,
+            result: row.try_get("result")?,
+            game_pgn: row.try_get("game_pgn")?,
+            contest_id: row.try_get("contest_id")?,
+            game_record: row.try_get("game_record")?,
+            variant: row.try_get("variant")?,
+            starting_time_sec: row.try_get("starting_time_sec")?,
+            drop_aggression: row.try_get("drop_aggression")?,
+        })
+    }
+
+    // Contests that are live on the game server, see `ContestSnapshot`. Snapshots that
+    // cannot be parsed (e.g. written by a different version) are skipped.
+    pub async fn live_contests(
+        &self,
+    ) -> Result<Vec<(Option<OffsetDateTime>, ContestSnapshot)>, anyhow::Error> {
+        let rows = sqlx::query::<DB>(
+            "SELECT contest_id, update_time, snapshot
+             FROM contest_snapshots
+             ORDER BY update_time DESC",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        let mut contests = vec![];
+        for row in rows {
+            let contest_id: String = row.try_get("contest_id")?;
+            let snapshot: String = row.try_get("snapshot")?;
+            match serde_json::from_str(&snapshot) {
+                Ok(snapshot) => contests.push((row.try_get("update_time")?, snapshot)),
+                Err(e) => warn!("Cannot parse snapshot for contest {}: {}", contest_id, e),
+            }
+        }
+        Ok(contests)
+    }
+
+    pub async fn match_pgn(&self, rowid: RowId) -> Result<String, anyhow::Error> {
+        sqlx::query("SELECT match_pgn FROM finished_matches WHERE match_id = $1")
+            .bind(rowid.id)
+            .fetch_one(&self.pool)
+            .await?
+            .try_get("match_pgn")
+            .map_err(anyhow::Error::from)
+    }
+
     pub async fn pgn(&self, rowid: RowId) -> Result<String, anyhow::Error> {
-        sqlx::query("SELECT game_pgn from finished_games WHERE ROWID = ?")
+        sqlx::query("SELECT game_pgn FROM finished_games WHERE game_id = $1")
             .bind(rowid.id)
             .fetch_one(&self.pool)
             .await?
@@ -172,6 +507,16 @@ where
             .map_err(anyhow::Error::from)
     }
 
+    // JSON-serialized `FinishedGameRecord`, if the game has one.
+    pub async fn game_record(&self, rowid: RowId) -> Result<Option<String>, anyhow::Error> {
+        sqlx::query("SELECT game_record FROM finished_games WHERE game_id = $1")
+            .bind(rowid.id)
+            .fetch_one(&self.pool)
+            .await?
+            .try_get("game_record")
+            .map_err(anyhow::Error::from)
+    }
+
     const STYLESHEET: &str = "
 table, th, td {
     border: 1px solid black;
@@ -186,7 +531,23 @@ td, th {
     fn register_handlers(app: &mut tide::Server<Self>) {
         app.at("/dyn/games").get(Self::handle_games);
         app.at("/dyn/pgn/:rowid").get(SqlxApp::hanle_pgn);
+        app.at("/dyn/game/:rowid").get(Self::handle_game_viewer);
+        app.at("/dyn/game-record/:rowid").get(Self::handle_game_record);
+        app.at("/dyn/matches").get(Self::handle_matches);
+        app.at("/dyn/match-pgn/:rowid").get(Self::handle_match_pgn);
+        app.at("/dyn/leaderboard").get(Self::handle_leaderboard);
+        app.at("/dyn/player/:player").get(Self::handle_player);
+        app.at("/dyn/ratings").get(Self::handle_ratings);
+        app.at("/dyn/ratings/:player").get(Self::handle_rating_history);
         app.at("/dyn/stats").get(|r| Self::handle_stats(r, None));
+        app.at("/dyn/errors").get(Self::handle_errors);
+        app.at("/dyn/performance").get(Self::handle_performance);
+        app.at(&format!("{}/openapi.json", api::API_PREFIX)).get(Self::handle_api_openapi);
+        app.at(&format!("{}/games", api::API_PREFIX)).get(Self::handle_api_games);
+        app.at(&format!("{}/games/:id", api::API_PREFIX)).get(Self::handle_api_game);
+        app.at(&format!("{}/stats/players", api::API_PREFIX)).get(Self::handle_api_player_stats);
+        app.at(&format!("{}/stats/teams", api::API_PREFIX)).get(Self::handle_api_team_stats);
+        app.at(&format!("{}/contests", api::API_PREFIX)).get(Self::handle_api_contests);
         app.at("/dyn/stats/:duration")
             .get(Self::handle_stats_with_duration);
 
@@ -202,40 +563,103 @@ td, th {
         }));
     }
 
+    // Game search. See `GamesQuery::from_params` for query parameters.
     async fn handle_games(req: Request<Self>) -> tide::Result {
+        let query = GamesQuery::from_params(|key| query_param(&req, key))
+            .map_err(|e| tide::Error::from_str(StatusCode::BadRequest, e))?;
+        let (page, page_size) = query.page.unwrap();
         let games = req
             .state()
-            .finished_games(OffsetDateTime::UNIX_EPOCH..OffsetDateTime::now_utc())
+            .finished_games(&query)
+            .await
+            .map_err(anyhow::Error::from)?;
+        let total_games = req
+            .state()
+            .count_finished_games(&query.filter)
             .await
             .map_err(anyhow::Error::from)?;
+        let total_pages = (total_games.max(1) as usize).div_ceil(page_size);
+
         let table_body = games
             .iter()
             .map(|(rowid, game)| {
                 let (start_date, start_time) = format_timestamp_date_and_time(game.game_start_time)
                     .unwrap_or(("-".into(), "-".into()));
-                let red_team = format!("{}, {}", game.player_red_a, game.player_red_b);
-                let blue_team = format!("{}, {}", game.player_blue_a, game.player_blue_b);
-                let (winners, losers, drawers) = match game.result.as_str() {
-                    "DRAW" => (
-                        "".to_string(),
-                        "".to_string(),
-                        format!("{}, {}", red_team, blue_team),
-                    ),
-                    "VICTORY_RED" => (red_team, blue_team, "".to_string()),
-                    "VICTORY_BLUE" => (blue_team, red_team, "".to_string()),
-                    _ => ("".to_string(), "".to_string(), "".to_string()),
+                let result = match game.result.as_str() {
+                    "VICTORY_RED" => "Red won",
+                    "VICTORY_BLUE" => "Blue won",
+                    "DRAW" => "Draw",
+                    _ => "",
                 };
+                let time_control = game
+                    .starting_time_sec
+                    .map_or("-".to_owned(), |t| format!("{}:{:02}", t / 60, t % 60));
                 rsx! {<tr>
                     <td>{start_date}</td>
                     <td>{start_time}</td>
-                    <td>{winners}</td>
-                    <td>{losers}</td>
-                    <td>{drawers}</td>
+                    <td>{format!("{}, {}", game.player_red_a, game.player_red_b)}</td>
+                    <td>{format!("{}, {}", game.player_blue_a, game.player_blue_b)}</td>
+                    <td>{result}</td>
+                    <td>{game.variant.clone().unwrap_or("-".to_owned())}</td>
+                    <td>{time_control}</td>
+                    <td>{game.drop_aggression.clone().unwrap_or("-".to_owned())}</td>
                     <td><a href={format!("/dyn/pgn/{}", rowid.id)}>{"pgn💾"}</a></td>
+                    <td><a href={format!("/dyn/game/{}", rowid.id)}>{"view▶"}</a></td>
                 </tr>}
             })
             .collect::<Vec<_>>();
 
+        // Clicking the current sort column reverses the order; other columns start with
+        // newest games or in alphabetical order.
+        let sort_link = |key: GamesSort, colspan: usize| {
+            let mut q = query.clone();
+            q.page = Some((1, page_size));
+            let mut title = key.title().to_owned();
+            if key == query.sort {
+                q.descending = !query.descending;
+                title.push_str(if query.descending { " ▼" } else { " ▲" });
+            } else {
+                q.sort = key;
+                q.descending = key == GamesSort::StartTime;
+            }
+            rsx! { <th colspan={colspan.to_string()}><a href={games_link(&q)}>{title}</a></th> }
+        };
+        let page_link = |p: usize, title: &'static str| {
+            let mut q = query.clone();
+            q.page = Some((p, page_size));
+            (1..=total_pages).contains(&p).then(|| rsx! { <a href={games_link(&q)}>{title}</a> })
+        };
+        let text_input = |name: &'static str, value: Option<String>, placeholder: &'static str| {
+            rsx! { <input type={"text"} name={name} value={value.unwrap_or_default()} placeholder={placeholder} /> }
+        };
+        let date_input = |name: &'static str, value: Option<OffsetDateTime>| {
+            rsx! { <input type={"date"} name={name} value={value.map_or(String::new(), |t| t.date().to_string())} /> }
+        };
+        let select = |name: &'static str, current: &Option<String>, values: &[&'static str]| {
+            let options = std::iter::once("")
+                .chain(values.iter().copied())
+                .map(|v| {
+                    let title = if v.is_empty() { "any" } else { v };
+                    let selected = current.as_deref().unwrap_or("") == v;
+                    (
+                        selected.then(|| rsx! { <option value={v} selected={"selected"}>{title}</option> }),
+                        (!selected).then(|| rsx! { <option value={v}>{title}</option> }),
+                    )
+                })
+                .collect::<Vec<_>>();
+            rsx! { <select name={name}>{options}</select> }
+        };
+        let filter = &query.filter;
+        let control_value = filter.starting_time_sec.map(|t| t.to_string());
+        let team_value = filter.team.as_ref().map(|[a, b]| format!("{a},{b}"));
+        let sort_param = (query.sort != GamesSort::StartTime).then(|| {
+            rsx! { <input type={"hidden"} name={"sort"} value={query.sort.to_param()} /> }
+        });
+        let order_param = (!query.descending).then(|| {
+            rsx! { <input type={"hidden"} name={"order"} value={"asc"} /> }
+        });
+        let until_date = filter.until.map(|t| t - time::Duration::DAY);
+
         let h: String = html! {
             <html>
                 <style>
@@ -244,14 +668,44 @@ td, th {
             <head>
             </head>
             <body>
+              <form method={"get"} action={"/dyn/games"}>
+                <p>
+                  {"Player: "}{text_input("player", filter.player_search.clone(), "name or part of it")}
+                  {" Team: "}{text_input("team", team_value, "player1,player2")}
+                  {" Result: "}{select("result", &filter.result, &api::GAME_RESULTS)}
+                </p>
+                <p>
+                  {"From: "}{date_input("since", filter.since)}
+                  {" To: "}{date_input("until", until_date)}
+                </p>
+                <p>
+                  {"Variant: "}{select("variant", &filter.variant, &games_query::VARIANTS)}
+                  {" Time control (sec): "}{text_input("control", control_value, "e.g. 300")}
+                  {" Drop aggression: "}{select("drop", &filter.drop_aggression, &games_query::DROP_AGGRESSIONS)}
+                </p>
+                {sort_param}
+                {order_param}
+                <input type={"submit"} value={"Search"} />
+                {" "}
+                <a href={"/dyn/games"}>{"Reset"}</a>
+              </form>
+              <p>
+                {format!("{total_games} games found. Page {page} of {total_pages}. ")}
+                {page_link(page.saturating_sub(1), "« Previous")}
+                {" "}
+                {page_link(page + 1, "Next »")}
+              </p>
               <table>
                 <tr>
-                    <th>{"Date"}</th>
-                    <th>{"Time (UTC)"}</th>
-                    <th>{"Winners"}</th>
-                    <th>{"Losers"}</th>
-                    <th>{"Drawers"}</th>
+                    {sort_link(GamesSort::StartTime, 2)}
+                    {sort_link(GamesSort::RedTeam, 1)}
+                    {sort_link(GamesSort::BlueTeam, 1)}
+                    {sort_link(GamesSort::Result, 1)}
+                    {sort_link(GamesSort::Variant, 1)}
+                    {sort_link(GamesSort::TimeControl, 1)}
+                    {sort_link(GamesSort::DropAggression, 1)}
                     <th>{"Pgn"}</th>
+                    <th>{"Replay"}</th>
                 </tr>
                 {table_body}
               </table>
@@ -264,101 +718,118 @@ td, th {
         Ok(resp)
     }
 
-    async fn hanle_pgn(req: Request<Self>) -> tide::Result {
-        let rowid = req.param("rowid")?.parse()?;
-        let p = req.state().pgn(RowId { id: rowid }).await?;
+    // Contest summaries: one row per finished match.
+    async fn handle_matches(req: Request<Self>) -> tide::Result {
+        let matches = req
+            .state()
+            .finished_matches()
+            .await
+            .map_err(anyhow::Error::from)?;
+        let table_body = matches
+            .iter()
+            .map(|(rowid, m)| {
+                let (end_date, end_time) = format_timestamp_date_and_time(m.match_end_time)
+                    .unwrap_or(("-".into(), "-".into()));
+                let winners = if m.winners.is_empty() { "(draw)".to_owned() } else { m.winners.clone() };
+                rsx! {<tr>
+                    <td>{end_date}</td>
+                    <td>{end_time}</td>
+                    <td>{m.contest_id.clone()}</td>
+                    <td>{m.match_length.clone()}</td>
+                    <td>{m.games_played}</td>
+                    <td>{m.players.clone()}</td>
+                    <td>{winners}</td>
+                    <td>{m.scores.clone()}</td>
+                    <td><a href={format!("/dyn/match-pgn/{}", rowid.id)}>{"pgn💾"}</a></td>
+                </tr>}
+            })
+            .collect::<Vec<_>>();
+
+        let h: String = html! {
+            <html>
+                <style>
+                    {Self::STYLESHEET}
+                </style>
+            <head>
+            </head>
+            <body>
+              <table>
+                <tr>
+                    <th>{"Date"}</th>
+                    <th>{"Time (UTC)"}</th>
+                    <th>{"Contest"}</th>
+                    <th>{"Length"}</th>
+                    <th>{"Games"}</th>
+                    <th>{"Players"}</th>
+                    <th>{"Winners"}</th>
+                    <th>{"Scores"}</th>
+                    <th>{"Pgn"}</th>
+                </tr>
+                {table_body}
+              </table>
+            </body>
+            </html>
+        };
         let mut resp = Response::new(StatusCode::Ok);
-        resp.insert_header(
-            "Content-Disposition",
-            format!("attachment; filename=\"game{rowid}.pgn\""),
-        );
-        resp.set_body(p);
+        resp.set_content_type(Mime::from("text/html; charset=UTF-8"));
+        resp.set_body(h);
         Ok(resp)
     }
 
-    async fn handle_stats_with_duration(req: Request<Self>) -> tide::Result {
-        let duration_str = req.param("duration")?;
-        let duration = humantime::parse_duration(duration_str)?;
-        Self::handle_stats(req, Some(duration.try_into()?)).await
-    }
-
-    async fn handle_stats(req: Request<Self>, lookback: Option<time::Duration>) -> tide::Result {
-        let now = OffsetDateTime::now_utc();
-        let range_start = match lookback {
-            None => OffsetDateTime::UNIX_EPOCH,
-            Some(d) => now.saturating_sub(d),
-        };
+    // Player leaderboard by rating and team leaderboard by average rating of the partners.
+    async fn handle_ratings(req: Request<Self>) -> tide::Result {
+        let player_ratings = req
+            .state()
+            .player_ratings()
+            .await
+            .map_err(anyhow::Error::from)?;
         let games = req
             .state()
-            .finished_games(range_start..now)
+            .finished_games(&GamesQuery::time_range(OffsetDateTime::UNIX_EPOCH..OffsetDateTime::now_utc()))
             .await
             .map_err(anyhow::Error::from)?;
-        let mut player_stats = HashMap::<String, RawStats>::new();
-        let mut team_stats = HashMap::<[String; 2], RawStats>::new();
-
+        let ratings = Ratings {
+            per_player: player_ratings.iter().cloned().collect(),
+        };
+        let mut team_games = HashMap::<[String; 2], usize>::new();
         for (_, game) in games.into_iter() {
-            let red_team = sort([game.player_red_a, game.player_red_b]);
-            let blue_team = sort([game.player_blue_a, game.player_blue_b]);
-            match game.result.as_str() {
-                "DRAW" => {
-                    for p in red_team.iter().chain(blue_team.iter()).cloned() {
-                        player_stats.entry(p).or_default().draws += 1;
-                    }
-                    for team in [red_team, blue_team].iter() {
-                        team_stats.entry(team.clone()).or_default().draws += 1;
-                    }
-                }
-                "VICTORY_RED" => {
-                    for p in red_team.iter().cloned() {
-                        player_stats.entry(p).or_default().wins += 1;
-                    }
-                    for p in blue_team.iter().cloned() {
-                        player_stats.entry(p).or_default().losses += 1;
-                    }
-                    team_stats.entry(red_team).or_default().wins += 1;
-                    team_stats.entry(blue_team).or_default().losses += 1;
-                }
-                "VICTORY_BLUE" => {
-                    for p in red_team.iter().cloned() {
-                        player_stats.entry(p).or_default().losses += 1;
-                    }
-                    for p in blue_team.iter().cloned() {
-                        player_stats.entry(p).or_default().wins += 1;
-                    }
-                    team_stats.entry(red_team).or_default().losses += 1;
-                    team_stats.entry(blue_team).or_default().wins += 1;
-                }
-                _ => {}
+            for team in [
+                sort([game.player_red_a, game.player_red_b]),
+                sort([game.player_blue_a, game.player_blue_b]),
+            ] {
+                *team_games.entry(team).or_default() += 1;
             }
         }
+        let mut team_ratings = team_games
+            .into_iter()
+            .map(|([p0, p1], games)| (ratings.team_rating(&p0, &p1), format!("{}, {}", p0, p1), games))
+            .collect::<Vec<_>>();
+        team_ratings.sort_unstable_by(|a, b| b.0.total_cmp(&a.0));
 
-        let mut final_player_stats = process_stats(player_stats.into_iter());
-        let mut final_team_stats = process_stats(
-            team_stats
-                .into_iter()
-                .map(|([t0, t1], s)| (format!("{}, {}", t0, t1), s)),
-        );
-        final_player_stats.sort_unstable_by(|a, b| b.pointrate.total_cmp(&a.pointrate));
-        final_team_stats.sort_unstable_by(|a, b| b.pointrate.total_cmp(&a.pointrate));
-
-        let leaderboard = |final_stats: Vec<FinalStats>| {
-            final_stats
-                .into_iter()
-                .map(|s| {
-                    rsx! {
-                        <tr>
-                            <td>{s.name}</td>
-                            <td>{format!("{:.3}", s.pointrate)}</td>
-                            <td>{s.points}</td>
-                            <td>{s.games}</td>
-                            <td>{s.wins}</td>
-                            <td>{s.losses}</td>
-                            <td>{s.draws}</td>
-                        </tr>
-                    }
-                })
-                .collect::<Vec<_>>()
-        };
+        let player_rows = player_ratings
+            .into_iter()
+            .map(|(name, r)| {
+                rsx! {
+                    <tr>
+                        <td><a href={format!("/dyn/player/{}", name)}>{name.clone()}</a></td>
+                        <td>{format!("{:.0}", r.rating)}</td>
+                        <td>{r.games_played.to_string()}</td>
+                    </tr>
+                }
+            })
+            .collect::<Vec<_>>();
+        let team_rows = team_ratings
+            .into_iter()
+            .map(|(rating, team, games)| {
+                rsx! {
+                    <tr>
+                        <td>{team}</td>
+                        <td>{format!("{:.0}", rating)}</td>
+                        <td>{games}</td>
+                    </tr>
+                }
+            })
+            .collect::<Vec<_>>();
 
         let h: String = html! {
             <html>
@@ -369,31 +840,888 @@ td, th {
             </head>
             <body>
               <table>
-                <p>{"Player Leaderboard"}</p>
+                <p>{"Player Ratings"}</p>
                 <tr>
                     <th>{"Player"}</th>
-                    <th>{"Pointrate"}</th>
-                    <th>{"Points"}</th>
+                    <th>{"Rating"}</th>
                     <th>{"Games"}</th>
-                    <th>{"Wins"}</th>
-                    <th>{"Losses"}</th>
-                    <th>{"Draws"}</th>
                 </tr>
-                {leaderboard(final_player_stats)}
+                {player_rows}
               </table>
               <table>
-                <p>{"Team Leaderboard"}</p>
+                <p>{"Team Ratings"}</p>
                 <tr>
                     <th>{"Team"}</th>
-                    <th>{"Pointrate"}</th>
-                    <th>{"Points"}</th>
-                    <th>{"Games"}</th>
-                    <th>{"Wins"}</th>
+                    <th>{"Rating"}</th>
+                    <th>{"Games together"}</th>
+                </tr>
+                {team_rows}
+              </table>
+            </body>
+            </html>
+        };
+        let mut resp = Response::new(StatusCode::Ok);
+        resp.set_content_type(Mime::from("text/html; charset=UTF-8"));
+        resp.set_body(h);
+        Ok(resp)
+    }
+
+    async fn handle_rating_history(req: Request<Self>) -> tide::Result {
+        let player_name = req.param("player")?.to_owned();
+        let history = req
+            .state()
+            .rating_history(&player_name)
+            .await
+            .map_err(anyhow::Error::from)?;
+        let table_body = history
+            .into_iter()
+            .map(|entry| {
+                let (end_date, end_time) = format_timestamp_date_and_time(entry.game_end_time)
+                    .unwrap_or(("-".into(), "-".into()));
+                rsx! {<tr>
+                    <td>{end_date}</td>
+                    <td>{end_time}</td>
+                    <td>{format!("{:.0}", entry.rating)}</td>
+                    <td>{format!("{:+.1}", entry.rating_change)}</td>
+                    <td>{entry.games_played}</td>
+                </tr>}
+            })
+            .collect::<Vec<_>>();
+
+        let h: String = html! {
+            <html>
+                <style>
+                    {Self::STYLESHEET}
+                </style>
+            <head>
+            </head>
+            <body>
+              <table>
+                <p>{format!("Rating history: {}", player_name)}</p>
+                <tr>
+                    <th>{"Date"}</th>
+                    <th>{"Time (UTC)"}</th>
+                    <th>{"Rating"}</th>
+                    <th>{"Change"}</th>
+                    <th>{"Games"}</th>
+                </tr>
+                {table_body}
+              </table>
+            </body>
+            </html>
+        };
+        let mut resp = Response::new(StatusCode::Ok);
+        resp.set_content_type(Mime::from("text/html; charset=UTF-8"));
+        resp.set_body(h);
+        Ok(resp)
+    }
+
+    async fn handle_match_pgn(req: Request<Self>) -> tide::Result {
+        let rowid = req.param("rowid")?.parse()?;
+        let p = req.state().match_pgn(RowId { id: rowid }).await?;
+        let mut resp = Response::new(StatusCode::Ok);
+        resp.insert_header(
+            "Content-Disposition",
+            format!("attachment; filename=\"match{rowid}.pgn\""),
+        );
+        resp.set_body(p);
+        Ok(resp)
+    }
+
+    async fn hanle_pgn(req: Request<Self>) -> tide::Result {
+        let rowid = req.param("rowid")?.parse()?;
+        let p = req.state().pgn(RowId { id: rowid }).await?;
+        let mut resp = Response::new(StatusCode::Ok);
+        resp.insert_header(
+            "Content-Disposition",
+            format!("attachment; filename=\"game{rowid}.pgn\""),
+        );
+        resp.set_body(p);
+        Ok(resp)
+    }
+
+    // The game viewer is part of the game client (see "www/"), which replays the game using
+    // `handle_game_record`. This assumes that the client and "/dyn/" are served from the same
+    // origin, like they are in production.
+    async fn handle_game_viewer(req: Request<Self>) -> tide::Result {
+        let rowid: i64 = req.param("rowid")?.parse()?;
+        Ok(tide::Redirect::new(format!("/?replay={rowid}")).into())
+    }
+
+    async fn handle_game_record(req: Request<Self>) -> tide::Result {
+        let rowid = req.param("rowid")?.parse()?;
+        let Some(record) = req.state().game_record(RowId { id: rowid }).await? else {
+            return Err(tide::Error::from_str(
+                StatusCode::NotFound,
+                format!("Game {rowid} was recorded without replay data; only PGN is available"),
+            ));
+        };
+        let mut resp = Response::new(StatusCode::Ok);
+        resp.set_body(record);
+        resp.set_content_type(tide::http::mime::JSON);
+        Ok(resp)
+    }
+
+    // Player leaderboard for a time window. Query parameters:
+    //   - `window`: lookback duration, e.g. "30days" (see `humantime::parse_duration`);
+    //     all time if omitted;
+    //   - `sort`: column to sort by, see `LeaderboardSort`.
+    async fn handle_leaderboard(req: Request<Self>) -> tide::Result {
+        let window = query_param(&req, "window");
+        let sort_key = match query_param(&req, "sort") {
+            None => LeaderboardSort::Pointrate,
+            Some(s) => LeaderboardSort::from_param(&s).ok_or_else(|| {
+                tide::Error::from_str(StatusCode::BadRequest, format!("Unknown sort column: {s}"))
+            })?,
+        };
+        let now = OffsetDateTime::now_utc();
+        let range_start = match &window {
+            None => OffsetDateTime::UNIX_EPOCH,
+            Some(w) => now.saturating_sub(humantime::parse_duration(w)?.try_into()?),
+        };
+        let games = req
+            .state()
+            .finished_games(&GamesQuery::time_range(range_start..now))
+            .await
+            .map_err(anyhow::Error::from)?;
+        // Ratings are always current: they are not recomputed for the window.
+        let ratings: HashMap<String, PlayerRating> = req
+            .state()
+            .player_ratings()
+            .await
+            .map_err(anyhow::Error::from)?
+            .into_iter()
+            .collect();
+        let rating = |name: &str| ratings.get(name).map_or(INITIAL_RATING, |r| r.rating);
+        let (player_stats, _) = aggregate_stats(games);
+        let mut final_stats = process_stats(player_stats.into_iter());
+        final_stats.sort_unstable_by(|a, b| match sort_key {
+            LeaderboardSort::Rating => rating(&b.name).total_cmp(&rating(&a.name)),
+            LeaderboardSort::Pointrate => b.pointrate.total_cmp(&a.pointrate),
+            LeaderboardSort::Points => b.points.total_cmp(&a.points),
+            LeaderboardSort::Games => b.games.cmp(&a.games),
+            LeaderboardSort::Wins => b.wins.cmp(&a.wins),
+        });
+
+        let window_param = window.as_ref().map_or(String::new(), |w| format!("&window={w}"));
+        let sort_link = |key: LeaderboardSort| {
+            let param = key.to_param();
+            let title = if key == sort_key { format!("{} ▼", key.title()) } else { key.title().to_owned() };
+            rsx! { <th><a href={format!("/dyn/leaderboard?sort={param}{window_param}")}>{title}</a></th> }
+        };
+        let window_links = [("All time", None), ("1 day", Some("1day")), ("7 days", Some("7days")),
+            ("30 days", Some("30days")), ("365 days", Some("365days"))]
+            .into_iter()
+            .map(|(title, w)| {
+                let href = match w {
+                    None => format!("/dyn/leaderboard?sort={}", sort_key.to_param()),
+                    Some(w) => format!("/dyn/leaderboard?sort={}&window={w}", sort_key.to_param()),
+                };
+                let title = if window.as_deref() == w { format!("[{title}]") } else { title.to_owned() };
+                rsx! { <a href={href}>{title}</a> }
+            })
+            .collect::<Vec<_>>();
+        let table_body = final_stats
+            .into_iter()
+            .map(|s| {
+                rsx! {
+                    <tr>
+                        <td><a href={format!("/dyn/player/{}", s.name)}>{s.name.clone()}</a></td>
+                        <td>{format!("{:.0}", rating(&s.name))}</td>
+                        <td>{format!("{:.3}", s.pointrate)}</td>
+                        <td>{s.points}</td>
+                        <td>{s.games}</td>
+                        <td>{s.wins}</td>
+                        <td>{s.losses}</td>
+                        <td>{s.draws}</td>
+                    </tr>
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let h: String = html! {
+            <html>
+                <style>
+                    {Self::STYLESHEET}
+                </style>
+            <head>
+            </head>
+            <body>
+              <p>{window_links}</p>
+              <table>
+                <tr>
+                    <th>{"Player"}</th>
+                    {sort_link(LeaderboardSort::Rating)}
+                    {sort_link(LeaderboardSort::Pointrate)}
+                    {sort_link(LeaderboardSort::Points)}
+                    {sort_link(LeaderboardSort::Games)}
+                    {sort_link(LeaderboardSort::Wins)}
+                    <th>{"Losses"}</th>
+                    <th>{"Draws"}</th>
+                </tr>
+                {table_body}
+              </table>
+            </body>
+            </html>
+        };
+        let mut resp = Response::new(StatusCode::Ok);
+        resp.set_content_type(Mime::from("text/html; charset=UTF-8"));
+        resp.set_body(h);
+        Ok(resp)
+    }
+
+    // Player profile: summary, results by board and color, partners, rating over time
+    // and game history.
+    async fn handle_player(req: Request<Self>) -> tide::Result {
+        let player_name = req.param("player")?.to_owned();
+        let mut query = GamesQuery::time_range(OffsetDateTime::UNIX_EPOCH..OffsetDateTime::now_utc());
+        query.filter.player = Some(player_name.clone());
+        let player_games = req
+            .state()
+            .finished_games(&query)
+            .await
+            .map_err(anyhow::Error::from)?;
+        let rating_history = req
+            .state()
+            .rating_history(&player_name)
+            .await
+            .map_err(anyhow::Error::from)?;
+        // Newest first, as returned by `finished_games`.
+        let games = player_games
+            .into_iter()
+            .filter_map(|(rowid, game)| {
+                let seat = player_seat(&game, &player_name)?;
+                let outcome = team_outcome(&game.result, seat.team);
+                Some((rowid, game, seat, outcome))
+            })
+            .collect::<Vec<_>>();
+        if games.is_empty() && rating_history.is_empty() {
+            return Err(tide::Error::from_str(
+                StatusCode::NotFound,
+                format!("No games found for player {player_name}"),
+            ));
+        }
+
+        let mut total = RawStats::default();
+        let mut per_board = HashMap::<BughouseBoard, RawStats>::new();
+        let mut per_force = HashMap::<Force, RawStats>::new();
+        let mut per_partner = HashMap::<String, RawStats>::new();
+        for (_, _, seat, outcome) in games.iter() {
+            let Some(outcome) = outcome else {
+                continue;
+            };
+            total.add(*outcome);
+            per_board.entry(seat.board).or_default().add(*outcome);
+            per_force.entry(get_bughouse_force(seat.team, seat.board)).or_default().add(*outcome);
+            per_partner.entry(seat.partner.clone()).or_default().add(*outcome);
+        }
+        let (current_streak, longest_win_streak) =
+            streaks(games.iter().rev().filter_map(|(_, _, _, outcome)| *outcome));
+        let current_streak = match current_streak {
+            None => "-".to_owned(),
+            Some((Outcome::Win, n)) => format!("{n} win(s)"),
+            Some((Outcome::Loss, n)) => format!("{n} loss(es)"),
+            Some((Outcome::Draw, n)) => format!("{n} draw(s)"),
+        };
+        let current_rating = rating_history
+            .first()
+            .map_or("-".to_owned(), |r| format!("{:.0}", r.rating));
+
+        let by_seat_stats = process_stats(
+            [
+                ("Board A", per_board.remove(&BughouseBoard::A)),
+                ("Board B", per_board.remove(&BughouseBoard::B)),
+                ("White", per_force.remove(&Force::White)),
+                ("Black", per_force.remove(&Force::Black)),
+            ]
+            .into_iter()
+            .map(|(label, s)| (label.to_owned(), s.unwrap_or_default())),
+        );
+        let by_seat = by_seat_stats
+            .into_iter()
+            .map(|s| {
+                rsx! {
+                    <tr>
+                        <td>{s.name}</td>
+                        <td>{if s.games == 0 { "-".to_owned() } else { format!("{:.3}", s.pointrate) }}</td>
+                        <td>{s.games}</td>
+                        <td>{s.wins}</td>
+                        <td>{s.losses}</td>
+                        <td>{s.draws}</td>
+                    </tr>
+                }
+            })
+            .collect::<Vec<_>>();
+        let mut partner_stats = process_stats(per_partner.into_iter());
+        partner_stats.sort_unstable_by(|a, b| b.games.cmp(&a.games).then_with(|| a.name.cmp(&b.name)));
+        let partners = partner_stats
+            .into_iter()
+            .map(|s| {
+                rsx! {
+                    <tr>
+                        <td><a href={format!("/dyn/player/{}", s.name)}>{s.name.clone()}</a></td>
+                        <td>{format!("{:.3}", s.pointrate)}</td>
+                        <td>{s.games}</td>
+                        <td>{s.wins}</td>
+                        <td>{s.losses}</td>
+                        <td>{s.draws}</td>
+                    </tr>
+                }
+            })
+            .collect::<Vec<_>>();
+        // Rating at the end of each day when the player had games.
+        let mut daily_ratings: Vec<(String, f64, i64)> = vec![];
+        for entry in rating_history.iter().rev() {
+            let date = format_timestamp_date_and_time(entry.game_end_time)
+                .map_or("-".to_owned(), |(date, _)| date);
+            match daily_ratings.last_mut() {
+                Some((last_date, rating, games)) if *last_date == date => {
+                    *rating = entry.rating;
+                    *games = entry.games_played;
+                }
+                _ => daily_ratings.push((date, entry.rating, entry.games_played)),
+            }
+        }
+        let rating_rows = daily_ratings
+            .into_iter()
+            .rev()
+            .map(|(date, rating, games)| {
+                rsx! {
+                    <tr>
+                        <td>{date}</td>
+                        <td>{format!("{:.0}", rating)}</td>
+                        <td>{games}</td>
+                    </tr>
+                }
+            })
+            .collect::<Vec<_>>();
+        let game_rows = games
+            .into_iter()
+            .map(|(rowid, game, seat, outcome)| {
+                let (start_date, start_time) = format_timestamp_date_and_time(game.game_start_time)
+                    .unwrap_or(("-".into(), "-".into()));
+                let outcome = outcome.map_or("-".to_owned(), |o| format!("{o:?}"));
+                rsx! {<tr>
+                    <td>{start_date}</td>
+                    <td>{start_time}</td>
+                    <td>{format!("{:?}", seat.board)}</td>
+                    <td>{format!("{:?}", get_bughouse_force(seat.team, seat.board))}</td>
+                    <td><a href={format!("/dyn/player/{}", seat.partner)}>{seat.partner.clone()}</a></td>
+                    <td>{seat.opponents.join(", ")}</td>
+                    <td>{outcome}</td>
+                    <td><a href={format!("/dyn/pgn/{}", rowid.id)}>{"pgn💾"}</a></td>
+                    <td><a href={format!("/dyn/game/{}", rowid.id)}>{"view▶"}</a></td>
+                </tr>}
+            })
+            .collect::<Vec<_>>();
+
+        let h: String = html! {
+            <html>
+                <style>
+                    {Self::STYLESHEET}
+                </style>
+            <head>
+            </head>
+            <body>
+              <h2>{player_name.clone()}</h2>
+              <p>{format!(
+                  "Rating: {}. Games: {}. Wins: {}. Losses: {}. Draws: {}. Current streak: {}. Longest win streak: {}.",
+                  current_rating, total.wins + total.losses + total.draws, total.wins, total.losses, total.draws,
+                  current_streak, longest_win_streak
+              )}</p>
+              <table>
+                <p>{"By board and color"}</p>
+                <tr>
+                    <th>{"Seat"}</th>
+                    <th>{"Pointrate"}</th>
+                    <th>{"Games"}</th>
+                    <th>{"Wins"}</th>
+                    <th>{"Losses"}</th>
+                    <th>{"Draws"}</th>
+                </tr>
+                {by_seat}
+              </table>
+              <table>
+                <p>{"Partners"}</p>
+                <tr>
+                    <th>{"Partner"}</th>
+                    <th>{"Pointrate"}</th>
+                    <th>{"Games"}</th>
+                    <th>{"Wins"}</th>
+                    <th>{"Losses"}</th>
+                    <th>{"Draws"}</th>
+                </tr>
+                {partners}
+              </table>
+              <table>
+                <p>
+                    {"Rating over time ("}
+                    <a href={format!("/dyn/ratings/{}", player_name)}>{"all games"}</a>
+                    {")"}
+                </p>
+                <tr>
+                    <th>{"Date"}</th>
+                    <th>{"Rating"}</th>
+                    <th>{"Games"}</th>
+                </tr>
+                {rating_rows}
+              </table>
+              <table>
+                <p>{"Games"}</p>
+                <tr>
+                    <th>{"Date"}</th>
+                    <th>{"Time (UTC)"}</th>
+                    <th>{"Board"}</th>
+                    <th>{"Color"}</th>
+                    <th>{"Partner"}</th>
+                    <th>{"Opponents"}</th>
+                    <th>{"Result"}</th>
+                    <th>{"Pgn"}</th>
+                    <th>{"Replay"}</th>
+                </tr>
+                {game_rows}
+              </table>
+            </body>
+            </html>
+        };
+        let mut resp = Response::new(StatusCode::Ok);
+        resp.set_content_type(Mime::from("text/html; charset=UTF-8"));
+        resp.set_body(h);
+        Ok(resp)
+    }
+
+    async fn handle_api_openapi(_req: Request<Self>) -> tide::Result {
+        json_response(&api::openapi_description())
+    }
+
+    // Query parameters: `since`, `until`, `player`, `result`. See `api::openapi_description`.
+    async fn handle_api_games(req: Request<Self>) -> tide::Result {
+        let range = api_time_range(&req)?;
+        let player = query_param(&req, "player");
+        let result = query_param(&req, "result");
+        if let Some(result) = &result {
+            if !api::GAME_RESULTS.contains(&result.as_str()) {
+                return Err(tide::Error::from_str(
+                    StatusCode::BadRequest,
+                    format!("Unknown result: {result}"),
+                ));
+            }
+        }
+        let mut query = GamesQuery::time_range(range);
+        query.filter.player = player;
+        query.filter.result = result;
+        let games = req
+            .state()
+            .finished_games(&query)
+            .await
+            .map_err(anyhow::Error::from)?;
+        let games = games
+            .iter()
+            .map(|(rowid, game)| api::game_summary(rowid.id, game))
+            .collect::<Vec<_>>();
+        json_response(&games)
+    }
+
+    async fn handle_api_game(req: Request<Self>) -> tide::Result {
+        let id = req.param("id")?;
+        let id = id.parse().map_err(|_| {
+            tide::Error::from_str(StatusCode::BadRequest, format!("Invalid game id: {id}"))
+        })?;
+        let rowid = RowId { id };
+        let game = req
+            .state()
+            .finished_game(rowid)
+            .await
+            .map_err(|e| tide::Error::from_str(StatusCode::NotFound, e.to_string()))?;
+        let (turns, turns_error) = match pgn::parse_bpgn_turns(&game.game_pgn) {
+            Ok(turns) => (turns, None),
+            Err(e) => (vec![], Some(e)),
+        };
+        json_response(&api::GameDetails {
+            summary: api::game_summary(rowid.id, &game),
+            pgn: game.game_pgn,
+            turns,
+            turns_error,
+        })
+    }
+
+    async fn handle_api_player_stats(req: Request<Self>) -> tide::Result {
+        let range = api_time_range(&req)?;
+        let games = req
+            .state()
+            .finished_games(&GamesQuery::time_range(range))
+            .await
+            .map_err(anyhow::Error::from)?;
+        let ratings: HashMap<String, PlayerRating> = req
+            .state()
+            .player_ratings()
+            .await
+            .map_err(anyhow::Error::from)?
+            .into_iter()
+            .collect();
+        let (player_stats, _) = aggregate_stats(games);
+        let mut final_stats = process_stats(player_stats.into_iter());
+        final_stats.sort_unstable_by(|a, b| b.pointrate.total_cmp(&a.pointrate));
+        let stats = final_stats
+            .into_iter()
+            .map(|s| api::PlayerStats {
+                rating: ratings.get(&s.name).map(|r| r.rating),
+                name: s.name,
+                games: s.games,
+                wins: s.wins,
+                losses: s.losses,
+                draws: s.draws,
+                points: s.points,
+                pointrate: s.pointrate,
+            })
+            .collect::<Vec<_>>();
+        json_response(&stats)
+    }
+
+    async fn handle_api_team_stats(req: Request<Self>) -> tide::Result {
+        let range = api_time_range(&req)?;
+        let games = req
+            .state()
+            .finished_games(&GamesQuery::time_range(range))
+            .await
+            .map_err(anyhow::Error::from)?;
+        let (_, team_stats) = aggregate_stats(games);
+        let mut stats = team_stats
+            .into_iter()
+            .map(|(players, s)| {
+                let games = s.wins + s.losses + s.draws;
+                let points = s.wins as f64 + 0.5 * s.draws as f64;
+                api::TeamStats {
+                    players,
+                    games,
+                    wins: s.wins,
+                    losses: s.losses,
+                    draws: s.draws,
+                    points,
+                    pointrate: points / games as f64,
+                }
+            })
+            .collect::<Vec<_>>();
+        stats.sort_unstable_by(|a, b| b.pointrate.total_cmp(&a.pointrate));
+        json_response(&stats)
+    }
+
+    async fn handle_api_contests(req: Request<Self>) -> tide::Result {
+        let contests = req
+            .state()
+            .live_contests()
+            .await
+            .map_err(anyhow::Error::from)?
+            .iter()
+            .map(|(update_time, snapshot)| api::live_contest(*update_time, snapshot))
+            .collect::<Vec<_>>();
+        json_response(&contests)
+    }
+
+    // Client error reports grouped by message and client version, most recent first.
+    async fn handle_errors(req: Request<Self>) -> tide::Result {
+        let groups = req
+            .state()
+            .client_error_groups()
+            .await
+            .map_err(anyhow::Error::from)?;
+        let format_time = |t| {
+            format_timestamp_date_and_time(t)
+                .map_or("-".to_owned(), |(date, time)| format!("{date} {time}"))
+        };
+        let table_body = groups
+            .into_iter()
+            .map(|g| {
+                rsx! {<tr>
+                    <td>{format_time(g.last_seen)}</td>
+                    <td>{format_time(g.first_seen)}</td>
+                    <td>{g.reports}</td>
+                    <td>{g.user_agents}</td>
+                    <td>{g.git_version}</td>
+                    <td>{g.kind}</td>
+                    <td><pre>{g.message}</pre></td>
+                </tr>}
+            })
+            .collect::<Vec<_>>();
+
+        let h: String = html! {
+            <html>
+                <style>
+                    {Self::STYLESHEET}
+                </style>
+            <head>
+            </head>
+            <body>
+              <table>
+                <tr>
+                    <th>{"Last seen (UTC)"}</th>
+                    <th>{"First seen (UTC)"}</th>
+                    <th>{"Reports"}</th>
+                    <th>{"User agents"}</th>
+                    <th>{"Client version"}</th>
+                    <th>{"Kind"}</th>
+                    <th>{"Message"}</th>
+                </tr>
+                {table_body}
+              </table>
+            </body>
+            </html>
+        };
+        let mut resp = Response::new(StatusCode::Ok);
+        resp.set_content_type(Mime::from("text/html; charset=UTF-8"));
+        resp.set_body(h);
+        Ok(resp)
+    }
+
+    // Client performance dashboard: daily percentiles of each meter, and a breakdown by server
+    // version (in deploy order) and by browser. See `PerfFilter::from_params` for query parameters.
+    async fn handle_performance(req: Request<Self>) -> tide::Result {
+        let filter = PerfFilter::from_params(|key| query_param(&req, key))
+            .map_err(|e| tide::Error::from_str(StatusCode::BadRequest, e))?;
+        let rows = req
+            .state()
+            .client_performance(&filter)
+            .await
+            .map_err(anyhow::Error::from)?;
+        let mut meters = std::collections::BTreeMap::<&str, Vec<&PerfRow>>::new();
+        for row in rows.iter() {
+            meters.entry(row.meter.as_str()).or_default().push(row);
+        }
+
+        // Group cells link to the dashboard narrowed down to the group, if possible.
+        let summary_row = |group: String, narrowed: Option<PerfFilter>, s: &PerfSummary| {
+            let group_link = narrowed.map(|f| rsx! { <a href={performance_link(&f)}>{" 🔍"}</a> });
+            rsx! {<tr>
+                <td>{group}{group_link}</td>
+                <td>{s.reports}</td>
+                <td>{s.num_values}</td>
+                <td>{format!("{:.1}", s.p50)}</td>
+                <td>{format!("{:.1}", s.p90)}</td>
+                <td>{format!("{:.1}", s.p99)}</td>
+            </tr>}
+        };
+        let sections = meters
+            .into_iter()
+            .map(|(meter, meter_rows)| {
+                let chart = performance::svg_chart(meter, &performance::daily_summaries(&meter_rows));
+                let mut versions = vec![];
+                for row in meter_rows.iter() {
+                    if !versions.contains(&row.git_version) {
+                        versions.push(row.git_version.clone());
+                    }
+                }
+                let by_version = performance::summaries_by(&meter_rows, |row| row.git_version.clone());
+                let by_browser = performance::summaries_by(&meter_rows, |row| {
+                    performance::user_agent_family(&row.user_agent)
+                });
+                let version_rows = versions
+                    .into_iter()
+                    .filter_map(|v| {
+                        let narrowed = PerfFilter { git_version: Some(v.clone()), ..filter.clone() };
+                        Some(summary_row(format!("version {v}"), Some(narrowed), by_version.get(&v)?))
+                    })
+                    .collect::<Vec<_>>();
+                let browser_rows = by_browser
+                    .iter()
+                    .map(|(browser, s)| summary_row(browser.to_string(), None, s))
+                    .collect::<Vec<_>>();
+                let meter_filter = PerfFilter { meter: Some(meter.to_owned()), ..filter.clone() };
+                rsx! {
+                    <div>
+                      <p><a href={performance_link(&meter_filter)}>{meter.to_owned()}</a></p>
+                      <img src={performance::svg_data_url(&chart)} />
+                      <table>
+                        <tr>
+                            <th>{"Group"}</th>
+                            <th>{"Reports"}</th>
+                            <th>{"Values"}</th>
+                            <th>{"P50"}</th>
+                            <th>{"P90"}</th>
+                            <th>{"P99"}</th>
+                        </tr>
+                        {version_rows}
+                        {browser_rows}
+                      </table>
+                    </div>
+                }
+            })
+            .collect::<Vec<_>>();
+        let text_input = |name: &'static str, value: Option<String>, placeholder: &'static str| {
+            rsx! { <input type={"text"} name={name} value={value.unwrap_or_default()} placeholder={placeholder} /> }
+        };
+
+        let h: String = html! {
+            <html>
+                <style>
+                    {Self::STYLESHEET}
+                </style>
+            <head>
+            </head>
+            <body>
+              <form method={"get"} action={"/dyn/performance"}>
+                <p>
+                  {"Days: "}{text_input("days", Some(filter.lookback_days.to_string()), "")}
+                  {" Version: "}{text_input("version", filter.git_version.clone(), "exact")}
+                  {" User agent: "}{text_input("user_agent", filter.user_agent.clone(), "e.g. Firefox")}
+                  {" Meter: "}{text_input("meter", filter.meter.clone(), "e.g. update_state")}
+                  {" "}<input type={"submit"} value={"Show"} />
+                </p>
+              </form>
+              {sections}
+            </body>
+            </html>
+        };
+        let mut resp = Response::new(StatusCode::Ok);
+        resp.set_content_type(Mime::from("text/html; charset=UTF-8"));
+        resp.set_body(h);
+        Ok(resp)
+    }
+
+    async fn handle_stats_with_duration(req: Request<Self>) -> tide::Result {
+        let duration_str = req.param("duration")?;
+        let duration = humantime::parse_duration(duration_str)?;
+        Self::handle_stats(req, Some(duration.try_into()?)).await
+    }
+
+    async fn handle_stats(req: Request<Self>, lookback: Option<time::Duration>) -> tide::Result {
+        let now = OffsetDateTime::now_utc();
+        let range_start = match lookback {
+            None => OffsetDateTime::UNIX_EPOCH,
+            Some(d) => now.saturating_sub(d),
+        };
+        let games = req
+            .state()
+            .finished_games(&GamesQuery::time_range(range_start..now))
+            .await
+            .map_err(anyhow::Error::from)?;
+        let (player_stats, team_stats) = aggregate_stats(games);
+        let turn_stats = req
+            .state()
+            .turn_stats(range_start..now)
+            .await
+            .map_err(anyhow::Error::from)?;
+        let rules_stats = req
+            .state()
+            .rules_stats(range_start..now)
+            .await
+            .map_err(anyhow::Error::from)?;
+
+        let mut final_player_stats = process_stats(player_stats.into_iter());
+        let mut final_team_stats = process_stats(
+            team_stats
+                .into_iter()
+                .map(|([t0, t1], s)| (format!("{}, {}", t0, t1), s)),
+        );
+        final_player_stats.sort_unstable_by(|a, b| b.pointrate.total_cmp(&a.pointrate));
+        final_team_stats.sort_unstable_by(|a, b| b.pointrate.total_cmp(&a.pointrate));
+
+        let leaderboard = |final_stats: Vec<FinalStats>| {
+            final_stats
+                .into_iter()
+                .map(|s| {
+                    rsx! {
+                        <tr>
+                            <td>{s.name}</td>
+                            <td>{format!("{:.3}", s.pointrate)}</td>
+                            <td>{s.points}</td>
+                            <td>{s.games}</td>
+                            <td>{s.wins}</td>
+                            <td>{s.losses}</td>
+                            <td>{s.draws}</td>
+                        </tr>
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+        let turn_table = turn_stats
+            .into_iter()
+            .map(|s| {
+                rsx! {
+                    <tr>
+                        <td>{s.player_name}</td>
+                        <td>{s.games}</td>
+                        <td>{s.turns}</td>
+                        <td>{s.captures}</td>
+                        <td>{s.drops}</td>
+                        <td>{s.avg_think_ms.map_or(String::new(), |ms| format!("{:.1}", ms / 1000.0))}</td>
+                    </tr>
+                }
+            })
+            .collect::<Vec<_>>();
+        let rules_table = rules_stats
+            .into_iter()
+            .map(|s| {
+                rsx! {
+                    <tr>
+                        <td>{s.starting_position}</td>
+                        <td>{s.starting_time_sec}</td>
+                        <td>{s.teaming}</td>
+                        <td>{s.drop_aggression}</td>
+                        <td>{s.contests}</td>
+                        <td>{s.games}</td>
+                    </tr>
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let h: String = html! {
+            <html>
+                <style>
+                    {Self::STYLESHEET}
+                </style>
+            <head>
+            </head>
+            <body>
+              <table>
+                <p>{"Player Leaderboard"}</p>
+                <tr>
+                    <th>{"Player"}</th>
+                    <th>{"Pointrate"}</th>
+                    <th>{"Points"}</th>
+                    <th>{"Games"}</th>
+                    <th>{"Wins"}</th>
+                    <th>{"Losses"}</th>
+                    <th>{"Draws"}</th>
+                </tr>
+                {leaderboard(final_player_stats)}
+              </table>
+              <table>
+                <p>{"Team Leaderboard"}</p>
+                <tr>
+                    <th>{"Team"}</th>
+                    <th>{"Pointrate"}</th>
+                    <th>{"Points"}</th>
+                    <th>{"Games"}</th>
+                    <th>{"Wins"}</th>
                     <th>{"Losses"}</th>
                     <th>{"Draws"}</th>
                 </tr>
                 {leaderboard(final_team_stats)}
               </table>
+              <table>
+                <p>{"Turns"}</p>
+                <tr>
+                    <th>{"Player"}</th>
+                    <th>{"Games"}</th>
+                    <th>{"Turns"}</th>
+                    <th>{"Captures"}</th>
+                    <th>{"Drops"}</th>
+                    <th>{"Avg think time, s"}</th>
+                </tr>
+                {turn_table}
+              </table>
+              <table>
+                <p>{"Rules"}</p>
+                <tr>
+                    <th>{"Starting position"}</th>
+                    <th>{"Starting time, s"}</th>
+                    <th>{"Teaming"}</th>
+                    <th>{"Drop aggression"}</th>
+                    <th>{"Contests"}</th>
+                    <th>{"Games"}</th>
+                </tr>
+                {rules_table}
+              </table>
             </body>
             </html>
         };
@@ -411,6 +1739,73 @@ struct RawStats {
     losses: usize,
 }
 
+impl RawStats {
+    fn add(&mut self, outcome: Outcome) {
+        match outcome {
+            Outcome::Win => self.wins += 1,
+            Outcome::Loss => self.losses += 1,
+            Outcome::Draw => self.draws += 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Win,
+    Loss,
+    Draw,
+}
+
+// Where a given player sat in a game.
+#[derive(Debug, Clone)]
+struct PlayerSeat {
+    team: Team,
+    board: BughouseBoard,
+    partner: String,
+    // The opponent on the same board goes first.
+    opponents: [String; 2],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LeaderboardSort {
+    Rating,
+    Pointrate,
+    Points,
+    Games,
+    Wins,
+}
+
+impl LeaderboardSort {
+    fn from_param(s: &str) -> Option<Self> {
+        match s {
+            "rating" => Some(Self::Rating),
+            "pointrate" => Some(Self::Pointrate),
+            "points" => Some(Self::Points),
+            "games" => Some(Self::Games),
+            "wins" => Some(Self::Wins),
+            _ => None,
+        }
+    }
+    fn to_param(self) -> &'static str {
+        match self {
+            Self::Rating => "rating",
+            Self::Pointrate => "pointrate",
+            Self::Points => "points",
+            Self::Games => "games",
+            Self::Wins => "wins",
+        }
+    }
+    fn title(self) -> &'static str {
+        match self {
+            Self::Rating => "Rating",
+            Self::Pointrate => "Pointrate",
+            Self::Points => "Points",
+            Self::Games => "Games",
+            Self::Wins => "Wins",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 struct FinalStats {
     name: String,
@@ -422,6 +1817,84 @@ struct FinalStats {
     pointrate: f64,
 }
 
+#[derive(Debug, Clone)]
+struct TurnStats {
+    player_name: String,
+    games: i64,
+    turns: i64,
+    captures: i64,
+    drops: i64,
+    avg_think_ms: Option<f64>,
+}
+
+// Popularity of a rule set, as used by contests created within the time range.
+#[derive(Debug, Clone)]
+struct RulesStats {
+    starting_position: String,
+    starting_time_sec: i64,
+    teaming: String,
+    drop_aggression: String,
+    contests: i64,
+    games: i64,
+}
+
+// Client error reports with the same kind, message and client version.
+#[derive(Debug, Clone)]
+struct ClientErrorGroup {
+    kind: String,
+    message: String,
+    git_version: String,
+    reports: i64,
+    user_agents: i64,  // distinct
+    first_seen: Option<OffsetDateTime>,
+    last_seen: Option<OffsetDateTime>,
+}
+
+// Per-player and per-team wins, losses and draws.
+fn aggregate_stats(
+    games: Vec<(RowId, GameResultRow)>,
+) -> (HashMap<String, RawStats>, HashMap<[String; 2], RawStats>) {
+    let mut player_stats = HashMap::<String, RawStats>::new();
+    let mut team_stats = HashMap::<[String; 2], RawStats>::new();
+
+    for (_, game) in games.into_iter() {
+        let red_team = sort([game.player_red_a, game.player_red_b]);
+        let blue_team = sort([game.player_blue_a, game.player_blue_b]);
+        match game.result.as_str() {
+            "DRAW" => {
+                for p in red_team.iter().chain(blue_team.iter()).cloned() {
+                    player_stats.entry(p).or_default().draws += 1;
+                }
+                for team in [red_team, blue_team].iter() {
+                    team_stats.entry(team.clone()).or_default().draws += 1;
+                }
+            }
+            "VICTORY_RED" => {
+                for p in red_team.iter().cloned() {
+                    player_stats.entry(p).or_default().wins += 1;
+                }
+                for p in blue_team.iter().cloned() {
+                    player_stats.entry(p).or_default().losses += 1;
+                }
+                team_stats.entry(red_team).or_default().wins += 1;
+                team_stats.entry(blue_team).or_default().losses += 1;
+            }
+            "VICTORY_BLUE" => {
+                for p in red_team.iter().cloned() {
+                    player_stats.entry(p).or_default().losses += 1;
+                }
+                for p in blue_team.iter().cloned() {
+                    player_stats.entry(p).or_default().wins += 1;
+                }
+                team_stats.entry(red_team).or_default().losses += 1;
+                team_stats.entry(blue_team).or_default().wins += 1;
+            }
+            _ => {}
+        }
+    }
+    (player_stats, team_stats)
+}
+
 fn process_stats<I: Iterator<Item = (String, RawStats)>>(raw_stats: I) -> Vec<FinalStats> {
     raw_stats
         .map(|(name, s)| {
@@ -440,6 +1913,108 @@ fn process_stats<I: Iterator<Item = (String, RawStats)>>(raw_stats: I) -> Vec<Fi
         .collect()
 }
 
+fn team_outcome(result: &str, team: Team) -> Option<Outcome> {
+    match (result, team) {
+        ("DRAW", _) => Some(Outcome::Draw),
+        ("VICTORY_RED", Team::Red) | ("VICTORY_BLUE", Team::Blue) => Some(Outcome::Win),
+        ("VICTORY_RED", Team::Blue) | ("VICTORY_BLUE", Team::Red) => Some(Outcome::Loss),
+        _ => None,
+    }
+}
+
+fn player_seat(game: &GameResultRow, player_name: &str) -> Option<PlayerSeat> {
+    let seats = [
+        (Team::Red, BughouseBoard::A, &game.player_red_a),
+        (Team::Red, BughouseBoard::B, &game.player_red_b),
+        (Team::Blue, BughouseBoard::A, &game.player_blue_a),
+        (Team::Blue, BughouseBoard::B, &game.player_blue_b),
+    ];
+    let name_at = |team: Team, board: BughouseBoard| -> String {
+        seats.iter().find(|(t, b, _)| *t == team && *b == board).unwrap().2.clone()
+    };
+    let (team, board, _) = seats.iter().find(|(_, _, name)| *name == player_name)?;
+    let (team, board) = (*team, *board);
+    Some(PlayerSeat {
+        team,
+        board,
+        partner: name_at(team, board.other()),
+        opponents: [name_at(team.opponent(), board), name_at(team.opponent(), board.other())],
+    })
+}
+
+// Returns current streak (outcome and length) and the longest winning streak.
+// `outcomes` must be in chronological order.
+fn streaks(outcomes: impl Iterator<Item = Outcome>) -> (Option<(Outcome, usize)>, usize) {
+    let mut current: Option<(Outcome, usize)> = None;
+    let mut longest_win_streak = 0;
+    for outcome in outcomes {
+        current = match current {
+            Some((o, n)) if o == outcome => Some((o, n + 1)),
+            _ => Some((outcome, 1)),
+        };
+        if let Some((Outcome::Win, n)) = current {
+            longest_win_streak = longest_win_streak.max(n);
+        }
+    }
+    (current, longest_win_streak)
+}
+
+// Parses `since` and `until` query parameters. Defaults to all time.
+fn api_time_range<State>(req: &Request<State>) -> tide::Result<Range<OffsetDateTime>> {
+    let parse = |key: &str, default: OffsetDateTime| match query_param(req, key) {
+        None => Ok(default),
+        Some(s) => api::parse_time(&s).map_err(|e| tide::Error::from_str(StatusCode::BadRequest, e)),
+    };
+    Ok(parse("since", OffsetDateTime::UNIX_EPOCH)?..parse("until", OffsetDateTime::now_utc())?)
+}
+
+fn json_response<T: serde::Serialize>(value: &T) -> tide::Result {
+    let mut resp = Response::new(StatusCode::Ok);
+    resp.set_body(tide::Body::from_json(value)?);
+    Ok(resp)
+}
+
+fn bind_args<'q, DB>(
+    mut query: sqlx::query::Query<'q, DB, <DB as sqlx::database::HasArguments<'q>>::Arguments>,
+    args: Vec<SqlArg>,
+) -> sqlx::query::Query<'q, DB, <DB as sqlx::database::HasArguments<'q>>::Arguments>
+where
+    DB: sqlx::Database,
+    i64: sqlx::Type<DB> + sqlx::Encode<'q, DB>,
+    String: sqlx::Type<DB> + sqlx::Encode<'q, DB>,
+    OffsetDateTime: sqlx::Type<DB> + sqlx::Encode<'q, DB>,
+{
+    for arg in args {
+        query = match arg {
+            SqlArg::Text(s) => query.bind(s),
+            SqlArg::Int(i) => query.bind(i),
+            SqlArg::Time(t) => query.bind(t),
+        };
+    }
+    query
+}
+
+fn games_link(query: &GamesQuery) -> String {
+    dyn_link("/dyn/games", query.to_params())
+}
+
+fn performance_link(filter: &PerfFilter) -> String {
+    dyn_link("/dyn/performance", filter.to_params())
+}
+
+fn dyn_link(path: &str, params: Vec<(&'static str, String)>) -> String {
+    let mut url = tide::http::Url::parse(&format!("http://localhost{path}")).unwrap();
+    url.query_pairs_mut().extend_pairs(params);
+    match url.query() {
+        None | Some("") => url.path().to_owned(),
+        Some(q) => format!("{}?{q}", url.path()),
+    }
+}
+
+fn query_param<State>(req: &Request<State>, key: &str) -> Option<String> {
+    req.url().query_pairs().find(|(k, _)| k == key).map(|(_, v)| v.into_owned())
+}
+
 fn format_timestamp_date_and_time(maybe_ts: Option<OffsetDateTime>) -> Option<(String, String)> {
     let datetime = maybe_ts?;
     let date = datetime
@@ -463,3 +2038,222 @@ where
     array.as_mut().sort();
     array
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Postgres tests are ignored by default. To run them, set this variable to a database address,
+    // e.g. "postgres://postgres@localhost:5432/bughouse_test", and run `cargo test -- --ignored`.
+    // All tables in the database are dropped! See also bughouse_console tests.
+    const POSTGRES_ENV_VAR: &str = "BUGHOUSE_TEST_POSTGRES_DB";
+
+    // Expects a fresh database: tables are created by `SqlxApp::new`.
+    async fn check_app<DB>(app: &SqlxApp<DB>)
+    where
+        DB: sqlx::Database,
+        for<'q> i64: sqlx::Type<DB> + sqlx::Encode<'q, DB> + sqlx::Decode<'q, DB>,
+        for<'q> f64: sqlx::Type<DB> + sqlx::Encode<'q, DB> + sqlx::Decode<'q, DB>,
+        for<'q> String: sqlx::Type<DB> + sqlx::Encode<'q, DB> + sqlx::Decode<'q, DB>,
+        for<'q> OffsetDateTime: sqlx::Type<DB> + sqlx::Encode<'q, DB> + sqlx::Decode<'q, DB>,
+        for<'c> &'c mut DB::Connection: sqlx::Executor<'c, Database = DB>,
+        for<'a> <DB as sqlx::database::HasArguments<'a>>::Arguments: sqlx::IntoArguments<'a, DB>,
+        for<'s> &'s str: sqlx::ColumnIndex<DB::Row>,
+        usize: sqlx::ColumnIndex<DB::Row>,
+    {
+        for (result, pgn, variant) in [("VICTORY_RED", "pgn1", "Classic"), ("DRAW", "pgn2", "FischerRandom")] {
+            sqlx::query::<DB>(
+                "INSERT INTO finished_games (
+                    game_start_time, player_red_a, player_red_b, player_blue_a, player_blue_b, result, game_pgn,
+                    variant)
+                 VALUES ($1, 'p1', 'p2', 'p3', 'p4', $2, $3, $4)",
+            )
+            .bind(OffsetDateTime::now_utc())
+            .bind(result.to_owned())
+            .bind(pgn.to_owned())
+            .bind(variant.to_owned())
+            .execute(&app.pool)
+            .await
+            .unwrap();
+        }
+        let games = app
+            .finished_games(&GamesQuery::time_range(OffsetDateTime::UNIX_EPOCH..OffsetDateTime::now_utc()))
+            .await
+            .unwrap();
+        assert_eq!(games.len(), 2);
+        assert_eq!(app.count_finished_games(&GameFilter::default()).await.unwrap(), 2);
+        let search = |params: &'static str| async move {
+            let query = GamesQuery::from_params(|key| {
+                params.split('&').filter_map(|kv| kv.split_once('=')).find(|(k, _)| *k == key)
+                    .map(|(_, v)| v.to_owned())
+            })
+            .unwrap();
+            let games = app.finished_games(&query).await.unwrap();
+            let count = app.count_finished_games(&query.filter).await.unwrap();
+            (games.into_iter().map(|(_, g)| g.result).collect::<Vec<_>>(), count)
+        };
+        assert_eq!(search("result=DRAW").await, (vec!["DRAW".to_owned()], 1));
+        assert_eq!(search("variant=Classic").await, (vec!["VICTORY_RED".to_owned()], 1));
+        assert_eq!(search("player=P1").await.1, 2);
+        assert_eq!(search("player=p5").await.1, 0);
+        assert_eq!(search("team=p2,p1").await.1, 2);
+        assert_eq!(search("team=p1,p3").await.1, 0);
+        assert_eq!(search("sort=result&order=asc&page=2&page_size=1").await, (vec!["VICTORY_RED".to_owned()], 2));
+        let (rowid, game) = games.iter().find(|(_, g)| g.result == "VICTORY_RED").unwrap();
+        assert_eq!(game.player_red_a, "p1");
+        assert_eq!(app.pgn(*rowid).await.unwrap(), "pgn1");
+        assert_eq!(app.game_record(*rowid).await.unwrap(), None);
+        let full_game = app.finished_game(*rowid).await.unwrap();
+        assert_eq!(full_game.game_pgn, "pgn1");
+        assert_eq!(api::game_summary(rowid.id, &full_game).result, "VICTORY_RED");
+        assert!(app.live_contests().await.unwrap().is_empty());
+        assert!(app.finished_matches().await.unwrap().is_empty());
+        assert!(app.player_ratings().await.unwrap().is_empty());
+        assert!(app.rating_history("p1").await.unwrap().is_empty());
+
+        sqlx::query::<DB>("UPDATE finished_games SET contest_id = 'c1' WHERE result = 'DRAW'")
+            .execute(&app.pool)
+            .await
+            .unwrap();
+        for contest_id in ["c1", "c2"] {
+            sqlx::query::<DB>(
+                "INSERT INTO contests (
+                    contest_id, create_time, starting_position, starting_time_sec, teaming, drop_aggression)
+                 VALUES ($1, $2, 'FischerRandom', 300, 'FixedTeams', 'NoChessMate')",
+            )
+            .bind(contest_id.to_owned())
+            .bind(OffsetDateTime::now_utc())
+            .execute(&app.pool)
+            .await
+            .unwrap();
+        }
+        let draw_rowid = games.iter().find(|(_, g)| g.result == "DRAW").unwrap().0;
+        for (turn_index, force, player_name, remaining_ms, capture) in [
+            (0_i64, "White", "p1", 300_000_i64, ""),
+            (1, "Black", "p3", 297_000, "P"),
+            (2, "White", "p1", 290_000, ""),
+        ] {
+            sqlx::query::<DB>(
+                "INSERT INTO turns (
+                    game_id, turn_index, board, force, player_name, algebraic, game_time_ms, capture,
+                    clock_remaining_ms)
+                 VALUES ($1, $2, 'A', $3, $4, 'e4', 0, NULLIF($5, ''), $6)",
+            )
+            .bind(draw_rowid.id)
+            .bind(turn_index)
+            .bind(force.to_owned())
+            .bind(player_name.to_owned())
+            .bind(capture.to_owned())
+            .bind(remaining_ms)
+            .execute(&app.pool)
+            .await
+            .unwrap();
+        }
+        let range = OffsetDateTime::UNIX_EPOCH..OffsetDateTime::now_utc();
+        let turn_stats = app.turn_stats(range.clone()).await.unwrap();
+        let turn_stats = turn_stats
+            .iter()
+            .map(|s| (s.player_name.as_str(), s.games, s.turns, s.captures, s.avg_think_ms))
+            .collect::<Vec<_>>();
+        assert_eq!(turn_stats, vec![("p1", 1, 2, 0, Some(10_000.0)), ("p3", 1, 1, 1, None)]);
+        let rules_stats = app.rules_stats(range).await.unwrap();
+        assert_eq!(rules_stats.len(), 1);
+        assert_eq!((rules_stats[0].contests, rules_stats[0].games), (2, 1));
+
+        for (message, git_version, user_agent) in [
+            ("panicked at 'oops'", "v1", "Firefox"),
+            ("panicked at 'oops'", "v1", "Chrome"),
+            ("panicked at 'oops'", "v2", "Chrome"),
+        ] {
+            sqlx::query::<DB>(
+                "INSERT INTO client_error_reports (report_time, git_version, user_agent, kind, message)
+                 VALUES ($1, $2, $3, 'RustPanic', $4)",
+            )
+            .bind(OffsetDateTime::now_utc())
+            .bind(git_version.to_owned())
+            .bind(user_agent.to_owned())
+            .bind(message.to_owned())
+            .execute(&app.pool)
+            .await
+            .unwrap();
+        }
+        let error_groups = app.client_error_groups().await.unwrap();
+        let error_groups = error_groups
+            .iter()
+            .map(|g| (g.git_version.as_str(), g.reports, g.user_agents))
+            .collect::<Vec<_>>();
+        assert_eq!(sort(error_groups), vec![("v1", 2, 2), ("v2", 1, 1)]);
+
+        for (report_id, git_version, user_agent) in [(1_i64, "v1", "Firefox"), (2, "v2", "Chrome")] {
+            sqlx::query::<DB>(
+                "INSERT INTO client_performance_reports (report_id, report_time, git_version, user_agent)
+                 VALUES ($1, $2, $3, $4)",
+            )
+            .bind(report_id)
+            .bind(OffsetDateTime::now_utc())
+            .bind(git_version.to_owned())
+            .bind(user_agent.to_owned())
+            .execute(&app.pool)
+            .await
+            .unwrap();
+            for (meter, num_values) in [("update_state", 10_i64), ("new_meter", 0)] {
+                sqlx::query::<DB>(
+                    "INSERT INTO client_performance_stats (report_id, meter, p50, p90, p99, num_values)
+                     VALUES ($1, $2, 1, 2, 3, $3)",
+                )
+                .bind(report_id)
+                .bind(meter.to_owned())
+                .bind(num_values)
+                .execute(&app.pool)
+                .await
+                .unwrap();
+            }
+        }
+        let perf_params = |params: &'static str| {
+            PerfFilter::from_params(|key| {
+                params.split('&').filter_map(|kv| kv.split_once('=')).find(|(k, _)| *k == key)
+                    .map(|(_, v)| v.to_owned())
+            })
+            .unwrap()
+        };
+        let perf = app.client_performance(&perf_params("")).await.unwrap();
+        assert_eq!(perf.len(), 2);
+        assert!(perf.iter().all(|row| row.meter == "update_state" && row.p99 == 3));
+        let perf = app.client_performance(&perf_params("user_agent=fire&meter=update_state")).await.unwrap();
+        assert_eq!(perf.iter().map(|row| row.git_version.as_str()).collect::<Vec<_>>(), vec!["v1"]);
+        assert!(app.client_performance(&perf_params("version=v3")).await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn sqlite() {
+        let path = std::env::temp_dir().join(format!(
+            "bughouse_webserver_test_{}.db",
+            OffsetDateTime::now_utc().unix_timestamp_nanos()
+        ));
+        let path = path.to_str().unwrap().to_owned();
+        let app = SqlxApp::<sqlx::Sqlite>::new(&format!("sqlite://{path}?mode=rwc")).unwrap();
+        async_std::task::block_on(check_app(&app));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[ignore = "requires Postgres, see POSTGRES_ENV_VAR"]
+    fn postgres() {
+        let address = std::env::var(POSTGRES_ENV_VAR)
+            .unwrap_or_else(|_| panic!("{POSTGRES_ENV_VAR} must be set to run Postgres tests"));
+        async_std::task::block_on(async {
+            let pool = sqlx::PgPool::connect(&address).await.unwrap();
+            sqlx::query(
+                "DROP TABLE IF EXISTS
+                finished_games, client_performance, finished_matches,
+                player_ratings, rating_history, contest_snapshots, schema_migrations, turns, contests,
+                client_error_reports, client_performance_reports, client_performance_stats",
+            )
+            .execute(&pool)
+            .await
+            .unwrap();
+        });
+        let app = SqlxApp::<sqlx::Postgres>::new(&address).unwrap();
+        async_std::task::block_on(check_app(&app));
+    }
+}
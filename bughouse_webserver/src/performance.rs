@@ -0,0 +1,326 @@
+// Client performance dashboard: stats of `ClientState` meters reported by clients, see
+// `BughouseClientPerformance`. Reports are filtered in SQL and aggregated here, because
+// bucketing by time differs between SQLite and Postgres.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use time::{Date, OffsetDateTime};
+
+use crate::games_query::{escape_like, SqlArg};
+
+pub const DEFAULT_LOOKBACK_DAYS: i64 = 30;
+pub const MAX_LOOKBACK_DAYS: i64 = 366;
+
+// Stats of one meter from one client report. Values are in milliseconds for duration meters.
+#[derive(Clone, Debug)]
+pub struct PerfRow {
+    pub report_time: OffsetDateTime,
+    pub git_version: String,
+    pub user_agent: String,
+    pub meter: String,
+    pub p50: i64,
+    pub p90: i64,
+    pub p99: i64,
+    pub num_values: i64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PerfFilter {
+    pub lookback_days: i64,
+    // Server version at the time of the report, exact match.
+    pub git_version: Option<String>,
+    // Case-insensitive substring match.
+    pub user_agent: Option<String>,
+    pub meter: Option<String>,
+}
+
+// Percentiles of several reports, weighted by the number of values in each report.
+//
+// Improvement potential. Report histograms instead of percentiles, so that they can be merged
+//   exactly.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PerfSummary {
+    pub reports: usize,
+    pub num_values: i64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+}
+
+impl PerfFilter {
+    // Returns SQL condition and appends its parameters to `args`.
+    pub fn where_clause(&self, now: OffsetDateTime, args: &mut Vec<SqlArg>) -> String {
+        let mut arg = |value: SqlArg| {
+            args.push(value);
+            format!("${}", args.len())
+        };
+        let since = now - time::Duration::days(self.lookback_days);
+        let mut conditions = vec![format!("r.report_time >= {}", arg(SqlArg::Time(since)))];
+        if let Some(git_version) = &self.git_version {
+            conditions.push(format!("r.git_version = {}", arg(SqlArg::Text(git_version.clone()))));
+        }
+        if let Some(user_agent) = &self.user_agent {
+            let pattern = format!("%{}%", escape_like(&user_agent.to_lowercase()));
+            conditions.push(format!("LOWER(r.user_agent) LIKE {} ESCAPE '\\'", arg(SqlArg::Text(pattern))));
+        }
+        if let Some(meter) = &self.meter {
+            conditions.push(format!("s.meter = {}", arg(SqlArg::Text(meter.clone()))));
+        }
+        conditions.join(" AND ")
+    }
+
+    // Parses query parameters: `days` (lookback), `version`, `user_agent` and `meter`.
+    // Empty parameters are ignored.
+    pub fn from_params(param: impl Fn(&str) -> Option<String>) -> Result<Self, String> {
+        let param = |key: &str| param(key).map(|v| v.trim().to_owned()).filter(|v| !v.is_empty());
+        let lookback_days = match param("days") {
+            None => DEFAULT_LOOKBACK_DAYS,
+            Some(d) => d.parse().map_err(|_| format!("Invalid days: {d}"))?,
+        };
+        if !(1..=MAX_LOOKBACK_DAYS).contains(&lookback_days) {
+            return Err(format!("Days must be in [1, {MAX_LOOKBACK_DAYS}]"));
+        }
+        Ok(PerfFilter {
+            lookback_days,
+            git_version: param("version"),
+            user_agent: param("user_agent"),
+            meter: param("meter"),
+        })
+    }
+
+    // Inverse of `from_params`. Only non-default parameters are included.
+    pub fn to_params(&self) -> Vec<(&'static str, String)> {
+        let mut params = vec![];
+        if self.lookback_days != DEFAULT_LOOKBACK_DAYS {
+            params.push(("days", self.lookback_days.to_string()));
+        }
+        params.extend(self.git_version.clone().map(|v| ("version", v)));
+        params.extend(self.user_agent.clone().map(|v| ("user_agent", v)));
+        params.extend(self.meter.clone().map(|v| ("meter", v)));
+        params
+    }
+}
+
+impl PerfSummary {
+    // Returns `None` if there are no values.
+    pub fn aggregate<'a>(rows: impl IntoIterator<Item = &'a PerfRow>) -> Option<Self> {
+        let mut reports = 0;
+        let mut num_values = 0;
+        let mut sums = [0.0; 3];
+        for row in rows {
+            reports += 1;
+            num_values += row.num_values;
+            for (sum, p) in sums.iter_mut().zip([row.p50, row.p90, row.p99]) {
+                *sum += p as f64 * row.num_values as f64;
+            }
+        }
+        if num_values <= 0 {
+            return None;
+        }
+        let [p50, p90, p99] = sums.map(|s| s / num_values as f64);
+        Some(PerfSummary { reports, num_values, p50, p90, p99 })
+    }
+}
+
+// Groups rows by `key` and aggregates each group.
+pub fn summaries_by<K: Ord>(rows: &[&PerfRow], key: impl Fn(&PerfRow) -> K) -> BTreeMap<K, PerfSummary> {
+    let mut groups = BTreeMap::<K, Vec<&PerfRow>>::new();
+    for row in rows {
+        groups.entry(key(row)).or_default().push(row);
+    }
+    groups
+        .into_iter()
+        .filter_map(|(k, group)| Some((k, PerfSummary::aggregate(group)?)))
+        .collect()
+}
+
+pub fn daily_summaries(rows: &[&PerfRow]) -> BTreeMap<Date, PerfSummary> {
+    summaries_by(rows, |row| row.report_time.date())
+}
+
+// Browser or client type. Full user agent strings are too diverse for grouping.
+pub fn user_agent_family(user_agent: &str) -> &'static str {
+    // Order matters: e.g. Edge user agents also mention Chrome and Safari.
+    const FAMILIES: [(&str, &str); 7] = [
+        ("Edg", "Edge"),
+        ("OPR", "Opera"),
+        ("Firefox", "Firefox"),
+        ("FxiOS", "Firefox"),
+        ("Chrome", "Chrome"),
+        ("CriOS", "Chrome"),
+        ("Safari", "Safari"),
+    ];
+    if user_agent == "Terminal" {
+        return "Terminal";
+    }
+    FAMILIES
+        .iter()
+        .find(|(marker, _)| user_agent.contains(marker))
+        .map_or("Other", |(_, family)| family)
+}
+
+// Line chart of P50, P90 and P99 by day, as an SVG image.
+pub fn svg_chart(title: &str, days: &BTreeMap<Date, PerfSummary>) -> String {
+    const WIDTH: f64 = 600.0;
+    const HEIGHT: f64 = 200.0;
+    const MARGIN: f64 = 40.0;
+    let (Some(first), Some(last)) = (days.keys().next(), days.keys().next_back()) else {
+        return String::new();
+    };
+    let span_days = (*last - *first).whole_days().max(1) as f64;
+    let max_value = days.values().map(|s| s.p99).fold(1.0, f64::max);
+    let x = |date: &Date| {
+        if first == last {
+            WIDTH / 2.0
+        } else {
+            MARGIN + (*date - *first).whole_days() as f64 / span_days * (WIDTH - 2.0 * MARGIN)
+        }
+    };
+    let y = |value: f64| HEIGHT - MARGIN - value / max_value * (HEIGHT - 2.0 * MARGIN);
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{WIDTH}\" height=\"{HEIGHT}\" \
+        font-family=\"sans-serif\" font-size=\"12\">"
+    );
+    let text = |svg: &mut String, x: f64, y: f64, anchor: &str, s: &str| {
+        write!(svg, "<text x=\"{x:.1}\" y=\"{y:.1}\" text-anchor=\"{anchor}\">{}</text>", escape_xml(s)).unwrap();
+    };
+    text(&mut svg, WIDTH / 2.0, 15.0, "middle", title);
+    write!(
+        svg,
+        "<path d=\"M{MARGIN} {MARGIN} V{} H{}\" stroke=\"black\" fill=\"none\"/>",
+        HEIGHT - MARGIN,
+        WIDTH - MARGIN
+    )
+    .unwrap();
+    text(&mut svg, MARGIN - 4.0, MARGIN + 4.0, "end", &format!("{max_value:.0}"));
+    text(&mut svg, MARGIN - 4.0, HEIGHT - MARGIN + 4.0, "end", "0");
+    text(&mut svg, x(first), HEIGHT - MARGIN + 16.0, "start", &first.to_string());
+    if first != last {
+        text(&mut svg, x(last), HEIGHT - MARGIN + 16.0, "end", &last.to_string());
+    }
+    let percentiles: [(&str, &str, fn(&PerfSummary) -> f64); 3] = [
+        ("P50", "green", |s| s.p50),
+        ("P90", "orange", |s| s.p90),
+        ("P99", "red", |s| s.p99),
+    ];
+    for (i, (name, color, value)) in percentiles.into_iter().enumerate() {
+        let points = days
+            .iter()
+            .map(|(date, s)| format!("{:.1},{:.1}", x(date), y(value(s))))
+            .collect::<Vec<_>>()
+            .join(" ");
+        write!(svg, "<polyline points=\"{points}\" stroke=\"{color}\" fill=\"none\"/>").unwrap();
+        for (date, s) in days {
+            write!(svg, "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"2\" fill=\"{color}\"/>", x(date), y(value(s))).unwrap();
+        }
+        let legend_x = WIDTH - MARGIN - 120.0 + i as f64 * 40.0;
+        write!(svg, "<text x=\"{legend_x}\" y=\"{MARGIN}\" fill=\"{color}\">{name}</text>").unwrap();
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+// For embedding an image into HTML: `<img src=...>`.
+pub fn svg_data_url(svg: &str) -> String {
+    let mut url = "data:image/svg+xml,".to_owned();
+    for b in svg.bytes() {
+        match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'=' | b'/' | b':' | b',' => {
+                url.push(b as char)
+            }
+            _ => write!(url, "%{b:02X}").unwrap(),
+        }
+    }
+    url
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(day: i64, user_agent: &str, p50: i64, num_values: i64) -> PerfRow {
+        PerfRow {
+            report_time: time::macros::datetime!(2023-03-01 12:00 UTC) + time::Duration::days(day - 1),
+            git_version: "v1".to_owned(),
+            user_agent: user_agent.to_owned(),
+            meter: "update_state".to_owned(),
+            p50,
+            p90: p50 * 2,
+            p99: p50 * 3,
+            num_values,
+        }
+    }
+
+    fn parse(query: &str) -> Result<PerfFilter, String> {
+        let pairs = query
+            .split('&')
+            .filter(|kv| !kv.is_empty())
+            .map(|kv| kv.split_once('=').unwrap())
+            .map(|(k, v)| (k.to_owned(), v.to_owned()))
+            .collect::<Vec<_>>();
+        PerfFilter::from_params(|key| pairs.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone()))
+    }
+
+    #[test]
+    fn params_roundtrip() {
+        let filter = parse("days=7&version=v1&user_agent=Fire_fox&meter=refresh").unwrap();
+        let roundtrip = filter.to_params().into_iter().map(|(k, v)| format!("{k}={v}")).collect::<Vec<_>>();
+        assert_eq!(roundtrip, ["days=7", "version=v1", "user_agent=Fire_fox", "meter=refresh"]);
+        let mut args = vec![];
+        let sql = filter.where_clause(OffsetDateTime::UNIX_EPOCH + time::Duration::days(7), &mut args);
+        assert_eq!(
+            sql,
+            "r.report_time >= $1 AND r.git_version = $2 AND LOWER(r.user_agent) LIKE $3 ESCAPE '\\' \
+            AND s.meter = $4"
+        );
+        assert_eq!(args[0], SqlArg::Time(OffsetDateTime::UNIX_EPOCH));
+        assert_eq!(args[2], SqlArg::Text("%fire\\_fox%".to_owned()));
+
+        assert!(parse("days=&version=").unwrap().to_params().is_empty());
+        assert!(parse("days=0").is_err());
+        assert!(parse("days=week").is_err());
+    }
+
+    #[test]
+    fn weighted_summary() {
+        let rows = [row(1, "Firefox", 10, 1), row(1, "Chrome", 20, 3), row(2, "Chrome", 100, 0)];
+        let rows = rows.iter().collect::<Vec<_>>();
+        let summary = PerfSummary::aggregate(rows.iter().copied()).unwrap();
+        assert_eq!((summary.reports, summary.num_values), (3, 4));
+        assert_eq!((summary.p50, summary.p90, summary.p99), (17.5, 35.0, 52.5));
+        let days = daily_summaries(&rows);
+        assert_eq!(days.len(), 1);
+        assert_eq!(days.values().next().unwrap().p50, 17.5);
+    }
+
+    #[test]
+    fn user_agent_families() {
+        let chrome = "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) \
+            Chrome/110.0.0.0 Safari/537.36";
+        let edge = format!("{chrome} Edg/110.0.1587.57");
+        assert_eq!(user_agent_family(chrome), "Chrome");
+        assert_eq!(user_agent_family(&edge), "Edge");
+        assert_eq!(user_agent_family("Mozilla/5.0 (X11; Linux x86_64; rv:109.0) Firefox/110.0"), "Firefox");
+        assert_eq!(user_agent_family("Terminal"), "Terminal");
+        assert_eq!(user_agent_family("curl/7.68.0"), "Other");
+    }
+
+    #[test]
+    fn chart() {
+        let rows = [row(1, "Firefox", 10, 1), row(3, "Firefox", 20, 1)];
+        let rows = rows.iter().collect::<Vec<_>>();
+        let svg = svg_chart("<meter>", &daily_summaries(&rows));
+        assert!(svg.starts_with("<svg"));
+        assert_eq!(svg.matches("<polyline").count(), 3);
+        assert!(svg.contains("&lt;meter&gt;"));
+        assert!(svg.contains("2023-03-03"));
+        assert!(svg_chart("empty", &BTreeMap::new()).is_empty());
+        assert!(!svg_data_url(&svg).contains(['<', '"', '#']));
+    }
+}
@@ -20,6 +20,9 @@ use crate::util::{sort_two, as_single_char};
 use crate::starter::{EffectiveStartingPosition, starting_piece_row, generate_starting_grid};
 
 
+const DROPPABLE_PIECES: [PieceKind; 5] =
+    [PieceKind::Pawn, PieceKind::Knight, PieceKind::Bishop, PieceKind::Rook, PieceKind::Queen];
+
 fn iter_minmax<T: PartialOrd + Copy, I: Iterator<Item = T>>(iter: I) -> Option<(T, T)> {
     match iter.minmax() {
         itertools::MinMaxResult::NoElements => None,
@@ -448,6 +451,7 @@ pub enum VictoryReason {
     Checkmate,
     Flag,
     Resignation,
+    Forfeit,  // see `DisconnectPolicy::Forfeit`
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
@@ -484,6 +488,7 @@ pub enum TurnError {
     DropBlocked,
     DropAggression,
     NoGameInProgress,
+    GamePaused,
     GameOver,
 }
 
@@ -908,6 +913,125 @@ impl Board {
         Ok(TurnOutcome{ new_grid, castling_relocations, capture })
     }
 
+    // Returns the cheapest piece that would allow the active player to win by dropping it, if
+    // they had one more such piece in reserve: either by delivering a bughouse mate right away,
+    // or by a checking drop after which every reply allows a mating drop from the rest of the
+    // reserve. Returns `None` if the player can already do this with their current reserve or
+    // if no single piece helps.
+    //
+    // Improvement potential. Also detect decisive threats that win material rather than mate
+    //   (e.g. a drop that wins the queen with a fork).
+    pub fn drop_mate_piece_needed(&self) -> Option<PieceKind> {
+        if self.status != ChessGameStatus::Active {
+            return None;
+        }
+        let bughouse_rules = self.bughouse_rules.as_ref()?;
+        if bughouse_rules.drop_aggression != DropAggression::MateAllowed {
+            return None;
+        }
+        let force = self.active_force;
+        let wins_with = |board: &Board, kind: PieceKind| {
+            board.can_drop_mate(kind) || board.can_force_drop_mate(kind)
+        };
+        if DROPPABLE_PIECES.iter().any(|&kind| self.reserves[force][kind] > 0 && wins_with(self, kind)) {
+            return None;
+        }
+        let mut board = self.clone();
+        for kind in DROPPABLE_PIECES {
+            if self.reserves[force][kind] > 0 {
+                continue;
+            }
+            board.reserves[force][kind] += 1;
+            let found = wins_with(&board, kind);
+            board.reserves[force][kind] -= 1;
+            if found {
+                return Some(kind);
+            }
+        }
+        None
+    }
+
+    // Whether the active player can deliver a bughouse mate by dropping `piece_kind`.
+    fn can_drop_mate(&self, piece_kind: PieceKind) -> bool {
+        let force = self.active_force;
+        for to in Coord::all() {
+            let drop = Turn::Drop(TurnDrop{ piece_kind, to });
+            if let Ok(mut outcome) = self.turn_outcome(drop, TurnMode::Normal) {
+                let opponent_king_pos = find_king(&outcome.new_grid, force.opponent()).unwrap();
+                if is_bughouse_mate_to(&mut outcome.new_grid, opponent_king_pos, None) {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    // Whether the active player can drop `piece_kind` with a check that forces a mate: every
+    // reply allows a mating drop from the remaining reserve. Like `is_bughouse_mate_to`, assumes
+    // that the opponent has a piece to block the check with, if there is a square for it.
+    fn can_force_drop_mate(&self, piece_kind: PieceKind) -> bool {
+        let force = self.active_force;
+        let opponent = force.opponent();
+        let mut board = self.clone();
+        board.reserves[force][piece_kind] -= 1;
+        'drops: for to in Coord::all() {
+            let drop = Turn::Drop(TurnDrop{ piece_kind, to });
+            let Ok(outcome) = self.turn_outcome(drop, TurnMode::Normal) else {
+                continue;
+            };
+            let mut grid = outcome.new_grid;
+            let king_pos = find_king(&grid, opponent).unwrap();
+            if !is_check_to(&grid, king_pos) {
+                continue;
+            }
+            for pos in Coord::all() {
+                if grid[pos].is_none() {
+                    let grid = grid.scoped_set(pos, Some(PieceOnBoard::new(
+                        PieceKind::Queen, PieceOrigin::Dropped, opponent
+                    )));
+                    if !is_check_to(&grid, king_pos) {
+                        continue 'drops;
+                    }
+                }
+            }
+            let mut has_replies = false;
+            for from in Coord::all() {
+                let Some(piece) = grid[from] else {
+                    continue;
+                };
+                if piece.force != opponent {
+                    continue;
+                }
+                for mv in generate_moves_for_mate_test(&grid, from, None) {
+                    let mut new_grid = grid.clone();
+                    if let Some(capture_pos) = get_capture(&grid, mv.from, mv.to, None) {
+                        new_grid[capture_pos] = None;
+                    }
+                    new_grid[mv.from] = None;
+                    new_grid[mv.to] = Some(piece);
+                    let new_king_pos = if piece.kind == PieceKind::King { mv.to } else { king_pos };
+                    if is_check_to(&new_grid, new_king_pos) {
+                        continue;
+                    }
+                    has_replies = true;
+                    board.en_passant_target = get_en_passant_target(&new_grid, Turn::Move(mv));
+                    board.grid = new_grid;
+                    let can_mate = DROPPABLE_PIECES.iter().any(|&kind| {
+                        board.reserves[force][kind] > 0 && board.can_drop_mate(kind)
+                    });
+                    if !can_mate {
+                        continue 'drops;
+                    }
+                }
+            }
+            // No replies means the drop is a mate already, which is handled by `can_drop_mate`.
+            if has_replies {
+                return true;
+            }
+        }
+        false
+    }
+
     pub fn receive_capture(&mut self, capture: &Capture) {
         self.reserves[capture.force][capture.piece_kind] += 1;
     }
@@ -87,6 +87,15 @@ impl Chalkboard {
     pub fn set_drawing(&mut self, player: String, drawing: ChalkDrawing) {
         self.player_drawings.insert(player, drawing);
     }
+
+    // Returns a chalkboard containing only drawings by players matching the predicate.
+    pub fn filtered(&self, mut include_player: impl FnMut(&str) -> bool) -> Self {
+        let player_drawings = self.player_drawings.iter()
+            .filter(|(player, _)| include_player(player))
+            .map(|(player, drawing)| (player.clone(), drawing.clone()))
+            .collect();
+        Chalkboard{ player_drawings }
+    }
 }
 
 #[derive(Debug)]
@@ -0,0 +1,38 @@
+use serde::{Serialize, Deserialize};
+
+
+pub const MAX_CHAT_MESSAGE_LENGTH: usize = 300;  // in characters
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum ChatChannel {
+    // Everybody in the contest, including spectators.
+    All,
+    // Sender's team: the team in the current game or, if the sender doesn't participate
+    // in it, the fixed team.
+    Team,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub sender: String,
+    pub channel: ChatChannel,
+    pub text: String,
+}
+
+// Server-side chat history entry.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ChatRecord {
+    pub message: ChatMessage,
+    // Names of people who can see the message, including the sender. `None` means
+    // everybody: this includes people who join the contest later.
+    pub recipients: Option<Vec<String>>,
+}
+
+impl ChatRecord {
+    pub fn visible_to(&self, name: &str) -> bool {
+        match &self.recipients {
+            None => true,
+            Some(recipients) => recipients.iter().any(|r| r == name),
+        }
+    }
+}
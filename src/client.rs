@@ -7,17 +7,21 @@ use instant::Instant;
 use crate::altered_game::AlteredGame;
 use crate::board::{TurnError, TurnMode, TurnInput};
 use crate::chalk::{Chalkboard, ChalkCanvas, ChalkDrawing, ChalkMark};
+use crate::chat::{ChatChannel, ChatMessage};
 use crate::clock::{GameInstant, WallGameTimePair};
 use crate::display::{DisplayBoard, get_board_index};
 use crate::force::Force;
-use crate::game::{TurnRecord, BughouseParticipantId, BughouseObserserId, PlayerRelation, BughouseBoard, BughouseGameStatus, BughouseGame};
-use crate::event::{BughouseServerEvent, BughouseClientEvent, BughouseClientPerformance};
+use crate::game::{TurnRecord, BughouseParticipantId, BughouseObserserId, PlayerRelation, BughouseBoard, BughouseGameStatus, BughouseGame, PlayerInGame};
+use crate::event::{BughouseServerEvent, BughouseClientEvent, BughouseClientPerformance, JoinMode};
 use crate::heartbeat::{Heart, HeartbeatOutcome};
 use crate::meter::{Meter, MeterBox, MeterStats};
+use crate::partner_message::{PartnerMessage, PartnerMessageRecord};
+use crate::persistence::FinishedGameRecord;
 use crate::pgn::BughouseExportFormat;
-use crate::player::{Player, Team};
+use crate::piece::PieceKind;
+use crate::player::{Player, PlayerPresence, Team};
 use crate::rules::{ChessRules, BughouseRules};
-use crate::scores::Scores;
+use crate::scores::{ContestResult, Scores};
 
 
 #[derive(Clone, Copy, Debug)]
@@ -31,12 +35,18 @@ pub enum SubjectiveGameResult {
 pub enum NotableEvent {
     ContestStarted(String),  // contains ContestID
     GameStarted,
+    GamePaused,
+    GameResumed,
     GameOver(SubjectiveGameResult),
+    ContestOver,
     MyTurnMade,
     OpponentTurnMade,
     MyReserveRestocked,
     LowTime,
     GameExportReady(String),
+    PartnerNeedsPiece(PieceKind),  // see `BughouseRules::partner_hints`
+    PartnerMessageReceived(PartnerMessage),
+    Kicked{ banned: bool },
 }
 
 // TODO: Does it make sense to have CannotApplyEvent instead of panic? Both can be caused by many
@@ -49,6 +59,8 @@ pub enum EventError {
 
 #[derive(Debug)]
 pub struct GameState {
+    // Used to tag game-scoped events sent to the server.
+    pub game_id: usize,
     // Game state including unconfirmed local changes.
     pub alt_game: AlteredGame,
     // Game start time: `None` before first move, non-`None` afterwards.
@@ -60,10 +72,16 @@ pub struct GameState {
     pub chalkboard: Chalkboard,
     // Canvas for the current client to draw on.
     pub chalk_canvas: ChalkCanvas,
+    // Messages sent by this client and their partner, in chronological order.
+    pub partner_messages: Vec<PartnerMessageRecord>,
     // Index of the next warning in `LOW_TIME_WARNING_THRESHOLDS`.
     next_low_time_warning_idx: usize,
     // Used to track how long it took the server to confirm a turn.
     awaiting_turn_confirmation_since: Option<Instant>,
+    // Piece that would allow the partner to deliver or force a drop mate right now (see
+    // `Board::drop_mate_piece_needed`). Always `None` unless `BughouseRules::partner_hints`
+    // is enabled.
+    partner_hint: Option<PieceKind>,
 }
 
 #[derive(Debug)]
@@ -76,8 +94,24 @@ pub struct Contest {
     pub bughouse_rules: BughouseRules,
     // All players including those not participating in the current game.
     pub players: Vec<Player>,
+    // Clients watching the contest without participating. Sorted.
+    pub spectators: Vec<String>,
+    // Whether this client joined as a spectator.
+    pub is_spectator: bool,
+    // Whether this is a finished game shown by `show_game_record` rather than a live contest.
+    pub is_replay: bool,
+    // Player who can kick other players and transfer ownership.
+    pub owner: Option<String>,
+    // Seating for the next game if it is known in advance.
+    pub upcoming_seating: Option<Vec<PlayerInGame>>,
+    // Individual mode: players in the order they are scheduled to play.
+    pub next_up: Vec<String>,
     // Scores from the past matches.
     pub scores: Scores,
+    // Chat messages visible to this client, in chronological order.
+    pub chat: Vec<ChatMessage>,
+    // Final result if the match is over. No new games will be started.
+    pub result: Option<ContestResult>,
     // Whether this client is ready to start a new game.
     pub is_ready: bool,
     // Active game or latest game
@@ -93,6 +127,7 @@ enum ContestState {
     Joining {
         contest_id: String,
         my_name: String,
+        mode: JoinMode,
     },
     Connected(Contest),
 }
@@ -199,8 +234,28 @@ impl ClientState {
         my_player_id.relation_to(other_player_id)
     }
 
+    pub fn partner_hint(&self) -> Option<PieceKind> { self.game_state().and_then(|s| s.partner_hint) }
+    pub fn is_game_paused(&self) -> bool {
+        matches!(self.game_state().and_then(|s| s.time_pair), Some(pair) if pair.is_paused())
+    }
+
     pub fn chalk_canvas(&self) -> Option<&ChalkCanvas> { self.game_state().map(|s| &s.chalk_canvas) }
     pub fn chalk_canvas_mut(&mut self) -> Option<&mut ChalkCanvas> { self.game_state_mut().map(|s| &mut s.chalk_canvas) }
+    // Chalk is always allowed after the game is over. During the game it requires
+    // `BughouseRules::live_chalk` and is available only to the players.
+    pub fn can_draw_chalk(&self) -> bool {
+        let Some(contest) = self.contest() else {
+            return false;
+        };
+        let Some(ref game_state) = contest.game_state else {
+            return false;
+        };
+        if game_state.alt_game.status() != BughouseGameStatus::Active {
+            return true;
+        }
+        contest.bughouse_rules.live_chalk
+            && matches!(game_state.alt_game.my_id(), BughouseParticipantId::Player(_))
+    }
 
     pub fn meter(&mut self, name: String) -> Meter { self.meter_box.meter(name) }
     pub fn read_meter_stats(&self) -> HashMap<String, MeterStats> { self.meter_box.read_stats() }
@@ -217,11 +272,18 @@ impl ClientState {
         self.contest_state = ContestState::Creating{ my_name };
     }
     pub fn join(&mut self, contest_id: String, my_name: String) {
+        self.join_with_mode(contest_id, my_name, JoinMode::Player);
+    }
+    pub fn spectate(&mut self, contest_id: String, my_name: String) {
+        self.join_with_mode(contest_id, my_name, JoinMode::Spectator);
+    }
+    fn join_with_mode(&mut self, contest_id: String, my_name: String, mode: JoinMode) {
         self.connection.send(BughouseClientEvent::Join {
             contest_id: contest_id.clone(),
             player_name: my_name.clone(),
+            mode,
         });
-        self.contest_state = ContestState::Joining{ contest_id, my_name };
+        self.contest_state = ContestState::Joining{ contest_id, my_name, mode };
     }
     pub fn set_team(&mut self, team: Team) {
         if let Some(contest) = self.contest_mut() {
@@ -230,7 +292,10 @@ impl ClientState {
         }
     }
     pub fn resign(&mut self) {
-        self.connection.send(BughouseClientEvent::Resign);
+        if let Some(game_state) = self.game_state() {
+            let game_id = game_state.game_id;
+            self.connection.send(BughouseClientEvent::Resign{ game_id });
+        }
     }
     pub fn set_ready(&mut self, is_ready: bool) {
         if let Some(contest) = self.contest_mut() {
@@ -249,33 +314,59 @@ impl ClientState {
             stats,
         }));
     }
+    pub fn send_partner_message(&mut self, message: PartnerMessage) {
+        if let Some(game_state) = self.game_state() {
+            let game_id = game_state.game_id;
+            self.connection.send(BughouseClientEvent::SendPartnerMessage{ game_id, message });
+        }
+    }
+    pub fn send_chat_message(&mut self, channel: ChatChannel, text: String) {
+        self.connection.send(BughouseClientEvent::SendChatMessage{ channel, text });
+    }
     pub fn request_export(&mut self, format: BughouseExportFormat) {
         self.connection.send(BughouseClientEvent::RequestExport{ format });
     }
+    pub fn kick_player(&mut self, player_name: String, ban: bool) {
+        self.connection.send(BughouseClientEvent::KickPlayer{ player_name, ban });
+    }
+    pub fn transfer_ownership(&mut self, player_name: String) {
+        self.connection.send(BughouseClientEvent::TransferOwnership{ player_name });
+    }
+    pub fn vote_kick(&mut self, player_name: String) {
+        self.connection.send(BughouseClientEvent::VoteKick{ player_name });
+    }
 
     pub fn refresh(&mut self) {
+        if matches!(self.contest(), Some(c) if c.is_replay) {
+            return;
+        }
         self.check_connection();
         self.update_low_time_warnings(true);
     }
 
     pub fn make_turn(&mut self, turn_input: TurnInput) -> Result<(), TurnError> {
         let game_state = self.game_state_mut().ok_or(TurnError::NoGameInProgress)?;
+        let game_id = game_state.game_id;
         let GameState{ ref mut alt_game, time_pair, ref mut awaiting_turn_confirmation_since, .. } = game_state;
+        if matches!(time_pair, Some(pair) if pair.is_paused()) {
+            return Err(TurnError::GamePaused);
+        }
         let now = Instant::now();
         let game_now = GameInstant::from_pair_game_maybe_active(*time_pair, now);
         let mode = alt_game.try_local_turn(turn_input.clone(), game_now)?;
         if mode == TurnMode::Normal {
             *awaiting_turn_confirmation_since = Some(now);
         }
-        self.connection.send(BughouseClientEvent::MakeTurn{ turn_input });
+        self.connection.send(BughouseClientEvent::MakeTurn{ game_id, turn_input });
         self.notable_event_queue.push_back(NotableEvent::MyTurnMade);
         Ok(())
     }
 
     pub fn cancel_preturn(&mut self) {
-        if let Some(alt_game) = self.alt_game_mut() {
-            if alt_game.cancel_preturn() {
-                self.connection.send(BughouseClientEvent::CancelPreturn);
+        if let Some(game_state) = self.game_state_mut() {
+            if game_state.alt_game.cancel_preturn() {
+                let game_id = game_state.game_id;
+                self.connection.send(BughouseClientEvent::CancelPreturn{ game_id });
             }
         }
     }
@@ -299,6 +390,78 @@ impl ClientState {
         );
     }
 
+    // Shows a finished game as it was at `time`, the way a spectator would have seen it.
+    // There is no server behind a replay: the client does not send events or check the
+    // connection. Can be called repeatedly to move back and forth in time.
+    pub fn show_game_record(&mut self, record: &FinishedGameRecord, time: GameInstant)
+        -> Result<(), EventError>
+    {
+        let snapshot = &record.game;
+        let elapsed = time.elapsed_since_start();
+        let turn_log = snapshot.turn_log.iter()
+            .filter(|t| t.time.elapsed_since_start() <= elapsed)
+            .cloned()
+            .collect::<Vec<_>>();
+        let game_over = turn_log.len() == snapshot.turn_log.len()
+            && elapsed >= snapshot.time.elapsed_since_start();
+        let (time, game_status) = if game_over {
+            (snapshot.time, snapshot.status)
+        } else {
+            (time, BughouseGameStatus::Active)
+        };
+        // Clocks start with the first turn.
+        let time = if turn_log.is_empty() { GameInstant::game_start() } else { time };
+        let partner_messages = snapshot.partner_messages.iter()
+            .filter(|r| r.turn_idx <= turn_log.len())
+            .cloned()
+            .collect();
+        let players = snapshot.players.iter().map(|p| Player {
+            name: p.name.clone(),
+            fixed_team: None,
+            presence: PlayerPresence::Online,
+            is_ready: false,
+        }).collect();
+        let contest_id = String::new();
+        self.contest_state = ContestState::Joining {
+            contest_id: contest_id.clone(),
+            my_name: String::new(),
+            mode: JoinMode::Spectator,
+        };
+        for event in [
+            BughouseServerEvent::ContestWelcome {
+                contest_id,
+                chess_rules: record.chess_rules.clone(),
+                bughouse_rules: record.bughouse_rules.clone(),
+            },
+            BughouseServerEvent::LobbyUpdated {
+                players,
+                spectators: vec![],
+                owner: None,
+                upcoming_seating: None,
+                next_up: vec![],
+            },
+            BughouseServerEvent::GameStarted {
+                game_id: 1,
+                starting_position: snapshot.starting_position.clone(),
+                players: snapshot.players.clone(),
+                time,
+                turn_log,
+                preturn: None,
+                game_status,
+                game_paused: true,
+                partner_messages,
+                scores: Scores::new(),
+            },
+        ] {
+            self.process_server_event(event)?;
+        }
+        // Safe to unwrap: `ContestWelcome` has just connected us.
+        self.contest_mut().unwrap().is_replay = true;
+        // Events like sounds and low time warnings are meant for live games.
+        self.notable_event_queue.clear();
+        Ok(())
+    }
+
     // Improvement potential: Split into functions like `CoreServerState.on_client_event` does.
     pub fn process_server_event(&mut self, event: BughouseServerEvent) -> Result<(), EventError> {
         use BughouseServerEvent::*;
@@ -309,15 +472,15 @@ impl ClientState {
                 return Err(EventError::ServerReturnedError(format!("Got error from server: {}", message)))
             },
             ContestWelcome{ contest_id, chess_rules, bughouse_rules } => {
-                let my_name = match &self.contest_state {
+                let (my_name, is_spectator) = match &self.contest_state {
                     ContestState::Creating{ my_name } => {
-                        my_name.clone()
+                        (my_name.clone(), false)
                     }
-                    ContestState::Joining{ contest_id: id, my_name } => {
+                    ContestState::Joining{ contest_id: id, my_name, mode } => {
                         if contest_id != *id {
                             return Err(cannot_apply_event!("Cannot apply ContestWelcome: expected contest {id}, but got {contest_id}"));
                         }
-                        my_name.clone()
+                        (my_name.clone(), *mode == JoinMode::Spectator)
                     },
                     _ => return Err(cannot_apply_event!("Cannot apply ContestWelcome: not expecting a new contest")),
                 };
@@ -329,27 +492,42 @@ impl ClientState {
                     chess_rules,
                     bughouse_rules,
                     players: Vec::new(),
+                    spectators: Vec::new(),
+                    is_spectator,
+                    is_replay: false,
+                    owner: None,
+                    upcoming_seating: None,
+                    next_up: Vec::new(),
                     scores: Scores::new(),
+                    chat: Vec::new(),
+                    result: None,
                     is_ready: false,
                     game_state: None,
                 });
             },
-            LobbyUpdated{ players } => {
+            LobbyUpdated{ players, spectators, owner, upcoming_seating, next_up } => {
                 let contest = self.contest_mut().ok_or_else(|| cannot_apply_event!("Cannot apply LobbyUpdated: no contest in progress"))?;
                 // TODO: Fix race condition: is_ready will toggle back and forth if a lobby update
                 //   (e.g. is_ready from another player) arrived before is_ready update from this
                 //   client reached the server. Same for `my_team`.
-                let me = players.iter().find(|p| p.name == contest.my_name).unwrap();
-                contest.is_ready = me.is_ready;
-                contest.my_team = me.fixed_team;
+                if !contest.is_spectator {
+                    let me = players.iter().find(|p| p.name == contest.my_name).unwrap();
+                    contest.is_ready = me.is_ready;
+                    contest.my_team = me.fixed_team;
+                }
                 contest.players = players;
+                contest.spectators = spectators;
+                contest.owner = owner;
+                contest.upcoming_seating = upcoming_seating;
+                contest.next_up = next_up;
             },
-            GameStarted{ starting_position, players, time, turn_log, preturn, game_status, scores } => {
+            GameStarted{ game_id, starting_position, players, time, turn_log, preturn, game_status, game_paused, partner_messages, scores } => {
                 let time_pair = if turn_log.is_empty() {
                     assert!(time.elapsed_since_start().is_zero());
                     None
                 } else {
-                    Some(WallGameTimePair::new(now, time.approximate()))
+                    let pair = WallGameTimePair::new(now, time.approximate());
+                    Some(if game_paused { pair.paused(now) } else { pair })
                 };
                 let contest = self.contest_mut().ok_or_else(|| cannot_apply_event!("Cannot apply GameStarted: no contest in progress"))?;
                 let game = BughouseGame::new_with_starting_position(
@@ -365,12 +543,15 @@ impl ClientState {
                 let alt_game = AlteredGame::new(my_id, game);
                 let perspective = alt_game.perspective();
                 contest.game_state = Some(GameState {
+                    game_id,
                     alt_game,
                     time_pair,
                     chalkboard: Chalkboard::new(),
                     chalk_canvas: ChalkCanvas::new(perspective),
+                    partner_messages,
                     next_low_time_warning_idx: 0,
                     awaiting_turn_confirmation_since: None,
+                    partner_hint: None,
                 });
                 for turn in turn_log {
                     self.apply_remote_turn(turn, false)?;
@@ -388,11 +569,13 @@ impl ClientState {
                 self.update_scores(scores)?;
                 self.notable_event_queue.push_back(NotableEvent::GameStarted);
                 self.update_low_time_warnings(false);
+                self.update_partner_hint(false);
             },
             TurnsMade{ turns, game_status, scores } => {
                 for turn in turns {
                     self.apply_remote_turn(turn, true)?;
                 }
+                self.update_partner_hint(true);
                 self.verify_game_status(game_status)?;
                 self.update_scores(scores)?;
                 if game_status != BughouseGameStatus::Active {
@@ -407,14 +590,62 @@ impl ClientState {
                 contest.scores = new_scores;
                 self.game_over_postprocess()?;
             },
+            GamePaused{ time } => {
+                let contest = self.contest_mut().ok_or_else(|| cannot_apply_event!("Cannot apply GamePaused: no contest in progress"))?;
+                let game_state = contest.game_state.as_mut().ok_or_else(|| cannot_apply_event!("Cannot apply GamePaused: no game in progress"))?;
+                game_state.time_pair = Some(WallGameTimePair::new(now, time.approximate()).paused(now));
+                self.notable_event_queue.push_back(NotableEvent::GamePaused);
+            },
+            GameResumed{ time } => {
+                let contest = self.contest_mut().ok_or_else(|| cannot_apply_event!("Cannot apply GameResumed: no contest in progress"))?;
+                let game_state = contest.game_state.as_mut().ok_or_else(|| cannot_apply_event!("Cannot apply GameResumed: no game in progress"))?;
+                game_state.time_pair = Some(WallGameTimePair::new(now, time.approximate()));
+                self.notable_event_queue.push_back(NotableEvent::GameResumed);
+            },
+            ContestOver{ result } => {
+                let contest = self.contest_mut().ok_or_else(|| cannot_apply_event!("Cannot apply ContestOver: no contest in progress"))?;
+                contest.scores = result.scores.clone();
+                contest.result = Some(result);
+                self.notable_event_queue.push_back(NotableEvent::ContestOver);
+            },
             ChalkboardUpdated{ chalkboard } => {
                 let contest = self.contest_mut().ok_or_else(|| cannot_apply_event!("Cannot apply ChalkboardUpdated: no contest in progress"))?;
                 let game_state = contest.game_state.as_mut().ok_or_else(|| cannot_apply_event!("Cannot apply ChalkboardUpdated: no game in progress"))?;
                 game_state.chalkboard = chalkboard;
             },
+            PartnerMessageReceived{ record } => {
+                let contest = self.contest_mut().ok_or_else(|| cannot_apply_event!("Cannot apply PartnerMessageReceived: no contest in progress"))?;
+                let game_state = contest.game_state.as_mut().ok_or_else(|| cannot_apply_event!("Cannot apply PartnerMessageReceived: no game in progress"))?;
+                game_state.partner_messages.push(record);
+                if BughouseParticipantId::Player(record.player_id) != game_state.alt_game.my_id() {
+                    self.notable_event_queue.push_back(NotableEvent::PartnerMessageReceived(record.message));
+                }
+            },
+            PlayerPresenceChanged{ player_name, presence } => {
+                let contest = self.contest_mut().ok_or_else(|| cannot_apply_event!("Cannot apply PlayerPresenceChanged: no contest in progress"))?;
+                let player = contest.players.iter_mut().find(|p| p.name == player_name).ok_or_else(
+                    || cannot_apply_event!("Cannot apply PlayerPresenceChanged: unknown player {}", player_name)
+                )?;
+                player.presence = presence;
+                if presence == PlayerPresence::Offline {
+                    player.is_ready = false;
+                }
+            },
+            ChatMessageReceived{ message } => {
+                let contest = self.contest_mut().ok_or_else(|| cannot_apply_event!("Cannot apply ChatMessageReceived: no contest in progress"))?;
+                contest.chat.push(message);
+            },
+            ChatHistory{ messages } => {
+                let contest = self.contest_mut().ok_or_else(|| cannot_apply_event!("Cannot apply ChatHistory: no contest in progress"))?;
+                contest.chat = messages;
+            },
             GameExportReady{ content } => {
                 self.notable_event_queue.push_back(NotableEvent::GameExportReady(content));
             },
+            Kicked{ banned } => {
+                self.contest_state = ContestState::NotConnected;
+                self.notable_event_queue.push_back(NotableEvent::Kicked{ banned });
+            },
             Heartbeat => {
                 // This event is needed only for `heart.register_incoming` above.
             }
@@ -474,6 +705,35 @@ impl ClientState {
         Ok(())
     }
 
+    fn update_partner_hint(&mut self, generate_notable_events: bool) {
+        let Some(contest) = self.contest_mut() else {
+            return;
+        };
+        if !contest.bughouse_rules.partner_hints {
+            return;
+        }
+        let Some(ref mut game_state) = contest.game_state else {
+            return;
+        };
+        let BughouseParticipantId::Player(my_player_id) = game_state.alt_game.my_id() else {
+            return;
+        };
+        let game = game_state.alt_game.game_confirmed();
+        let partner_id = my_player_id.partner();
+        let partner_board = game.board(partner_id.board_idx);
+        let new_hint = if game.status() == BughouseGameStatus::Active && partner_board.active_force() == partner_id.force {
+            partner_board.drop_mate_piece_needed()
+        } else {
+            None
+        };
+        let old_hint = std::mem::replace(&mut game_state.partner_hint, new_hint);
+        if let Some(piece_kind) = new_hint {
+            if generate_notable_events && new_hint != old_hint {
+                self.notable_event_queue.push_back(NotableEvent::PartnerNeedsPiece(piece_kind));
+            }
+        }
+    }
+
     fn verify_game_status(&mut self, game_status: BughouseGameStatus) -> Result<(), EventError> {
         let contest = self.contest_mut().ok_or_else(|| cannot_apply_event!("Cannot verify game status: no contest in progress"))?;
         let game_state = contest.game_state.as_mut().ok_or_else(|| cannot_apply_event!("Cannot verify game status: no game in progress"))?;
@@ -538,15 +798,15 @@ impl ClientState {
     where
         F: FnOnce(&mut Chalkboard, String, BughouseBoard)
     {
+        if !self.can_draw_chalk() {
+            return;
+        }
         let Some(contest) = self.contest_mut() else {
             return;
         };
         let Some(ref mut game_state) = contest.game_state else {
             return;
         };
-        if game_state.alt_game.status() == BughouseGameStatus::Active {
-            return;
-        }
         let board_idx = get_board_index(display_board, game_state.alt_game.my_id());
         f(&mut game_state.chalkboard, contest.my_name.clone(), board_idx);
         self.send_chalk_drawing_update();
@@ -556,9 +816,10 @@ impl ClientState {
         // Caller must ensure that contest and game exist.
         let contest = self.contest().unwrap();
         let game_state = contest.game_state.as_ref().unwrap();
+        let game_id = game_state.game_id;
         let drawing = game_state.chalkboard.drawings_by(&contest.my_name).cloned()
             .unwrap_or_else(|| ChalkDrawing::new());
-        self.connection.send(BughouseClientEvent::UpdateChalkDrawing{ drawing })
+        self.connection.send(BughouseClientEvent::UpdateChalkDrawing{ game_id, drawing })
     }
 
     fn check_connection(&mut self) {
@@ -49,6 +49,9 @@ impl GameInstant {
         }
     }
     pub fn from_pair_game_active(pair: WallGameTimePair, now: Instant) -> GameInstant {
+        if pair.paused {
+            return pair.game_t;
+        }
         GameInstant {
             elapsed_since_start: (now - pair.world_t) + pair.game_t.elapsed_since_start,
             measurement: pair.game_t.measurement,
@@ -98,15 +101,32 @@ impl GameInstant {
 // when reconnecting to existing game, but this could panic because Rust doesn't
 // allow for negative durations. So this class can be used to sync game clock and
 // real-world clock instead.
+//
+// The pair can be paused. Game time does not advance while the pair is paused.
 #[derive(Clone, Copy, Debug)]
 pub struct WallGameTimePair {
     world_t: Instant,
     game_t: GameInstant,
+    paused: bool,
 }
 
 impl WallGameTimePair {
     pub fn new(world_t: Instant, game_t: GameInstant) -> Self {
-        WallGameTimePair{ world_t, game_t }
+        WallGameTimePair{ world_t, game_t, paused: false }
+    }
+
+    pub fn is_paused(&self) -> bool { self.paused }
+
+    // Freezes game time at its value at `now`.
+    pub fn paused(self, now: Instant) -> Self {
+        let game_t = GameInstant::from_pair_game_active(self, now);
+        WallGameTimePair{ world_t: now, game_t, paused: true }
+    }
+
+    // Continues game time from the moment it was paused.
+    pub fn resumed(self, now: Instant) -> Self {
+        let game_t = GameInstant::from_pair_game_active(self, now);
+        WallGameTimePair{ world_t: now, game_t, paused: false }
     }
 }
 
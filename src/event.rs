@@ -4,13 +4,15 @@ use serde::{Serialize, Deserialize};
 
 use crate::board::TurnInput;
 use crate::chalk::{ChalkDrawing, Chalkboard};
+use crate::chat::{ChatChannel, ChatMessage};
 use crate::clock::GameInstant;
 use crate::game::{TurnRecord, BughouseGameStatus, PlayerInGame};
 use crate::meter::MeterStats;
+use crate::partner_message::{PartnerMessage, PartnerMessageRecord};
 use crate::pgn::BughouseExportFormat;
-use crate::player::{Player, Team};
+use crate::player::{Player, PlayerPresence, Team};
 use crate::rules::{ChessRules, BughouseRules};
-use crate::scores::Scores;
+use crate::scores::{ContestResult, Scores};
 use crate::starter::EffectiveStartingPosition;
 
 
@@ -26,15 +28,30 @@ pub enum BughouseServerEvent {
     },
     LobbyUpdated {
         players: Vec<Player>,
+        spectators: Vec<String>,
+        owner: Option<String>,  // player name
+        // Seating for the next game if it is known in advance (see `SeatRotation`).
+        upcoming_seating: Option<Vec<PlayerInGame>>,
+        // Individual mode: player names in the order they are scheduled to play; the first
+        // `TOTAL_PLAYERS` play the next game. Empty in fixed teams mode.
+        next_up: Vec<String>,
+    },
+    // Sent whenever player connection state changes. The full state is sent with `LobbyUpdated`.
+    PlayerPresenceChanged {
+        player_name: String,
+        presence: PlayerPresence,
     },
     // Improvement potential: Rename `GameStarted` to take reconnection into account.
     GameStarted {
+        game_id: usize,  // sequential number of the game within the contest, starting from 1
         starting_position: EffectiveStartingPosition,
         players: Vec<PlayerInGame>,
         time: GameInstant,                // for re-connection
         turn_log: Vec<TurnRecord>,        // for re-connection
         preturn: Option<TurnInput>,       // for re-connection
         game_status: BughouseGameStatus,  // for re-connection
+        game_paused: bool,                // for re-connection
+        partner_messages: Vec<PartnerMessageRecord>,  // for re-connection; only from own team
         scores: Scores,
     },
     // Improvement potential: unite `TurnsMade` and `GameOver` into a single event "something happened".
@@ -50,16 +67,55 @@ pub enum BughouseServerEvent {
         game_status: BughouseGameStatus,
         scores: Scores,
     },
+    // Sent when the game is paused because of `DisconnectPolicy::Pause`.
+    GamePaused {
+        time: GameInstant,
+    },
+    // Sent when a paused game continues, e.g. when all players have rejoined a game restored
+    // after server restart.
+    GameResumed {
+        time: GameInstant,
+    },
+    // Sent when the match is over (see `MatchLength`) and on joining a finished contest.
+    // No new games are started afterwards.
+    ContestOver {
+        result: ContestResult,
+    },
     ChalkboardUpdated {
         chalkboard: Chalkboard,
     },
+    // Sent only to the members of the team that sent the message.
+    PartnerMessageReceived {
+        record: PartnerMessageRecord,
+    },
+    // Team messages are sent only to the team.
+    ChatMessageReceived {
+        message: ChatMessage,
+    },
+    // Sent on joining (or re-joining) the contest. Contains only messages visible to the client.
+    ChatHistory {
+        messages: Vec<ChatMessage>,
+    },
     GameExportReady {
         content: String,
     },
+    // Sent to the client of a player who was removed from the contest. The client is no
+    // longer connected to the contest afterwards.
+    Kicked {
+        banned: bool,
+    },
     Heartbeat,
 }
 
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum JoinMode {
+    Player,
+    // Spectators receive all game updates, but cannot participate. They are not counted
+    // as players and are not assigned to boards.
+    Spectator,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct BughouseClientPerformance {
     pub user_agent: String,
@@ -74,11 +130,9 @@ pub enum BughouseClientErrorReport {
     UnknownError{ message: String },
 }
 
-// TODO: Make sure server does not process events like MakeTurn sent during an older game.
-//   This hasn't been spotted in practice so far, but seems possible in theory. Solutions:
-//   - Add game_id tag to relevant events; or
-//   - Implement a barrier: don't start a new game until all clients confirmed that they are
-//     ready; or don't accept events for a new game until the client confirms game start.
+// Game-scoped events are tagged with `game_id` from `BughouseServerEvent::GameStarted`.
+// This way the server does not apply events like `MakeTurn` sent during an older game
+// to the new one.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum BughouseClientEvent {
     NewContest {
@@ -89,26 +143,85 @@ pub enum BughouseClientEvent {
     Join {
         contest_id: String,
         player_name: String,
+        mode: JoinMode,
     },
     SetTeam {
         team: Team,
     },
     MakeTurn {
+        game_id: usize,
         turn_input: TurnInput,
     },
-    CancelPreturn,
-    Resign,
+    CancelPreturn {
+        game_id: usize,
+    },
+    Resign {
+        game_id: usize,
+    },
     SetReady {
         is_ready: bool,
     },
     Leave,
     UpdateChalkDrawing {
+        game_id: usize,
         drawing: ChalkDrawing,
     },
+    SendPartnerMessage {
+        game_id: usize,
+        message: PartnerMessage,
+    },
     RequestExport {
         format: BughouseExportFormat,
     },
+    SendChatMessage {
+        channel: ChatChannel,
+        text: String,
+    },
+    // Contest owner only.
+    KickPlayer {
+        player_name: String,
+        ban: bool,  // whether to prevent the name from re-joining the contest
+    },
+    // Contest owner only.
+    TransferOwnership {
+        player_name: String,
+    },
+    // Allowed for any player when the target has been offline long enough.
+    VoteKick {
+        player_name: String,
+    },
     ReportPerformace(BughouseClientPerformance),
+    // Sent by clients predating `ReportErrorWithContext`. Kept so that stale clients could
+    // still report errors.
     ReportError(BughouseClientErrorReport),
+    ReportErrorWithContext{ report: BughouseClientErrorReport, git_version: String, user_agent: String },
     Heartbeat,
 }
+
+impl BughouseClientEvent {
+    // Returns the game the event refers to, if the event is game-scoped.
+    pub fn game_id(&self) -> Option<usize> {
+        use BughouseClientEvent::*;
+        match self {
+            MakeTurn{ game_id, .. } |
+            CancelPreturn{ game_id } |
+            Resign{ game_id } |
+            UpdateChalkDrawing{ game_id, .. } |
+            SendPartnerMessage{ game_id, .. } => Some(*game_id),
+            NewContest{ .. } |
+            Join{ .. } |
+            SetTeam{ .. } |
+            SetReady{ .. } |
+            Leave |
+            RequestExport{ .. } |
+            SendChatMessage{ .. } |
+            KickPlayer{ .. } |
+            TransferOwnership{ .. } |
+            VoteKick{ .. } |
+            ReportPerformace(..) |
+            ReportError(..) |
+            ReportErrorWithContext{ .. } |
+            Heartbeat => None,
+        }
+    }
+}
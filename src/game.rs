@@ -10,12 +10,17 @@ use strum::{EnumIter, IntoEnumIterator};
 use crate::board::{Board, Reserve, Turn, TurnInput, TurnExpanded, TurnFacts, TurnMode, TurnError, ChessGameStatus, VictoryReason, DrawReason};
 use crate::clock::GameInstant;
 use crate::force::Force;
+use crate::partner_message::{PartnerMessage, PartnerMessageRecord};
 use crate::piece::piece_to_pictogram;
 use crate::player::Team;
 use crate::rules::{ChessRules, BughouseRules};
 use crate::starter::{EffectiveStartingPosition, generate_starting_position};
 
 
+pub const TOTAL_PLAYERS: usize = 4;
+pub const TOTAL_PLAYERS_PER_TEAM: usize = 2;
+
+
 // Stripped version of `TurnRecordExpanded`. For sending turns across network.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TurnRecord {
@@ -197,6 +202,13 @@ impl BughousePlayerId {
             force: self.force.opponent(),
         }
     }
+    pub fn partner(self) -> Self {
+        let board_idx = self.board_idx.other();
+        BughousePlayerId {
+            board_idx,
+            force: get_bughouse_force(self.team(), board_idx),
+        }
+    }
 
     pub fn relation_to(self, other_player: BughousePlayerId) -> PlayerRelation {
         let same_board = self.board_idx == other_player.board_idx;
@@ -235,6 +247,7 @@ pub struct BughouseGame {
     starting_position: EffectiveStartingPosition,
     boards: EnumMap<BughouseBoard, Board>,
     turn_log: Vec<TurnRecordExpanded>,
+    partner_messages: Vec<PartnerMessageRecord>,
     status: BughouseGameStatus,
 }
 
@@ -268,6 +281,7 @@ impl BughouseGame {
             boards,
             status: BughouseGameStatus::Active,
             turn_log: Vec::new(),
+            partner_messages: Vec::new(),
         }
     }
 
@@ -283,6 +297,7 @@ impl BughouseGame {
     }
     pub fn turn_log(&self) -> &Vec<TurnRecordExpanded> { &self.turn_log }
     pub fn last_turn_record(&self) -> Option<&TurnRecordExpanded> { self.turn_log.last() }
+    pub fn partner_messages(&self) -> &Vec<PartnerMessageRecord> { &self.partner_messages }
     pub fn status(&self) -> BughouseGameStatus { self.status }
 
     pub fn players(&self) -> Vec<PlayerInGame> {
@@ -399,6 +414,14 @@ impl BughouseGame {
         self.try_turn(player_id.board_idx, turn_input, mode, now)
     }
 
+    pub fn add_partner_message(&mut self, player_id: BughousePlayerId, message: PartnerMessage)
+        -> PartnerMessageRecord
+    {
+        let record = PartnerMessageRecord{ player_id, message, turn_idx: self.turn_log.len() };
+        self.partner_messages.push(record);
+        record
+    }
+
     pub fn outcome(&self) -> String {
         use BughouseGameStatus::*;
         use VictoryReason::*;
@@ -414,6 +437,7 @@ impl BughouseGame {
             Victory(team, Checkmate) => format!("{} won by checkmate", make_team_string(team)),
             Victory(team, Flag) => format!("{} won by flag", make_team_string(team)),
             Victory(team, Resignation) => format!("{} won by resignation", make_team_string(team)),
+            Victory(team, Forfeit) => format!("{} won by forfeit", make_team_string(team)),
             Draw(SimultaneousFlag) => "Draw by simultaneous flags".to_owned(),
             Draw(ThreefoldRepetition) => "Draw by threefold repetition".to_owned(),
         }
@@ -13,6 +13,7 @@
 mod altered_game;
 mod board;
 mod chalk;
+mod chat;
 mod clock;
 mod coord;
 mod display;
@@ -20,6 +21,7 @@ mod event;
 mod force;
 mod game;
 mod grid;
+mod partner_message;
 mod piece;
 mod player;
 mod rules;
@@ -32,14 +34,18 @@ pub mod janitor;
 pub mod meter;
 pub mod persistence;
 pub mod pgn;
+pub mod rating;
 pub mod server;
 pub mod server_hooks;
+#[cfg(feature = "sqlx")]
+pub mod sqlx_migrations;
 pub mod test_util;
 pub mod util;
 
 pub use altered_game::*;
 pub use board::*;
 pub use chalk::*;
+pub use chat::*;
 pub use clock::*;
 pub use coord::*;
 pub use display::*;
@@ -47,6 +53,7 @@ pub use event::*;
 pub use force::*;
 pub use game::*;
 pub use grid::*;
+pub use partner_message::*;
 pub use piece::*;
 pub use player::*;
 pub use rules::*;
@@ -1,10 +1,9 @@
-// Improvement potential. Synchronized version for server-side logging.
-
 use std::cell::RefCell;
 use std::cmp;
 use std::collections::HashMap;
 use std::fmt;
 use std::rc::Rc;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use hdrhistogram::Histogram;
@@ -30,6 +29,15 @@ impl fmt::Display for MeterStats {
     }
 }
 
+// Cumulative histogram, as exported to monitoring systems: `buckets` maps each upper bound to
+// the number of values that do not exceed it. All counts are since the start.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MeterBuckets {
+    pub buckets: Vec<(u64, u64)>,
+    pub sum: u64,
+    pub num_values: u64,
+}
+
 
 pub struct MeterBox {
     meters: HashMap<String, Meter>,
@@ -84,12 +92,113 @@ impl Meter {
         self.histogram.borrow_mut().reset();
     }
     fn stats(&self) -> MeterStats {
-        let histogram = self.histogram.borrow();
-        MeterStats {
-            p50: histogram.value_at_quantile(0.5),
-            p90: histogram.value_at_quantile(0.9),
-            p99: histogram.value_at_quantile(0.99),
-            num_values: histogram.len()
+        histogram_stats(&self.histogram.borrow())
+    }
+}
+
+
+// Thread-safe counterpart of `MeterBox`, used on the server.
+//
+// Each meter keeps two histograms: one since the start, so that monitoring systems can
+// compute rates and windowed quantiles from monotonic bucket counts, and one since the last
+// `consume_recent_stats`, for periodic logging.
+#[derive(Clone, Default)]
+pub struct SyncMeterBox {
+    meters: Arc<Mutex<HashMap<String, SyncMeter>>>,
+}
+
+impl SyncMeterBox {
+    pub fn new() -> Self {
+        SyncMeterBox {
+            meters: Arc::new(Mutex::new(HashMap::new())),
         }
     }
+
+    pub fn meter(&self, name: String) -> SyncMeter {
+        self.meters.lock().unwrap().entry(name).or_insert_with(SyncMeter::new).clone()
+    }
+
+    // Stats since the start.
+    pub fn read_stats(&self) -> HashMap<String, MeterStats> {
+        self.meters.lock().unwrap().iter()
+            .map(|(name, meter)| (name.clone(), histogram_stats(&meter.histograms.lock().unwrap().total)))
+            .collect()
+    }
+    // Bucket counts since the start. Bucket bounds are the same for all meters: 1, 2, 5, 10,
+    // 20, 50 and so on, up to 5·10^8 (which covers durations in microseconds up to 500 seconds).
+    pub fn read_buckets(&self) -> HashMap<String, MeterBuckets> {
+        let bounds = (0..=8).flat_map(|p| [1, 2, 5].map(|m| m * 10_u64.pow(p))).collect::<Vec<_>>();
+        self.meters.lock().unwrap().iter()
+            .map(|(name, meter)| {
+                let histograms = meter.histograms.lock().unwrap();
+                let buckets = bounds.iter()
+                    .map(|&bound| (bound, histograms.total.count_between(0, bound)))
+                    .collect();
+                (name.clone(), MeterBuckets {
+                    buckets,
+                    sum: histograms.total_sum,
+                    num_values: histograms.total.len(),
+                })
+            })
+            .collect()
+    }
+    // Stats since the previous call.
+    pub fn consume_recent_stats(&self) -> HashMap<String, MeterStats> {
+        self.meters.lock().unwrap().iter()
+            .map(|(name, meter)| {
+                let mut histograms = meter.histograms.lock().unwrap();
+                let stats = histogram_stats(&histograms.recent);
+                histograms.recent.reset();
+                (name.clone(), stats)
+            })
+            .collect()
+    }
+}
+
+struct SyncMeterHistograms {
+    total: Histogram<u64>,
+    total_sum: u64,  // saturating
+    recent: Histogram<u64>,
+}
+
+#[derive(Clone)]
+pub struct SyncMeter {
+    histograms: Arc<Mutex<SyncMeterHistograms>>,
+}
+
+impl SyncMeter {
+    fn new() -> Self {
+        const SIGNIFICANT_DIGITS: u8 = 3;
+        let histogram = || Histogram::new(SIGNIFICANT_DIGITS).unwrap();
+        SyncMeter {
+            histograms: Arc::new(Mutex::new(SyncMeterHistograms {
+                total: histogram(),
+                total_sum: 0,
+                recent: histogram(),
+            })),
+        }
+    }
+
+    pub fn record(&self, value: u64) {
+        let mut histograms = self.histograms.lock().unwrap();
+        histograms.total.record(value).unwrap();
+        histograms.total_sum = histograms.total_sum.saturating_add(value);
+        histograms.recent.record(value).unwrap();
+    }
+    // Server-side events are fast, so durations are recorded in microseconds rather than
+    // in milliseconds like `Meter::record_duration`.
+    pub fn record_duration_micros(&self, duration: Duration) {
+        let value = cmp::min(duration.as_micros(), u64::MAX.into()).try_into().unwrap();
+        self.record(value);
+    }
+}
+
+
+fn histogram_stats(histogram: &Histogram<u64>) -> MeterStats {
+    MeterStats {
+        p50: histogram.value_at_quantile(0.5),
+        p90: histogram.value_at_quantile(0.9),
+        p99: histogram.value_at_quantile(0.99),
+        num_values: histogram.len()
+    }
 }
@@ -0,0 +1,46 @@
+use serde::{Serialize, Deserialize};
+
+use crate::game::BughousePlayerId;
+use crate::piece::PieceKind;
+
+
+// Standard bughouse vocabulary for communicating with partner during the game.
+// Visible only to the team.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum PartnerMessage {
+    Need(PieceKind),
+    DontGive(PieceKind),
+    Sit,
+    Go,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct PartnerMessageRecord {
+    pub player_id: BughousePlayerId,
+    pub message: PartnerMessage,
+    // Number of turns made on both boards before the message was sent.
+    pub turn_idx: usize,
+}
+
+impl PartnerMessage {
+    // Parses messages like "need N", "dontgive Q", "sit", "go".
+    pub fn parse(command: &str, piece: Option<&str>) -> Option<Self> {
+        let piece_kind = piece.and_then(|p| PieceKind::from_algebraic(&p.to_uppercase()));
+        match (command, piece_kind) {
+            ("need", Some(kind)) => Some(PartnerMessage::Need(kind)),
+            ("dontgive", Some(kind)) => Some(PartnerMessage::DontGive(kind)),
+            ("sit", None) if piece.is_none() => Some(PartnerMessage::Sit),
+            ("go", None) if piece.is_none() => Some(PartnerMessage::Go),
+            _ => None,
+        }
+    }
+
+    pub fn to_log_entry(&self) -> String {
+        match self {
+            PartnerMessage::Need(kind) => format!("need {}", kind.to_full_algebraic()),
+            PartnerMessage::DontGive(kind) => format!("don't give {}", kind.to_full_algebraic()),
+            PartnerMessage::Sit => "sit".to_owned(),
+            PartnerMessage::Go => "go".to_owned(),
+        }
+    }
+}
@@ -1,6 +1,418 @@
+use serde::{Serialize, Deserialize};
 use time::OffsetDateTime;
 
-#[derive(Debug)]
+use crate::chalk::Chalkboard;
+use crate::chat::ChatRecord;
+use crate::clock::GameInstant;
+use crate::game::{TurnRecord, BughouseGameStatus, PlayerInGame};
+use crate::partner_message::PartnerMessageRecord;
+use crate::player::Player;
+use crate::rules::{ChessRules, BughouseRules};
+use crate::scores::{ContestResult, Scores};
+use crate::starter::EffectiveStartingPosition;
+
+// Database backends supported by the server hooks and the webserver. SQL is written to be
+// portable where possible; `DatabaseDialect` covers the rest.
+//
+// Portability notes:
+//   - Use "$1", "$2", etc. for query parameters.
+//   - Don't rely on SQLite `rowid`: tables have explicit primary keys.
+//   - Use BIGINT for integers (Rust i64) and TIMESTAMPTZ for timestamps (`OffsetDateTime`):
+//     sqlx refuses to decode INTEGER and TIMESTAMP into these types in Postgres.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DatabaseDialect {
+    Sqlite,
+    Postgres,
+}
+
+impl DatabaseDialect {
+    // Auto-incremented integer primary key.
+    pub fn id_column_type(self) -> &'static str {
+        match self {
+            // An alias for `rowid`, see https://www.sqlite.org/lang_createtable.html#rowid.
+            DatabaseDialect::Sqlite => "INTEGER PRIMARY KEY",
+            DatabaseDialect::Postgres => "BIGSERIAL PRIMARY KEY",
+        }
+    }
+}
+
+// Schema migrations. Both the game server (`bughouse_console`) and `bughouse_webserver` call
+// `apply_migrations` on start, so the database is always up to date regardless of which one
+// started first. Applied versions are recorded in the `schema_migrations` table.
+//
+// Rules:
+//   - Never change a migration after it has been released: add a new one instead.
+//   - Follow the portability notes for `DatabaseDialect`.
+//   - Each migration is applied in a transaction, so it must not contain statements that
+//     cannot run in a transaction.
+pub struct Migration {
+    pub version: i64,
+    pub description: &'static str,
+    pub steps: Vec<MigrationStep>,
+}
+
+pub enum MigrationStep {
+    Sql(String),
+    // Statements to run only if the column does not exist yet. Needed for changes that could
+    // have been made before migrations were introduced: such databases can be in any state.
+    UnlessColumnExists {
+        table: &'static str,
+        column: &'static str,
+        statements: Vec<String>,
+    },
+}
+
+// Database access required to apply migrations. Implemented on top of sqlx in `sqlx_migrations`
+// (only with the "sqlx" feature, because this crate is also compiled to WASM).
+// All statements must be executed on the same connection.
+pub trait MigrationDb {
+    type Error;
+    fn execute(&mut self, sql: &str) -> Result<(), Self::Error>;
+    // Returns true iff the query returns at least one row.
+    fn has_rows(&mut self, sql: &str) -> Result<bool, Self::Error>;
+}
+
+impl DatabaseDialect {
+    // Statements that start a transaction and lock the database against concurrent
+    // migrations until the transaction ends.
+    fn begin_migration_sql(self) -> &'static [&'static str] {
+        match self {
+            // Takes the write lock right away rather than on the first write. Other
+            // connections wait for it according to the busy timeout.
+            DatabaseDialect::Sqlite => &["BEGIN IMMEDIATE"],
+            // The key is arbitrary, but must be the same for all binaries.
+            DatabaseDialect::Postgres => &["BEGIN", "SELECT pg_advisory_xact_lock(1401142839)"],
+        }
+    }
+
+    fn column_exists_sql(self, table: &str, column: &str) -> String {
+        match self {
+            DatabaseDialect::Sqlite => format!(
+                "SELECT name FROM pragma_table_info('{table}') WHERE name = '{column}'"
+            ),
+            DatabaseDialect::Postgres => format!(
+                "SELECT column_name FROM information_schema.columns
+                 WHERE table_schema = current_schema() AND table_name = '{table}' AND column_name = '{column}'"
+            ),
+        }
+    }
+}
+
+pub fn migrations(dialect: DatabaseDialect) -> Vec<Migration> {
+    use MigrationStep::*;
+    let id = dialect.id_column_type();
+    let add_column = |table: &'static str, column: &'static str, column_type: &str| UnlessColumnExists {
+        table,
+        column,
+        statements: vec![format!("ALTER TABLE {table} ADD COLUMN {column} {column_type}")],
+    };
+    // Older versions relied on SQLite `rowid`. Keep old row IDs, so that existing links stay
+    // valid. SQLite cannot add a primary key to an existing table, so the table is re-created.
+    let add_primary_key = |table: &'static str, column: &'static str, columns: &str| UnlessColumnExists {
+        table,
+        column,
+        statements: match dialect {
+            DatabaseDialect::Sqlite => vec![
+                format!("ALTER TABLE {table} RENAME TO {table}_legacy"),
+                format!("CREATE TABLE {table} ({column} {id}, {columns})"),
+                format!(
+                    "INSERT INTO {table} ({column}, {names}) SELECT rowid, {names} FROM {table}_legacy",
+                    names = columns.split(',').map(|c| c.split_whitespace().next().unwrap()).collect::<Vec<_>>().join(", ")
+                ),
+                format!("DROP TABLE {table}_legacy"),
+            ],
+            DatabaseDialect::Postgres => vec![
+                format!("ALTER TABLE {table} ADD COLUMN {column} {id}"),
+            ],
+        },
+    };
+
+    let mut baseline = vec![
+        // Tables as they were before migrations were introduced.
+        Sql("CREATE TABLE IF NOT EXISTS finished_games (
+            git_version TEXT,
+            invocation_id TEXT,
+            game_start_time TIMESTAMPTZ,
+            game_end_time TIMESTAMPTZ,
+            player_red_a TEXT,
+            player_red_b TEXT,
+            player_blue_a TEXT,
+            player_blue_b TEXT,
+            result TEXT,
+            game_pgn TEXT)".to_owned()),
+        Sql("CREATE TABLE IF NOT EXISTS client_performance (
+            git_version TEXT,
+            invocation_id TEXT,
+            user_agent TEXT,
+            time_zone TEXT,
+            turn_confirmation_p50 BIGINT,
+            turn_confirmation_p90 BIGINT,
+            turn_confirmation_p99 BIGINT,
+            turn_confirmation_n BIGINT,
+            process_outgoing_events_p99 BIGINT,
+            process_notable_events_p99 BIGINT,
+            refresh_p99 BIGINT,
+            update_state_p50 BIGINT,
+            update_state_p90 BIGINT,
+            update_state_p99 BIGINT,
+            update_state_n BIGINT,
+            update_clock_p99 BIGINT,
+            update_drag_state_p99 BIGINT)".to_owned()),
+        Sql("CREATE TABLE IF NOT EXISTS finished_matches (
+            git_version TEXT,
+            invocation_id TEXT,
+            contest_id TEXT,
+            match_end_time TIMESTAMPTZ,
+            match_length TEXT,
+            games_played BIGINT,
+            players TEXT,
+            winners TEXT,
+            scores TEXT,
+            match_pgn TEXT)".to_owned()),
+        // Current rating of each player and the rating after each game. See `rating` module.
+        Sql("CREATE TABLE IF NOT EXISTS player_ratings (
+            player_name TEXT PRIMARY KEY,
+            rating DOUBLE PRECISION,
+            games_played BIGINT,
+            update_time TIMESTAMPTZ)".to_owned()),
+        Sql("CREATE TABLE IF NOT EXISTS rating_history (
+            player_name TEXT,
+            game_end_time TIMESTAMPTZ,
+            rating DOUBLE PRECISION,
+            rating_change DOUBLE PRECISION,
+            games_played BIGINT)".to_owned()),
+        // Latest state of each live contest, for restoring contests after server restart.
+        // Snapshot is a JSON-serialized `ContestSnapshot`.
+        Sql("CREATE TABLE IF NOT EXISTS contest_snapshots (
+            contest_id TEXT PRIMARY KEY,
+            update_time TIMESTAMPTZ,
+            snapshot TEXT)".to_owned()),
+        add_column("finished_games", "game_record", "TEXT"),
+        add_column("finished_games", "variant", "TEXT"),
+        add_column("finished_games", "starting_time_sec", "BIGINT"),
+        add_column("finished_games", "drop_aggression", "TEXT"),
+    ];
+    if dialect == DatabaseDialect::Postgres {
+        // Early versions used types that sqlx cannot decode.
+        baseline.extend([
+            "ALTER TABLE finished_games
+                ALTER COLUMN game_start_time TYPE TIMESTAMPTZ,
+                ALTER COLUMN game_end_time TYPE TIMESTAMPTZ",
+            "ALTER TABLE finished_matches
+                ALTER COLUMN match_end_time TYPE TIMESTAMPTZ,
+                ALTER COLUMN games_played TYPE BIGINT",
+            "ALTER TABLE player_ratings
+                ALTER COLUMN update_time TYPE TIMESTAMPTZ,
+                ALTER COLUMN games_played TYPE BIGINT",
+            "ALTER TABLE rating_history
+                ALTER COLUMN game_end_time TYPE TIMESTAMPTZ,
+                ALTER COLUMN games_played TYPE BIGINT",
+            "ALTER TABLE contest_snapshots ALTER COLUMN update_time TYPE TIMESTAMPTZ",
+        ].map(|s| Sql(s.to_owned())));
+    }
+    // Older versions did not store the variant separately, but it is in the PGN.
+    baseline.extend([("Classic", "Bughouse"), ("FischerRandom", "Bughouse Chess960")].map(
+        |(variant, pgn_variant)| Sql(format!(
+            "UPDATE finished_games SET variant = '{variant}'
+             WHERE variant IS NULL AND game_pgn LIKE '%[Variant \"{pgn_variant}\"]%'"
+        ))
+    ));
+
+    vec![
+        Migration {
+            version: 1,
+            description: "Baseline: tables created before migrations were introduced",
+            steps: baseline,
+        },
+        Migration {
+            version: 2,
+            description: "Primary keys for finished_games and finished_matches",
+            steps: vec![
+                add_primary_key("finished_games", "game_id", "
+                    git_version TEXT,
+                    invocation_id TEXT,
+                    game_start_time TIMESTAMPTZ,
+                    game_end_time TIMESTAMPTZ,
+                    player_red_a TEXT,
+                    player_red_b TEXT,
+                    player_blue_a TEXT,
+                    player_blue_b TEXT,
+                    result TEXT,
+                    game_pgn TEXT,
+                    game_record TEXT,
+                    variant TEXT,
+                    starting_time_sec BIGINT,
+                    drop_aggression TEXT"),
+                add_primary_key("finished_matches", "match_id", "
+                    git_version TEXT,
+                    invocation_id TEXT,
+                    contest_id TEXT,
+                    match_end_time TIMESTAMPTZ,
+                    match_length TEXT,
+                    games_played BIGINT,
+                    players TEXT,
+                    winners TEXT,
+                    scores TEXT,
+                    match_pgn TEXT"),
+            ],
+        },
+        Migration {
+            version: 3,
+            description: "Contest ID for finished_games",
+            steps: vec![
+                Sql("ALTER TABLE finished_games ADD COLUMN contest_id TEXT".to_owned()),
+            ],
+        },
+        Migration {
+            version: 4,
+            description: "Turns of finished games and contest rules",
+            steps: vec![
+                // See `TurnRow`. `game_id` refers to `finished_games`.
+                Sql("CREATE TABLE turns (
+                    game_id BIGINT,
+                    turn_index BIGINT,
+                    board TEXT,
+                    force TEXT,
+                    player_name TEXT,
+                    algebraic TEXT,
+                    game_time_ms BIGINT,
+                    capture TEXT,
+                    drop_piece TEXT,
+                    clock_remaining_ms BIGINT,
+                    PRIMARY KEY (game_id, turn_index))".to_owned()),
+                Sql("CREATE INDEX turns_player_name ON turns (player_name)".to_owned()),
+                // See `ContestRow`.
+                Sql("CREATE TABLE contests (
+                    contest_id TEXT PRIMARY KEY,
+                    create_time TIMESTAMPTZ,
+                    owner TEXT,
+                    starting_position TEXT,
+                    starting_time_sec BIGINT,
+                    teaming TEXT,
+                    seat_rotation TEXT,
+                    min_pawn_drop_row BIGINT,
+                    max_pawn_drop_row BIGINT,
+                    drop_aggression TEXT,
+                    partner_hints BOOLEAN,
+                    live_chalk BOOLEAN,
+                    disconnect_policy TEXT,
+                    disconnect_timeout_sec BIGINT,
+                    match_length TEXT)".to_owned()),
+            ],
+        },
+        Migration {
+            version: 5,
+            description: "Client error reports",
+            steps: vec![
+                // See `ClientErrorReportRow`.
+                Sql(format!("CREATE TABLE client_error_reports (
+                    report_id {id},
+                    report_time TIMESTAMPTZ,
+                    git_version TEXT,
+                    server_git_version TEXT,
+                    user_agent TEXT,
+                    kind TEXT,
+                    message TEXT,
+                    backtrace TEXT,
+                    contest_id TEXT,
+                    game_number BIGINT,
+                    player_name TEXT)")),
+                Sql("CREATE INDEX client_error_reports_report_time ON client_error_reports (report_time)".to_owned()),
+            ],
+        },
+        Migration {
+            version: 6,
+            description: "Client performance in long format",
+            steps: vec![
+                // One row per `BughouseClientPerformance` report and one row per meter, so that
+                // new meters are stored without schema changes. The old `client_performance`
+                // table is no longer written. Its rows are kept, but they are not shown on the
+                // dashboard: they have no report time.
+                Sql(format!("CREATE TABLE client_performance_reports (
+                    report_id {id},
+                    report_time TIMESTAMPTZ,
+                    git_version TEXT,
+                    invocation_id TEXT,
+                    user_agent TEXT,
+                    time_zone TEXT)")),
+                Sql("CREATE INDEX client_performance_reports_report_time
+                    ON client_performance_reports (report_time)".to_owned()),
+                // See `MeterStats`. `report_id` refers to `client_performance_reports`.
+                Sql("CREATE TABLE client_performance_stats (
+                    report_id BIGINT,
+                    meter TEXT,
+                    p50 BIGINT,
+                    p90 BIGINT,
+                    p99 BIGINT,
+                    num_values BIGINT,
+                    PRIMARY KEY (report_id, meter))".to_owned()),
+            ],
+        },
+    ]
+}
+
+// Applies all migrations that have not been applied yet, each in a separate transaction.
+// Returns versions of the migrations applied.
+//
+// Each transaction locks the database and checks whether the migration is still needed, so
+// it's safe to run this from the game server and the webserver simultaneously: a migration
+// applied by the other party in the meantime is skipped.
+pub fn apply_migrations<D: MigrationDb>(db: &mut D, dialect: DatabaseDialect) -> Result<Vec<i64>, D::Error> {
+    let mut applied = vec![];
+    for migration in migrations(dialect) {
+        for statement in dialect.begin_migration_sql() {
+            db.execute(statement)?;
+        }
+        match apply_migration_if_needed(db, dialect, &migration) {
+            Ok(applied_now) => {
+                db.execute("COMMIT")?;
+                if applied_now {
+                    applied.push(migration.version);
+                }
+            },
+            Err(e) => {
+                // The original error is more useful than rollback error, if any.
+                let _ = db.execute("ROLLBACK");
+                return Err(e);
+            },
+        }
+    }
+    Ok(applied)
+}
+
+// Must be called in a transaction holding the migration lock. Returns false if the migration
+// has already been applied.
+fn apply_migration_if_needed<D: MigrationDb>(
+    db: &mut D, dialect: DatabaseDialect, migration: &Migration
+) -> Result<bool, D::Error> {
+    db.execute("CREATE TABLE IF NOT EXISTS schema_migrations (
+        version BIGINT PRIMARY KEY,
+        description TEXT)")?;
+    let version = migration.version;
+    if db.has_rows(&format!("SELECT version FROM schema_migrations WHERE version = {version}"))? {
+        return Ok(false);
+    }
+    for step in migration.steps.iter() {
+        match step {
+            MigrationStep::Sql(statement) => db.execute(statement)?,
+            MigrationStep::UnlessColumnExists{ table, column, statements } => {
+                if !db.has_rows(&dialect.column_exists_sql(table, column))? {
+                    for statement in statements {
+                        db.execute(statement)?;
+                    }
+                }
+            }
+        }
+    }
+    db.execute(&format!(
+        "INSERT INTO schema_migrations (version, description) VALUES ({}, '{}')",
+        migration.version,
+        migration.description.replace('\'', "''")
+    ))?;
+    Ok(true)
+}
+
+#[derive(Clone, Debug)]
 pub struct GameResultRow {
     pub git_version: String,
     pub invocation_id: String,
@@ -12,4 +424,130 @@ pub struct GameResultRow {
     pub player_blue_b: String,
     pub result: String,
     pub game_pgn: String,
+    // `None` for games recorded by older versions.
+    pub contest_id: Option<String>,
+    // JSON-serialized `FinishedGameRecord`. `None` for games recorded by older versions.
+    pub game_record: Option<String>,
+    // Key rules, stored separately for filtering. `None` for games recorded by older
+    // versions (except `variant`, which is restored from the PGN).
+    pub variant: Option<String>,  // `StartingPosition` variant name, e.g. "FischerRandom"
+    pub starting_time_sec: Option<i64>,
+    pub drop_aggression: Option<String>,  // `DropAggression` variant name
+}
+
+// Player rating after a game. See `rating` module.
+#[derive(Clone, Debug)]
+pub struct RatingHistoryRow {
+    pub player_name: String,
+    pub game_end_time: Option<OffsetDateTime>,
+    pub rating: f64,
+    pub rating_change: f64,
+    pub games_played: i64,  // including this game
+}
+
+// A contest with limited `MatchLength` that has ended. Games are also recorded individually.
+#[derive(Debug)]
+pub struct MatchResultRow {
+    pub git_version: String,
+    pub invocation_id: String,
+    pub contest_id: String,
+    pub match_end_time: Option<OffsetDateTime>,
+    pub match_length: String,
+    pub games_played: i64,
+    pub players: String,  // comma-separated
+    pub winners: String,  // comma-separated; empty if the teams are tied
+    pub scores: String,   // see `Scores::to_human_readable`
+    pub match_pgn: String,
+}
+
+// One turn of a finished game. See `server::finished_game_turns`.
+#[derive(Clone, Debug)]
+pub struct TurnRow {
+    pub turn_index: i64,  // 0-based, in the order the turns were made on both boards
+    pub board: String,  // `BughouseBoard`: "A" or "B"
+    pub force: String,  // `Force`: "White" or "Black"
+    pub player_name: String,
+    pub algebraic: String,
+    pub game_time_ms: i64,  // `GameInstant` of the turn
+    pub capture: Option<String>,  // captured piece, e.g. "Q"
+    pub drop_piece: Option<String>,  // dropped piece, e.g. "N"
+    pub clock_remaining_ms: i64,  // time left on the player's clock after the turn
+}
+
+// Contest rules, recorded once when the contest is created. See `server::contest_row`.
+#[derive(Clone, Debug)]
+pub struct ContestRow {
+    pub contest_id: String,
+    pub create_time: Option<OffsetDateTime>,
+    pub owner: Option<String>,
+    // `ChessRules`.
+    pub starting_position: String,
+    pub starting_time_sec: i64,
+    // `BughouseRules`. Enums are stored as variant names.
+    pub teaming: String,
+    pub seat_rotation: String,
+    pub min_pawn_drop_row: i64,
+    pub max_pawn_drop_row: i64,
+    pub drop_aggression: String,
+    pub partner_hints: bool,
+    pub live_chalk: bool,
+    pub disconnect_policy: String,
+    pub disconnect_timeout_sec: Option<i64>,  // pause or grace period, if any
+    pub match_length: String,  // see `MatchLength::to_human_readable`
+}
+
+// An error reported by a client. See `BughouseClientErrorReport`.
+#[derive(Clone, Debug)]
+pub struct ClientErrorReportRow {
+    pub report_time: Option<OffsetDateTime>,
+    pub git_version: String,  // client version; may differ from server version if the page is stale
+    pub server_git_version: String,
+    pub user_agent: String,
+    pub kind: String,  // `BughouseClientErrorReport` variant name
+    pub message: String,  // panic info or error message
+    pub backtrace: Option<String>,
+    // Where the client was when the error happened.
+    pub contest_id: Option<String>,
+    pub game_number: Option<i64>,  // 1-based game index within the contest
+    pub player_name: Option<String>,  // player or spectator
+}
+
+// Everything needed to rebuild a game: it is restored by replaying the turns.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GameSnapshot {
+    pub starting_position: EffectiveStartingPosition,
+    pub players: Vec<PlayerInGame>,
+    pub turn_log: Vec<TurnRecord>,
+    pub partner_messages: Vec<PartnerMessageRecord>,
+    pub status: BughouseGameStatus,
+    // Game time when the snapshot was taken (for an active game) or when the game ended.
+    pub time: GameInstant,
+    // Only for the current game. Drawings from older games are not kept.
+    pub chalkboard: Option<Chalkboard>,
+}
+
+// Everything needed to replay a finished game, including turn times. Unlike PGN, this
+// is not meant to be read by other programs.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FinishedGameRecord {
+    pub chess_rules: ChessRules,
+    pub bughouse_rules: BughouseRules,
+    pub game: GameSnapshot,
+}
+
+// Contest state that survives server restarts. Connections, readiness and preturns are
+// not saved: players need to rejoin anyway.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ContestSnapshot {
+    pub contest_id: String,
+    pub chess_rules: ChessRules,
+    pub bughouse_rules: BughouseRules,
+    pub players: Vec<Player>,
+    pub owner: Option<String>,
+    pub banned_names: Vec<String>,
+    pub scores: Scores,
+    pub match_history: Vec<GameSnapshot>,
+    pub current_game: Option<GameSnapshot>,
+    pub chat: Vec<ChatRecord>,
+    pub contest_result: Option<ContestResult>,
 }
@@ -78,6 +78,7 @@ fn make_termination_string(game: &BughouseGame) -> &'static str {
         // There is no "resign" Termination, should use "normal" apparently:
         // https://lichess.org/forum/general-chess-discussion/how-do-i-make-it-say-that-one-side-resigned#4
         Victory(_, Resignation) => "normal",
+        Victory(_, Forfeit) => "abandoned",
         // Somehow I'm skeptical many chess engines would be prepared for a "time forfeit" draw
         Draw(SimultaneousFlag) => "normal",
         Draw(ThreefoldRepetition) => "normal",
@@ -156,13 +157,24 @@ fn player_notation(player_id: BughousePlayerId) -> &'static str {
 //   - "Variant" - follow chess.com example;
 //   - "Outcome" - human-readable game result description; this is addition to "Result"
 //     and "Termination" fields, which follow PGN standard, but are less informative.
+//
+// Partner messages are exported as comments, e.g. "{a: need N}".
 pub fn export_to_bpgn(_format: BughouseExportFormat, game: &BughouseGame, round: usize)
     -> String
 {
     let header = make_bughouse_bpng_header(game, round);
     let mut doc = TextDocument::new();
     let mut full_turn_idx = enum_map!{ _ => 1 };
-    for turn_record in game.turn_log() {
+    let mut partner_messages = game.partner_messages().iter().peekable();
+    let mut push_partner_messages = |doc: &mut TextDocument, turn_idx: usize| {
+        while let Some(record) = partner_messages.next_if(|r| r.turn_idx <= turn_idx) {
+            doc.push_word(&format!(
+                "{{{}: {}}}", player_notation(record.player_id), record.message.to_log_entry()
+            ));
+        }
+    };
+    for (turn_idx, turn_record) in game.turn_log().iter().enumerate() {
+        push_partner_messages(&mut doc, turn_idx);
         let TurnRecordExpanded{ player_id, turn_expanded, .. } = turn_record;
         let turn_algebraic = &turn_expanded.algebraic;
         let turn_notation = format!(
@@ -176,5 +188,79 @@ pub fn export_to_bpgn(_format: BughouseExportFormat, game: &BughouseGame, round:
         }
         doc.push_word(&turn_notation);
     }
+    push_partner_messages(&mut doc, game.turn_log().len());
     format!("{}{}", header, doc.render())
 }
+
+// Exports all games of a contest as a single BPGN file, one game per round.
+pub fn export_match_to_bpgn<'a>(
+    format: BughouseExportFormat, games: impl IntoIterator<Item = &'a BughouseGame>
+) -> String {
+    games.into_iter().enumerate().map(|(round, game)| {
+        export_to_bpgn(format, game, round + 1)
+    }).collect::<Vec<_>>().join("\n")
+}
+
+// A turn from BPGN move text, e.g. "12b. Qxf7#".
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct BpgnTurn {
+    pub player_id: BughousePlayerId,
+    pub full_turn_idx: u32,
+    pub algebraic: String,
+}
+
+fn parse_turn_number(word: &str) -> Option<(u32, BughousePlayerId)> {
+    use BughouseBoard::*;
+    use Force::*;
+    let word = word.strip_suffix('.')?;
+    let player_char = word.chars().last()?;
+    let full_turn_idx = word[..word.len() - player_char.len_utf8()].parse().ok()?;
+    let (board_idx, force) = match player_char {
+        'A' => (A, White),
+        'a' => (A, Black),
+        'B' => (B, White),
+        'b' => (B, Black),
+        _ => return None,
+    };
+    Some((full_turn_idx, BughousePlayerId{ board_idx, force }))
+}
+
+// Parses turns from a single-game BPGN, such as produced by `export_to_bpgn`. Headers,
+// comments (including partner messages) and the result are skipped. Turns are not validated
+// against the rules.
+//
+// Improvement potential. Parse headers and return a complete game.
+pub fn parse_bpgn_turns(bpgn: &str) -> Result<Vec<BpgnTurn>, String> {
+    let move_text = bpgn.lines()
+        .filter(|line| !line.trim_start().starts_with('['))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut without_comments = String::new();
+    let mut in_comment = false;
+    for ch in move_text.chars() {
+        match (ch, in_comment) {
+            ('{', false) => in_comment = true,
+            ('}', true) => { in_comment = false; without_comments.push(' '); },
+            (_, true) => {},
+            (_, false) => without_comments.push(ch),
+        }
+    }
+    if in_comment {
+        return Err("Unterminated comment".to_owned());
+    }
+    let mut turns = vec![];
+    let mut words = without_comments.split_whitespace();
+    while let Some(word) = words.next() {
+        if matches!(word, "1-0" | "0-1" | "1/2-1/2" | "*") {
+            continue;
+        }
+        let Some((full_turn_idx, player_id)) = parse_turn_number(word) else {
+            return Err(format!("Expected turn number, found \"{word}\""));
+        };
+        let Some(algebraic) = words.next() else {
+            return Err(format!("Missing turn after \"{word}\""));
+        };
+        turns.push(BpgnTurn{ player_id, full_turn_idx, algebraic: algebraic.to_owned() });
+    }
+    Ok(turns)
+}
@@ -19,11 +19,26 @@ impl Team {
 }
 
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum PlayerPresence {
+    // Connected and responding to heartbeats.
+    Online,
+    // Connected, but hasn't responded for a few seconds. Likely a network hiccup: the player
+    // may come back any moment.
+    TemporarilyLost,
+    // No connection. The player needs to rejoin.
+    Offline,
+}
+
 // Player while in lobby. May or may not have an assigned team yet.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Player {
     pub name: String,
     pub fixed_team: Option<Team>,
-    pub is_online: bool,
+    pub presence: PlayerPresence,
     pub is_ready: bool,
 }
+
+impl Player {
+    pub fn is_online(&self) -> bool { self.presence == PlayerPresence::Online }
+}
@@ -0,0 +1,124 @@
+// Player ratings based on finished games (see `GameResultRow`).
+//
+// Uses Elo with a team model: team strength is the average rating of the two partners, and
+// each partner's rating changes by their own K-factor times the difference between the team's
+// actual and expected score. This way winning with a strong partner gives fewer points than
+// winning with a weak one. Partners receive the same change unless only one of them is
+// a newcomer (see `NEWCOMER_K_FACTOR`).
+//
+// Improvement potential. Consider Glicko-2: it would give a measure of rating reliability and
+//   handle inactive players better.
+
+use std::collections::HashMap;
+
+use serde::{Serialize, Deserialize};
+
+use crate::persistence::{GameResultRow, RatingHistoryRow};
+
+
+pub const INITIAL_RATING: f64 = 1600.0;
+// Newcomers' ratings move faster until the rating stabilizes.
+const NEWCOMER_GAMES: u32 = 30;
+const NEWCOMER_K_FACTOR: f64 = 40.0;
+const K_FACTOR: f64 = 20.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlayerRating {
+    pub rating: f64,
+    pub games_played: u32,
+}
+
+impl Default for PlayerRating {
+    fn default() -> Self {
+        PlayerRating { rating: INITIAL_RATING, games_played: 0 }
+    }
+}
+
+impl PlayerRating {
+    fn k_factor(&self) -> f64 {
+        if self.games_played < NEWCOMER_GAMES { NEWCOMER_K_FACTOR } else { K_FACTOR }
+    }
+}
+
+// Current ratings, indexed by player name. Players without games have `INITIAL_RATING`.
+#[derive(Clone, Debug, Default)]
+pub struct Ratings {
+    pub per_player: HashMap<String, PlayerRating>,
+}
+
+impl Ratings {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn get(&self, player_name: &str) -> PlayerRating {
+        self.per_player.get(player_name).copied().unwrap_or_default()
+    }
+
+    // Team strength used to compute expected score.
+    pub fn team_rating(&self, player_a: &str, player_b: &str) -> f64 {
+        (self.get(player_a).rating + self.get(player_b).rating) / 2.0
+    }
+
+    // Updates ratings of all four players. Returns one history entry per player, or nothing
+    // if the game cannot be rated (e.g. the result is unknown).
+    pub fn apply_game(&mut self, game: &GameResultRow) -> Vec<RatingHistoryRow> {
+        let Some(red_score) = red_team_score(&game.result) else {
+            return vec![];
+        };
+        let red_rating = self.team_rating(&game.player_red_a, &game.player_red_b);
+        let blue_rating = self.team_rating(&game.player_blue_a, &game.player_blue_b);
+        let red_expected = expected_score(red_rating, blue_rating);
+        let teams = [
+            ([&game.player_red_a, &game.player_red_b], red_score - red_expected),
+            ([&game.player_blue_a, &game.player_blue_b], red_expected - red_score),
+        ];
+        // Compute all changes before applying them: the second team must see the ratings
+        // from before the game.
+        let mut history = vec![];
+        for (players, score_diff) in teams {
+            for player_name in players {
+                let before = self.get(player_name);
+                let rating_change = before.k_factor() * score_diff;
+                history.push(RatingHistoryRow {
+                    player_name: player_name.clone(),
+                    game_end_time: game.game_end_time,
+                    rating: before.rating + rating_change,
+                    rating_change,
+                    games_played: (before.games_played + 1).into(),
+                });
+            }
+        }
+        for entry in history.iter() {
+            self.per_player.insert(entry.player_name.clone(), PlayerRating {
+                rating: entry.rating,
+                games_played: entry.games_played as u32,
+            });
+        }
+        history
+    }
+}
+
+// Replays games in the given order, which should be chronological.
+pub fn compute_ratings<'a>(games: impl IntoIterator<Item = &'a GameResultRow>) -> (Ratings, Vec<RatingHistoryRow>) {
+    let mut ratings = Ratings::new();
+    let mut history = vec![];
+    for game in games {
+        history.extend(ratings.apply_game(game));
+    }
+    (ratings, history)
+}
+
+// Probability that a team with `rating` beats a team with `opponent_rating`, counting draws
+// as half a win.
+pub fn expected_score(rating: f64, opponent_rating: f64) -> f64 {
+    1.0 / (1.0 + 10_f64.powf((opponent_rating - rating) / 400.0))
+}
+
+// Parses `GameResultRow::result`.
+fn red_team_score(result: &str) -> Option<f64> {
+    match result {
+        "VICTORY_RED" => Some(1.0),
+        "VICTORY_BLUE" => Some(0.0),
+        "DRAW" => Some(0.5),
+        _ => None,
+    }
+}
@@ -26,6 +26,50 @@ pub enum Teaming {
     IndividualMode,
 }
 
+// How teammates are seated in `Teaming::FixedTeams` mode. Note that teammates always play
+// different colors (see `get_bughouse_force`), so alternating boards also alternates colors.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum SeatRotation {
+    // Teammates are assigned to boards randomly before each game.
+    Random,
+    // Teammates swap boards after each game. In the first game players who joined earlier
+    // play on board A.
+    AlternateBoards,
+    // Everybody keeps their board from the first game, but both teams swap colors after each
+    // game. In the first game players who joined earlier play on board A, and the red team
+    // plays white on board A.
+    AlternateColors,
+    // Everybody keeps their seat from the first game. In the first game players who joined
+    // earlier play on board A.
+    FixedSeats,
+}
+
+// What happens when a player loses connection during a game. Players are considered
+// disconnected when they leave or when their connection is permanently lost (see
+// `HeartbeatOutcome::OtherPartyPermanentlyLost`); short network hiccups are ignored.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum DisconnectPolicy {
+    // Clocks keep running.
+    Ignore,
+    // All clocks are paused until everybody is back, but for no longer than `max_pause`
+    // in total per game. After that clocks continue running.
+    Pause{ max_pause: Duration },
+    // The team of a player who hasn't reconnected within `grace_period` loses.
+    Forfeit{ grace_period: Duration },
+}
+
+// When the contest ends. Points are counted as in chess: victory gives 1 point, draw
+// gives 1/2. In individual mode the limits apply to each player separately.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum MatchLength {
+    Unlimited,
+    // Ends as soon as somebody has this many points.
+    FirstTo{ points: u32 },
+    // Ends after this many games. In fixed teams mode ends earlier if the remaining games
+    // cannot change the winner.
+    BestOf{ games: u32 },
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ChessRules {
     pub starting_position: StartingPosition,
@@ -37,9 +81,29 @@ pub struct BughouseRules {
     // Improvement potential. Should `teaming` reside in `BughouseRules` or be moved to
     //   a separate struct (e.g. `ContestRules`)?
     pub teaming: Teaming,
+    pub seat_rotation: SeatRotation,  // only for `Teaming::FixedTeams`
     pub min_pawn_drop_row: SubjectiveRow,
     pub max_pawn_drop_row: SubjectiveRow,
     pub drop_aggression: DropAggression,
+    // Whether players are shown which piece would allow their partner to deliver or force
+    // a drop mate.
+    // Should be disabled in competitive contests, since this is a form of computer assistance.
+    pub partner_hints: bool,
+    // Whether players can draw with chalk while the game is active. Live drawings are visible
+    // only to the team (and to spectators) until the game is over.
+    pub live_chalk: bool,
+    pub disconnect_policy: DisconnectPolicy,
+    pub match_length: MatchLength,
+}
+
+impl MatchLength {
+    pub fn to_human_readable(self) -> String {
+        match self {
+            MatchLength::Unlimited => "unlimited".to_owned(),
+            MatchLength::FirstTo{ points } => format!("first to {points}"),
+            MatchLength::BestOf{ games } => format!("best of {games}"),
+        }
+    }
 }
 
 impl ChessRules {
@@ -55,9 +119,14 @@ impl BughouseRules {
     pub fn chess_com() -> Self {
         Self{
             teaming: Teaming::FixedTeams,
+            seat_rotation: SeatRotation::Random,
             min_pawn_drop_row: SubjectiveRow::from_one_based(2),
             max_pawn_drop_row: SubjectiveRow::from_one_based(7),
             drop_aggression: DropAggression::MateAllowed,
+            partner_hints: false,
+            live_chalk: false,
+            disconnect_policy: DisconnectPolicy::Ignore,
+            match_length: MatchLength::Unlimited,
         }
     }
 }
@@ -6,12 +6,26 @@ use crate::player::Team;
 
 
 // Victory is scored as 2:0, draw is 1:1.
+//
+// In individual mode players may join after the contest has started, so total score is not
+// a fair comparison. Use `per_player_average` to rank players instead.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Scores {
     // Not EnumMap, because it does not support serde.
     // Improvement potential: Implement Serde support for EnumMap instead.
     pub per_team: HashMap<Team, u32>,  // when teaming == Teaming::FixedTeams
     pub per_player: HashMap<String, u32>,  // when teaming == Teaming::IndividualMode
+    pub games_played: HashMap<String, u32>,  // when teaming == Teaming::IndividualMode
+}
+
+// Final result of a contest with limited `MatchLength`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ContestResult {
+    pub scores: Scores,
+    pub games_played: usize,
+    // Members of the winning team or, in individual mode, the players with the highest
+    // score. Empty if the teams are tied.
+    pub winners: Vec<String>,
 }
 
 impl Scores {
@@ -19,6 +33,42 @@ impl Scores {
         Scores {
             per_team: HashMap::new(),
             per_player: HashMap::new(),
+            games_played: HashMap::new(),
+        }
+    }
+
+    // Average score per game played, in the same units as `per_player`. Returns `None` if
+    // the player hasn't finished any games yet.
+    pub fn per_player_average(&self, player_name: &str) -> Option<f64> {
+        let games = *self.games_played.get(player_name)?;
+        if games == 0 {
+            return None;
         }
+        let score = *self.per_player.get(player_name).unwrap_or(&0);
+        Some(score as f64 / games as f64)
+    }
+
+    // E.g. "Red 3 : Blue 1½" in fixed teams mode or "p1 3, p2 2½, p3 ½" in individual mode.
+    pub fn to_human_readable(&self) -> String {
+        if !self.per_team.is_empty() {
+            let team_score = |team| score_to_points_string(*self.per_team.get(&team).unwrap_or(&0));
+            format!("Red {} : Blue {}", team_score(Team::Red), team_score(Team::Blue))
+        } else {
+            let mut per_player = self.per_player.iter().collect::<Vec<_>>();
+            per_player.sort_by(|(name1, score1), (name2, score2)| score2.cmp(score1).then(name1.cmp(name2)));
+            per_player.iter()
+                .map(|(name, &score)| format!("{} {}", name, score_to_points_string(score)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        }
+    }
+}
+
+// Converts internal score units to chess points, e.g. "3" or "2½".
+pub fn score_to_points_string(score: u32) -> String {
+    match (score / 2, score % 2) {
+        (0, 1) => "½".to_owned(),
+        (points, 0) => points.to_string(),
+        (points, _) => format!("{}½", points),
     }
 }
@@ -1,7 +1,8 @@
 // Improvement potential. Replace `game.find_player(&self.players[player_id].name)`
 //   with a direct mapping (player_id -> player_bughouse_id).
 
-use std::collections::{HashSet, HashMap, hash_map};
+use std::cmp;
+use std::collections::{HashSet, HashMap, VecDeque, hash_map};
 use std::iter;
 use std::ops;
 use std::sync::{Arc, Mutex, MutexGuard, mpsc};
@@ -14,23 +15,48 @@ use log::{info, warn};
 use rand::{Rng, seq::SliceRandom};
 use strum::IntoEnumIterator;
 
-use crate::board::{TurnMode, TurnError, TurnInput, VictoryReason};
+use crate::board::{Turn, TurnMode, TurnError, TurnInput, VictoryReason};
 use crate::chalk::{ChalkDrawing, Chalkboard};
-use crate::clock::GameInstant;
-use crate::game::{TurnRecord, BughouseBoard, BughousePlayerId, PlayerInGame, BughouseGameStatus, BughouseGame};
+use crate::chat::{ChatChannel, ChatMessage, ChatRecord, MAX_CHAT_MESSAGE_LENGTH};
+use crate::clock::{Clock, GameInstant, WallGameTimePair};
+use crate::force::Force;
+use crate::game::{TOTAL_PLAYERS, TOTAL_PLAYERS_PER_TEAM, TurnRecord, BughouseBoard, BughousePlayerId, PlayerInGame, BughouseGameStatus, BughouseGame};
 use crate::get_bughouse_force;
 use crate::heartbeat::{Heart, HeartbeatOutcome};
-use crate::event::{BughouseServerEvent, BughouseClientEvent, BughouseClientErrorReport};
+use crate::meter::{SyncMeter, SyncMeterBox};
+use crate::event::{BughouseServerEvent, BughouseClientEvent, BughouseClientErrorReport, JoinMode};
+use crate::partner_message::PartnerMessage;
+use crate::persistence::{
+    GameSnapshot, ContestSnapshot, ContestRow, ClientErrorReportRow, FinishedGameRecord, TurnRow
+};
 use crate::pgn::{self, BughouseExportFormat};
-use crate::player::{Player, Team};
-use crate::rules::{Teaming, ChessRules, BughouseRules};
-use crate::scores::Scores;
+use crate::player::{Player, PlayerPresence, Team};
+use crate::rules::{Teaming, SeatRotation, DisconnectPolicy, MatchLength, ChessRules, BughouseRules};
+use crate::scores::{ContestResult, Scores};
 use crate::server_hooks::{ServerHooks, NoopServerHooks};
 
 
-const TOTAL_PLAYERS: usize = 4;
-const TOTAL_PLAYERS_PER_TEAM: usize = 2;
 const CONTEST_GC_INACTIVITY_THRESHOLD: Duration = Duration::from_secs(3600 * 24);
+// Contest snapshots are written on tick, at most this often for each contest. Some events
+// (e.g. chalk updates) are very frequent, while a snapshot includes the entire match history.
+const CONTEST_SNAPSHOT_INTERVAL: Duration = Duration::from_secs(1);
+// `IncomingEvent::Tick` is expected this often.
+pub const TICK_INTERVAL: Duration = Duration::from_millis(100);
+// How long a player must be offline before other players can vote to kick them. Should be
+// less than `OTHER_PARTY_PERMANENTLY_LOST_THRESHOLD`, otherwise lobby players would always
+// be removed automatically before the vote is possible.
+const KICK_VOTE_OFFLINE_THRESHOLD: Duration = Duration::from_secs(30);
+const MAX_CHAT_HISTORY: usize = 1000;
+// Each sender may send at most `CHAT_RATE_LIMIT_MESSAGES` within `CHAT_RATE_LIMIT_WINDOW`.
+const CHAT_RATE_LIMIT_MESSAGES: usize = 5;
+const CHAT_RATE_LIMIT_WINDOW: Duration = Duration::from_secs(10);
+// Each client may send at most `ERROR_REPORT_RATE_LIMIT_REPORTS` error reports within
+// `ERROR_REPORT_RATE_LIMIT_WINDOW`; the rest are dropped.
+const ERROR_REPORT_RATE_LIMIT_REPORTS: usize = 5;
+const ERROR_REPORT_RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+// Error report fields come from the client, so they are truncated (in characters).
+const MAX_ERROR_REPORT_TAG_LENGTH: usize = 200;  // git version, user agent
+const MAX_ERROR_REPORT_TEXT_LENGTH: usize = 10_000;  // message, backtrace
 
 #[derive(Debug)]
 pub enum IncomingEvent {
@@ -40,14 +66,22 @@ pub enum IncomingEvent {
 
 #[derive(Debug)]
 pub struct GameState {
+    game_id: usize,
     game: BughouseGame,
-    game_start: Option<Instant>,
+    // `None` before the first turn. Paused when the game is restored after server restart
+    // or because of `DisconnectPolicy::Pause`.
+    time_pair: Option<WallGameTimePair>,
     preturns: HashMap<BughousePlayerId, TurnInput>,
     chalkboard: Chalkboard,
+    // When the current pause caused by `DisconnectPolicy::Pause` or by server restart started.
+    disconnect_pause_start: Option<Instant>,
+    // Total duration of finished pauses caused by `DisconnectPolicy::Pause` or by server restart.
+    disconnect_pause_total: Duration,
 }
 
 impl GameState {
     pub fn game(&self) -> &BughouseGame { &self.game }
+    fn is_paused(&self) -> bool { matches!(self.time_pair, Some(pair) if pair.is_paused()) }
 }
 
 
@@ -95,8 +129,10 @@ pub struct Client {
     events_tx: mpsc::Sender<BughouseServerEvent>,
     contest_id: Option<ContestId>,
     player_id: Option<PlayerId>,
+    spectator_name: Option<String>,
     logging_id: String,
     heart: Heart,
+    error_report_times: VecDeque<Instant>,  // recent error reports, for rate limiting
 }
 
 impl Client {
@@ -127,8 +163,10 @@ impl Clients {
             events_tx,
             contest_id: None,
             player_id: None,
+            spectator_name: None,
             logging_id,
             heart: Heart::new(now),
+            error_report_times: VecDeque::new(),
         };
         let id = ClientId(self.next_id);
         self.next_id += 1;
@@ -143,23 +181,25 @@ impl Clients {
     // TODO: Make sure network connection is closed in a reasnable timeframe whenever
     //   a client is removed.
     //
-    // Improvement potential. Send an event informing other clients that somebody went
-    // offline (for TUI: could use “ϟ” for “disconnected”; there is a plug emoji “🔌”
-    // that works much better, but it's not supported by console fonts).
+    // Other clients learn that the player went offline from `Contest::update_presence`.
     pub fn remove_client(&mut self, id: ClientId) -> Option<String> {
         self.map.remove(&id).map(|client| client.logging_id)
     }
 
-    // Sends the event to each client who has joined the contest.
+    // Sends the event to each client who has joined the contest. Returns the number of
+    // recipients.
     //
     // Improvement potential. Do not iterate over all clients. Keep the list of clients
     // in each contest.
-    fn broadcast(&mut self, contest_id: &ContestId, event: &BughouseServerEvent) {
+    fn broadcast(&mut self, contest_id: &ContestId, event: &BughouseServerEvent) -> usize {
+        let mut recipients = 0;
         for client in self.map.values_mut() {
             if client.contest_id.as_ref() == Some(contest_id) {
                 client.send(event.clone());
+                recipients += 1;
             }
         }
+        recipients
     }
 }
 
@@ -177,16 +217,50 @@ struct Contest {
     chess_rules: ChessRules,
     bughouse_rules: BughouseRules,
     players: Players,
+    spectators: Vec<String>,  // sorted
+    owner: Option<String>,  // player name; can kick players and transfer ownership
+    banned_names: HashSet<String>,
+    offline_since: HashMap<PlayerId, Instant>,  // not `Online`, i.e. including `TemporarilyLost`
+    disconnected_since: HashMap<PlayerId, Instant>,  // `Offline`; forfeit grace period starts here
+    kick_votes: HashMap<PlayerId, HashSet<PlayerId>>,  // target -> voters
     scores: Scores,
+    chat: VecDeque<ChatRecord>,  // up to `MAX_CHAT_HISTORY` latest messages
+    chat_send_times: HashMap<String, VecDeque<Instant>>,  // sender -> recent messages, for rate limiting
     match_history: Vec<BughouseGame>,  // final game states
     game_state: Option<GameState>,  // active game or latest game
+    contest_result: Option<ContestResult>,  // set when the match is over; no more games afterwards
     last_activity: Instant,  // for GC
+    snapshot_pending: bool,  // whether the contest has changed since the last snapshot
+    last_snapshot: Option<Instant>,
     board_assignment_override: Option<Vec<PlayerInGame>>,  // for tests
 }
 
 struct Context<'a, 'b> {
     clients: &'b mut MutexGuard<'a, Clients>,
     hooks: &'a mut dyn ServerHooks,
+    meters: &'a ServerMeters,
+}
+
+// Durations are in microseconds.
+struct ServerMeters {
+    apply_event_network: SyncMeter,
+    apply_event_tick: SyncMeter,
+    clients_lock_wait: SyncMeter,
+    tick_lag: SyncMeter,  // how much later than `TICK_INTERVAL` after the previous tick
+    broadcast_recipients: SyncMeter,
+}
+
+impl ServerMeters {
+    fn new(meter_box: &SyncMeterBox) -> Self {
+        let meter = |name: &str| meter_box.meter(name.to_owned());
+        ServerMeters {
+            apply_event_network: meter("apply_event_network_us"),
+            apply_event_tick: meter("apply_event_tick_us"),
+            clients_lock_wait: meter("clients_lock_wait_us"),
+            tick_lag: meter("tick_lag_us"),
+            broadcast_recipients: meter("broadcast_recipients"),
+        }
+    }
 }
 
 struct CoreServerState {
@@ -205,6 +279,8 @@ pub struct ServerState {
     // Optimization potential: Lock-free map instead of Mutex<HashMap>.
     clients: Arc<Mutex<Clients>>,
     hooks: Box<dyn ServerHooks>,
+    meters: ServerMeters,
+    last_tick: Option<Instant>,
     core: CoreServerState,
 }
 
@@ -212,15 +288,33 @@ impl ServerState {
     pub fn new(
         clients: Arc<Mutex<Clients>>,
         hooks: Option<Box<dyn ServerHooks>>,
+        meter_box: &SyncMeterBox,
     ) -> Self {
+        let mut hooks = hooks.unwrap_or_else(|| Box::new(NoopServerHooks{}));
+        let mut core = CoreServerState::new();
+        core.restore_contests(hooks.load_contests(), Instant::now());
         ServerState {
             clients,
-            hooks: hooks.unwrap_or_else(|| Box::new(NoopServerHooks{})),
-            core: CoreServerState::new(),
+            hooks,
+            meters: ServerMeters::new(meter_box),
+            last_tick: None,
+            core,
         }
     }
 
     pub fn apply_event(&mut self, event: IncomingEvent) {
+        let lock_start = Instant::now();
+        if let IncomingEvent::Tick = event {
+            if let Some(last_tick) = self.last_tick.replace(lock_start) {
+                let lag = (lock_start - last_tick).saturating_sub(TICK_INTERVAL);
+                self.meters.tick_lag.record_duration_micros(lag);
+            }
+        }
+        let apply_event_meter = match event {
+            IncomingEvent::Network(..) => &self.meters.apply_event_network,
+            IncomingEvent::Tick => &self.meters.apply_event_tick,
+        };
+
         // Lock clients for the entire duration of the function. This means simpler and
         // more predictable event processing, e.g. it gives a guarantee that all broadcasts
         // from a single `apply_event` reach the same set of clients.
@@ -228,13 +322,20 @@ impl ServerState {
         // Improvement potential. Rethink this approach. With multiple parallel contests this
         // global mutex may become a bottleneck.
         let mut clients = self.clients.lock().unwrap();
+        let lock_acquired = Instant::now();
+        self.meters.clients_lock_wait.record_duration_micros(lock_acquired - lock_start);
 
         let mut ctx = Context {
             clients: &mut clients,
-            hooks: self.hooks.as_mut()
+            hooks: self.hooks.as_mut(),
+            meters: &self.meters,
         };
 
         self.core.apply_event(&mut ctx, event);
+
+        drop(clients);
+        // Also serves as the clients lock hold time.
+        apply_event_meter.record_duration_micros(lock_acquired.elapsed());
     }
 
     #[allow(non_snake_case)]
@@ -243,6 +344,18 @@ impl ServerState {
         assert_eq!(assignment.len(), TOTAL_PLAYERS);
         self.core.contests.get_mut(&contest_id).unwrap().board_assignment_override = Some(assignment);
     }
+
+    // Processes a tick as if it happened `delay` from now.
+    #[allow(non_snake_case)]
+    pub fn TEST_tick_after(&mut self, delay: Duration) {
+        let mut clients = self.clients.lock().unwrap();
+        let mut ctx = Context {
+            clients: &mut clients,
+            hooks: self.hooks.as_mut(),
+            meters: &self.meters,
+        };
+        self.core.on_tick(&mut ctx, Instant::now() + delay);
+    }
 }
 
 impl CoreServerState {
@@ -285,16 +398,42 @@ impl CoreServerState {
             chess_rules,
             bughouse_rules,
             players: Players::new(),
+            spectators: Vec::new(),
+            owner: None,
+            banned_names: HashSet::new(),
+            offline_since: HashMap::new(),
+            disconnected_since: HashMap::new(),
+            kick_votes: HashMap::new(),
             scores: Scores::new(),
+            chat: VecDeque::new(),
+            chat_send_times: HashMap::new(),
             match_history: Vec::new(),
             game_state: None,
+            contest_result: None,
             last_activity: now,
+            snapshot_pending: true,
+            last_snapshot: None,
             board_assignment_override: None,
         };
         assert!(self.contests.insert(id.clone(), contest).is_none());
         id
     }
 
+    fn restore_contests(&mut self, snapshots: Vec<ContestSnapshot>, now: Instant) {
+        for snapshot in snapshots {
+            let contest_id = snapshot.contest_id.clone();
+            match Contest::restore(snapshot, now) {
+                Ok(contest) => {
+                    info!("Contest {} restored", contest_id);
+                    assert!(self.contests.insert(contest.contest_id.clone(), contest).is_none());
+                },
+                Err(err) => {
+                    warn!("Cannot restore contest {}: {}", contest_id, err);
+                },
+            }
+        }
+    }
+
     fn apply_event(&mut self, ctx: &mut Context, event: IncomingEvent) {
         // Use the same timestamp for the entire event processing. Other code reachable
         // from this function should not call `Instant::now()`. Doing so may cause a race
@@ -332,7 +471,13 @@ impl CoreServerState {
                 return;
             },
             BughouseClientEvent::ReportError(report) => {
-                process_report_error(ctx, client_id, report);
+                let contest = ctx.clients[client_id].contest_id.as_ref().and_then(|id| self.contests.get(id));
+                process_report_error(ctx, client_id, now, contest, report, "unknown", "unknown");
+                return;
+            },
+            BughouseClientEvent::ReportErrorWithContext{ report, git_version, user_agent } => {
+                let contest = ctx.clients[client_id].contest_id.as_ref().and_then(|id| self.contests.get(id));
+                process_report_error(ctx, client_id, now, contest, report, git_version, user_agent);
                 return;
             },
             BughouseClientEvent::Heartbeat => {
@@ -346,6 +491,7 @@ impl CoreServerState {
             BughouseClientEvent::NewContest{ chess_rules, bughouse_rules, .. } => {
                 ctx.clients[client_id].contest_id = None;
                 ctx.clients[client_id].player_id = None;
+                ctx.clients[client_id].spectator_name = None;
                 let contest_id = self.make_contest(now, chess_rules.clone(), bughouse_rules.clone());
                 info!("Contest {} created by client {}", contest_id.0, ctx.clients[client_id].logging_id);
                 Some(contest_id)
@@ -355,6 +501,7 @@ impl CoreServerState {
                 //   contest. This likely indicates a client error.
                 ctx.clients[client_id].contest_id = None;
                 ctx.clients[client_id].player_id = None;
+                ctx.clients[client_id].spectator_name = None;
                 Some(ContestId(contest_id.clone()))
             },
             _ => ctx.clients[client_id].contest_id.clone(),
@@ -381,22 +528,32 @@ impl CoreServerState {
         contest.process_client_event(ctx, client_id, now, event);
         contest.post_process(ctx, now);
         contest.last_activity = now;
+        contest.snapshot_pending = true;
     }
 
     fn on_tick(&mut self, ctx: &mut Context, now: Instant) {
-        self.gc_old_contests(now);
+        self.gc_old_contests(ctx, now);
         self.check_client_connections(ctx, now);
         for contest in self.contests.values_mut() {
+            let progress_before = contest.game_progress();
             contest.test_flags(ctx, now);
             contest.post_process(ctx, now);
+            if contest.game_progress() != progress_before {
+                contest.snapshot_pending = true;
+            }
+            contest.save_snapshot_if_needed(ctx, now);
         }
     }
 
-    fn gc_old_contests(&mut self, now: Instant) {
+    fn gc_old_contests(&mut self, ctx: &mut Context, now: Instant) {
         // Improvement potential. O(1) time GC.
         // Improvement potential. GC unused contests (zero games and/or no players) sooner.
-        self.contests.retain(|_, contest| {
-            contest.last_activity.duration_since(now) <= CONTEST_GC_INACTIVITY_THRESHOLD
+        self.contests.retain(|contest_id, contest| {
+            let keep = now.saturating_duration_since(contest.last_activity) <= CONTEST_GC_INACTIVITY_THRESHOLD;
+            if !keep {
+                ctx.hooks.on_contest_removed(&contest_id.0);
+            }
+            keep
         });
     }
 
@@ -418,19 +575,20 @@ impl CoreServerState {
 
 impl Contest {
     fn test_flags(&mut self, ctx: &mut Context, now: Instant) {
-        if let Some(GameState{ game_start, ref mut game, .. }) = self.game_state {
-            if let Some(game_start) = game_start {
-                if game.status() == BughouseGameStatus::Active {
-                    let game_now = GameInstant::from_now_game_active(game_start, now);
+        if let Some(GameState{ time_pair, ref mut game, .. }) = self.game_state {
+            if let Some(time_pair) = time_pair {
+                if game.status() == BughouseGameStatus::Active && !time_pair.is_paused() {
+                    let game_now = GameInstant::from_pair_game_active(time_pair, now);
                     game.test_flag(game_now);
                     if game.status() != BughouseGameStatus::Active {
-                        update_score_on_game_over(game, &mut self.scores);
+                        update_score_on_game_over(game, &self.players, &mut self.scores);
                         let ev = BughouseServerEvent::GameOver {
                             time: game_now,
                             game_status: game.status(),
                             scores: self.scores.clone(),
                         };
                         self.broadcast(ctx, &ev);
+                        self.reveal_live_chalk(ctx);
                     }
                 }
             }
@@ -438,31 +596,73 @@ impl Contest {
     }
 
     fn broadcast(&self, ctx: &mut Context, event: &BughouseServerEvent) {
-        ctx.hooks.on_server_broadcast_event(event, self.game_state.as_ref(), self.match_history.len() + 1);
-        ctx.clients.broadcast(&self.contest_id, event);
+        ctx.hooks.on_server_broadcast_event(
+            &self.contest_id.0, event, self.game_state.as_ref(), self.match_history.len() + 1
+        );
+        let recipients = ctx.clients.broadcast(&self.contest_id, event);
+        ctx.meters.broadcast_recipients.record(recipients as u64);
+    }
+
+    // Sends the event to the clients of the current game players from the given team.
+    // Events sent this way are not reported to server hooks, because they are private.
+    fn send_to_team(&self, ctx: &mut Context, team: Team, event: &BughouseServerEvent) {
+        let Some(GameState{ ref game, .. }) = self.game_state else {
+            return;
+        };
+        for client in ctx.clients.map.values_mut() {
+            if client.contest_id.as_ref() != Some(&self.contest_id) {
+                continue;
+            }
+            let player_team = client.player_id
+                .and_then(|id| game.find_player(&self.players[id].name))
+                .map(|id| id.team());
+            if player_team == Some(team) {
+                client.send(event.clone());
+            }
+        }
     }
 
     fn process_client_event(
         &mut self, ctx: &mut Context, client_id: ClientId, now: Instant, event: BughouseClientEvent
     ) {
+        if let Some(event_game_id) = event.game_id() {
+            let current_game_id = self.game_state.as_ref().map(|s| s.game_id);
+            if current_game_id != Some(event_game_id) {
+                // Most likely the event was sent before the client learned about a new game.
+                // This is not the client's fault, so don't reply with an error.
+                warn!(
+                    "Client {} sent an event for game {} in contest {}, but current game is {:?}; dropping:\n{:?}",
+                    ctx.clients[client_id].logging_id, event_game_id, self.contest_id.0, current_game_id, event
+                );
+                return;
+            }
+        }
         let result = match event {
             BughouseClientEvent::NewContest{ player_name, .. } => {
                 // The contest was created earlier.
-                self.join_player(ctx, client_id, now, player_name)
+                let result = self.join_player(ctx, client_id, now, player_name.clone());
+                if result.is_ok() {
+                    self.owner = Some(player_name);
+                    self.send_lobby_updated(ctx);
+                }
+                result
             },
-            BughouseClientEvent::Join{ contest_id: _, player_name } => {
-                self.join_player(ctx, client_id, now, player_name)
+            BughouseClientEvent::Join{ contest_id: _, player_name, mode } => {
+                match mode {
+                    JoinMode::Player => self.join_player(ctx, client_id, now, player_name),
+                    JoinMode::Spectator => self.join_spectator(ctx, client_id, now, player_name),
+                }
             },
             BughouseClientEvent::SetTeam{ team } => {
                 self.process_set_team(ctx, client_id, team)
             },
-            BughouseClientEvent::MakeTurn{ turn_input } => {
+            BughouseClientEvent::MakeTurn{ turn_input, .. } => {
                 self.process_make_turn(ctx, client_id, now, turn_input)
             },
-            BughouseClientEvent::CancelPreturn => {
+            BughouseClientEvent::CancelPreturn{ .. } => {
                 self.process_cancel_preturn(ctx, client_id)
             },
-            BughouseClientEvent::Resign => {
+            BughouseClientEvent::Resign{ .. } => {
                 self.process_resign(ctx, client_id, now)
             },
             BughouseClientEvent::SetReady{ is_ready } => {
@@ -471,16 +671,31 @@ impl Contest {
             BughouseClientEvent::Leave => {
                 self.process_leave(ctx, client_id)
             },
-            BughouseClientEvent::UpdateChalkDrawing{ drawing } => {
+            BughouseClientEvent::UpdateChalkDrawing{ drawing, .. } => {
                 self.process_update_chalk_drawing(ctx, client_id, drawing)
             },
+            BughouseClientEvent::SendPartnerMessage{ message, .. } => {
+                self.process_send_partner_message(ctx, client_id, message)
+            },
             BughouseClientEvent::RequestExport{ format } => {
                 self.process_request_export(ctx, client_id, format)
             },
+            BughouseClientEvent::SendChatMessage{ channel, text } => {
+                self.process_send_chat_message(ctx, client_id, now, channel, text)
+            },
+            BughouseClientEvent::KickPlayer{ player_name, ban } => {
+                self.process_kick_player(ctx, client_id, player_name, ban)
+            },
+            BughouseClientEvent::TransferOwnership{ player_name } => {
+                self.process_transfer_ownership(ctx, client_id, player_name)
+            },
+            BughouseClientEvent::VoteKick{ player_name } => {
+                self.process_vote_kick(ctx, client_id, now, player_name)
+            },
             BughouseClientEvent::ReportPerformace(..) => {
                 unreachable!("Contest-independent event must be processed separately");
             },
-            BughouseClientEvent::ReportError(..) => {
+            BughouseClientEvent::ReportError(..) | BughouseClientEvent::ReportErrorWithContext{ .. } => {
                 unreachable!("Contest-independent event must be processed separately");
             },
             BughouseClientEvent::Heartbeat => {
@@ -497,11 +712,12 @@ impl Contest {
     ) -> EventResult {
         assert!(ctx.clients[client_id].contest_id.is_none());
         assert!(ctx.clients[client_id].player_id.is_none());
-        if let Some(ref game_state) = self.game_state {
+        if self.banned_names.contains(&player_name) {
+            return Err(format!("Cannot join: player \"{}\" is banned from the contest", player_name));
+        }
+        if self.game_state.is_some() {
             let Some(player_id) = self.players.find_by_name(&player_name) else {
-                // Improvement potential. Allow joining mid-game in individual mode.
-                //   Q. How to balance score in this case? Should we switch to negative numbers?
-                return Err("Cannot join: game has already started".to_owned());
+                return self.join_player_mid_game(ctx, client_id, now, player_name);
             };
             let existing_client_id = ctx.clients.map.iter().find_map(
                 |(&id, c)| if c.player_id == Some(player_id) { Some(id) } else { None }
@@ -520,12 +736,13 @@ impl Contest {
             // team in FixedTeam mode.
             self.send_lobby_updated(ctx);
             ctx.clients[client_id].send(self.make_game_start_event(now, Some(player_id)));
-            let chalkboard = game_state.chalkboard.clone();
+            let chalkboard = self.chalkboard_for(Some(player_id));
             ctx.clients[client_id].send(BughouseServerEvent::ChalkboardUpdated{ chalkboard });
+            self.send_chat_history(ctx, client_id, &player_name);
+            self.send_contest_result(ctx, client_id);
             Ok(())
         } else {
-            // TODO: Allow to kick players from the lobby when the old client is offline.
-            if self.players.find_by_name(&player_name).is_some() {
+            if self.players.find_by_name(&player_name).is_some() || self.spectators.contains(&player_name) {
                 return Err(format!("Cannot join: player \"{}\" already exists", player_name));
             }
             if !is_valid_player_name(&player_name) {
@@ -539,16 +756,90 @@ impl Contest {
             let player_id = self.players.add_player(Player {
                 name: player_name,
                 fixed_team: None,
-                is_online: true,
+                presence: PlayerPresence::Online,
                 is_ready: false,
             });
             ctx.clients[client_id].player_id = Some(player_id);
             ctx.clients[client_id].send(self.make_contest_welcome_event());
             self.send_lobby_updated(ctx);
+            self.send_chat_history(ctx, client_id, &self.players[player_id].name);
             Ok(())
         }
     }
 
+    // Adds a new player while a game is running. The player observes the current game and is
+    // queued for the next one. Scores stay fair thanks to `Scores::per_player_average`.
+    fn join_player_mid_game(
+        &mut self, ctx: &mut Context, client_id: ClientId, now: Instant, player_name: String
+    ) -> EventResult {
+        assert!(self.game_state.is_some());
+        if self.bughouse_rules.teaming != Teaming::IndividualMode {
+            return Err("Cannot join: game has already started".to_owned());
+        }
+        if self.contest_result.is_some() {
+            return Err("Cannot join: contest is over".to_owned());
+        }
+        if self.spectators.contains(&player_name) {
+            return Err(format!("Cannot join: player \"{}\" already exists", player_name));
+        }
+        if !is_valid_player_name(&player_name) {
+            return Err(format!("Invalid player name: \"{}\"", player_name))
+        }
+        info!(
+            "Client {} join contest {} mid-game as {}",
+            ctx.clients[client_id].logging_id, self.contest_id.0, player_name
+        );
+        ctx.clients[client_id].contest_id = Some(self.contest_id.clone());
+        let player_id = self.players.add_player(Player {
+            name: player_name,
+            fixed_team: None,
+            presence: PlayerPresence::Online,
+            is_ready: false,
+        });
+        ctx.clients[client_id].player_id = Some(player_id);
+        ctx.clients[client_id].send(self.make_contest_welcome_event());
+        self.send_lobby_updated(ctx);
+        ctx.clients[client_id].send(self.make_game_start_event(now, Some(player_id)));
+        let chalkboard = self.chalkboard_for(Some(player_id));
+        ctx.clients[client_id].send(BughouseServerEvent::ChalkboardUpdated{ chalkboard });
+        self.send_chat_history(ctx, client_id, &self.players[player_id].name);
+        Ok(())
+    }
+
+    fn join_spectator(
+        &mut self, ctx: &mut Context, client_id: ClientId, now: Instant, spectator_name: String
+    ) -> EventResult {
+        assert!(ctx.clients[client_id].contest_id.is_none());
+        assert!(ctx.clients[client_id].spectator_name.is_none());
+        if self.banned_names.contains(&spectator_name) {
+            return Err(format!("Cannot join: player \"{}\" is banned from the contest", spectator_name));
+        }
+        if self.players.find_by_name(&spectator_name).is_some() || self.spectators.contains(&spectator_name) {
+            return Err(format!("Cannot join: player \"{}\" already exists", spectator_name));
+        }
+        if !is_valid_player_name(&spectator_name) {
+            return Err(format!("Invalid player name: \"{}\"", spectator_name))
+        }
+        info!(
+            "Client {} join contest {} as spectator {}",
+            ctx.clients[client_id].logging_id, self.contest_id.0, spectator_name
+        );
+        ctx.clients[client_id].contest_id = Some(self.contest_id.clone());
+        ctx.clients[client_id].spectator_name = Some(spectator_name.clone());
+        self.spectators.push(spectator_name.clone());
+        self.spectators.sort();
+        ctx.clients[client_id].send(self.make_contest_welcome_event());
+        self.send_lobby_updated(ctx);
+        if self.game_state.is_some() {
+            ctx.clients[client_id].send(self.make_game_start_event(now, None));
+            let chalkboard = self.chalkboard_for(None);
+            ctx.clients[client_id].send(BughouseServerEvent::ChalkboardUpdated{ chalkboard });
+        }
+        self.send_chat_history(ctx, client_id, &spectator_name);
+        self.send_contest_result(ctx, client_id);
+        Ok(())
+    }
+
     fn process_set_team(&mut self, ctx: &mut Context, client_id: ClientId, team: Team) -> EventResult {
         let Some(player_id) = ctx.clients[client_id].player_id else {
             return Err("Cannot set team: not joined".to_owned());
@@ -564,7 +855,7 @@ impl Contest {
     fn process_make_turn(
         &mut self, ctx: &mut Context, client_id: ClientId, now: Instant, turn_input: TurnInput
     ) -> EventResult {
-        let Some(GameState{ ref mut game_start, ref mut game, ref mut preturns, .. }) = self.game_state else {
+        let Some(GameState{ ref mut time_pair, ref mut game, ref mut preturns, .. }) = self.game_state else {
             return Err("Cannot make turn: no game in progress".to_owned());
         };
         let Some(player_id) = ctx.clients[client_id].player_id else {
@@ -577,20 +868,23 @@ impl Contest {
         let mode = game.turn_mode_for_player(player_bughouse_id);
         match mode {
             Ok(TurnMode::Normal) => {
+                if matches!(time_pair, Some(pair) if pair.is_paused()) {
+                    return Err("Cannot make turn: game is paused".to_owned());
+                }
                 let mut turns = vec![];
-                let game_now = GameInstant::from_now_game_maybe_active(*game_start, now);
+                let game_now = GameInstant::from_pair_game_maybe_active(*time_pair, now);
                 match apply_turn(
-                    game_now, player_bughouse_id, turn_input, game, scores
+                    game_now, player_bughouse_id, turn_input, game, &self.players, scores
                 ) {
                     Ok(turn_event) => {
-                        if game_start.is_none() {
-                            *game_start = Some(now);
+                        if time_pair.is_none() {
+                            *time_pair = Some(WallGameTimePair::new(now, game_now));
                         }
                         turns.push(turn_event);
                         let opponent_bughouse_id = player_bughouse_id.opponent();
                         if let Some(preturn) = preturns.remove(&opponent_bughouse_id) {
                             if let Ok(preturn_event) = apply_turn(
-                                game_now, opponent_bughouse_id, preturn, game, scores
+                                game_now, opponent_bughouse_id, preturn, game, &self.players, scores
                             ) {
                                 turns.push(preturn_event);
                             }
@@ -601,12 +895,16 @@ impl Contest {
                         return Err(format!("Impossible turn: {:?}", error));
                     },
                 }
+                let game_status = game.status();
                 let ev = BughouseServerEvent::TurnsMade {
                     turns,
-                    game_status: game.status(),
+                    game_status,
                     scores: scores.clone(),
                 };
                 self.broadcast(ctx, &ev);
+                if game_status != BughouseGameStatus::Active {
+                    self.reveal_live_chalk(ctx);
+                }
                 Ok(())
             },
             Ok(TurnMode::Preturn) => {
@@ -641,7 +939,7 @@ impl Contest {
     }
 
     fn process_resign(&mut self, ctx: &mut Context, client_id: ClientId, now: Instant) -> EventResult {
-        let Some(GameState{ ref mut game, game_start, .. }) = self.game_state else {
+        let Some(GameState{ ref mut game, time_pair, .. }) = self.game_state else {
             return Err("Cannot resign: no game in progress".to_owned());
         };
         if game.status() != BughouseGameStatus::Active {
@@ -658,15 +956,16 @@ impl Contest {
             VictoryReason::Resignation
         );
         let scores = &mut self.scores;
-        let game_now = GameInstant::from_now_game_maybe_active(game_start, now);
+        let game_now = GameInstant::from_pair_game_maybe_active(time_pair, now);
         game.set_status(status, game_now);
-        update_score_on_game_over(game, scores);
+        update_score_on_game_over(game, &self.players, scores);
         let ev = BughouseServerEvent::GameOver {
             time: game_now,
             game_status: status,
             scores: scores.clone(),
         };
         self.broadcast(ctx, &ev);
+        self.reveal_live_chalk(ctx);
         Ok(())
     }
 
@@ -679,6 +978,9 @@ impl Contest {
                 return Err("Cannot update readiness: game still in progress".to_owned());
             }
         }
+        if self.contest_result.is_some() {
+            return Err("Cannot update readiness: contest is over".to_owned());
+        }
         self.players[player_id].is_ready = is_ready;
         self.send_lobby_updated(ctx);
         Ok(())
@@ -704,15 +1006,167 @@ impl Contest {
         let Some(player_id) = ctx.clients[client_id].player_id else {
             return Err("Cannot update chalk drawing: not joined".to_owned());
         };
+        let player_name = &self.players[player_id].name;
         if game.status() == BughouseGameStatus::Active {
-            return Err("Cannot update chalk drawing: can draw only after game is over".to_owned());
+            if !self.bughouse_rules.live_chalk {
+                return Err("Cannot update chalk drawing: can draw only after game is over".to_owned());
+            }
+            if game.find_player(player_name).is_none() {
+                return Err("Cannot update chalk drawing: player does not participate".to_owned());
+            }
+        }
+        chalkboard.set_drawing(player_name.clone(), drawing);
+        self.send_chalkboard_updated(ctx);
+        Ok(())
+    }
+
+    // While the game is active, drawings are visible only to the team and to spectators.
+    // Players who do not participate in the current game see no drawings: they could join
+    // the next game on either team, so they are treated like opponents. After the game is
+    // over all drawings become public.
+    fn chalkboard_for(&self, player_id: Option<PlayerId>) -> Chalkboard {
+        let Some(GameState{ ref game, ref chalkboard, .. }) = self.game_state else {
+            panic!("Expected ContestState::Game");
+        };
+        let Some(player_id) = player_id else {
+            return chalkboard.clone();
+        };
+        if game.status() != BughouseGameStatus::Active {
+            return chalkboard.clone();
+        }
+        let team_of = |name: &str| game.find_player(name).map(|id| id.team());
+        let viewer_team = team_of(&self.players[player_id].name);
+        chalkboard.filtered(|name| viewer_team.is_some() && team_of(name) == viewer_team)
+    }
+
+    // Should be called when the game is over, so that players could see drawings made by
+    // the other team during the game.
+    fn reveal_live_chalk(&self, ctx: &mut Context) {
+        if self.bughouse_rules.live_chalk {
+            self.send_chalkboard_updated(ctx);
+        }
+    }
+
+    fn send_chalkboard_updated(&self, ctx: &mut Context) {
+        let Some(GameState{ ref game, ref chalkboard, .. }) = self.game_state else {
+            return;
+        };
+        if game.status() != BughouseGameStatus::Active {
+            let chalkboard = chalkboard.clone();
+            self.broadcast(ctx, &BughouseServerEvent::ChalkboardUpdated{ chalkboard });
+            return;
+        }
+        // Live drawings are private, so they are not reported to server hooks.
+        for client in ctx.clients.map.values_mut() {
+            if client.contest_id.as_ref() == Some(&self.contest_id) {
+                let chalkboard = self.chalkboard_for(client.player_id);
+                client.send(BughouseServerEvent::ChalkboardUpdated{ chalkboard });
+            }
+        }
+    }
+
+    fn process_send_partner_message(
+        &mut self, ctx: &mut Context, client_id: ClientId, message: PartnerMessage
+    ) -> EventResult {
+        let Some(GameState{ ref mut game, .. }) = self.game_state else {
+            return Err("Cannot send partner message: no game in progress".to_owned());
+        };
+        let Some(player_id) = ctx.clients[client_id].player_id else {
+            return Err("Cannot send partner message: not joined".to_owned());
+        };
+        let Some(player_bughouse_id) = game.find_player(&self.players[player_id].name) else {
+            return Err("Cannot send partner message: player does not participate".to_owned());
+        };
+        if game.status() != BughouseGameStatus::Active {
+            return Err("Cannot send partner message: game already over".to_owned());
         }
-        chalkboard.set_drawing(self.players[player_id].name.clone(), drawing);
-        let chalkboard = chalkboard.clone();
-        self.broadcast(ctx, &BughouseServerEvent::ChalkboardUpdated{ chalkboard });
+        let record = game.add_partner_message(player_bughouse_id, message);
+        let ev = BughouseServerEvent::PartnerMessageReceived{ record };
+        self.send_to_team(ctx, player_bughouse_id.team(), &ev);
         Ok(())
     }
 
+    fn process_send_chat_message(
+        &mut self, ctx: &mut Context, client_id: ClientId, now: Instant, channel: ChatChannel, text: String
+    ) -> EventResult {
+        let player_id = ctx.clients[client_id].player_id;
+        let sender = match (player_id, &ctx.clients[client_id].spectator_name) {
+            (Some(id), _) => self.players[id].name.clone(),
+            (None, Some(name)) => name.clone(),
+            (None, None) => return Err("Cannot send chat message: not joined".to_owned()),
+        };
+        let text = text.trim().to_owned();
+        if text.is_empty() {
+            return Err("Cannot send chat message: message is empty".to_owned());
+        }
+        if text.chars().count() > MAX_CHAT_MESSAGE_LENGTH {
+            return Err(format!(
+                "Cannot send chat message: message is longer than {} characters", MAX_CHAT_MESSAGE_LENGTH
+            ));
+        }
+        let recipients = match channel {
+            ChatChannel::All => None,
+            ChatChannel::Team => {
+                let Some(team) = player_id.and_then(|id| self.player_team(id)) else {
+                    return Err("Cannot send chat message: not in a team".to_owned());
+                };
+                Some(self.players.map.iter()
+                    .filter(|(id, _)| self.player_team(**id) == Some(team))
+                    .map(|(_, p)| p.name.clone())
+                    .sorted()
+                    .collect_vec())
+            },
+        };
+        let send_times = self.chat_send_times.entry(sender.clone()).or_default();
+        while matches!(send_times.front(), Some(&t) if now.duration_since(t) >= CHAT_RATE_LIMIT_WINDOW) {
+            send_times.pop_front();
+        }
+        if send_times.len() >= CHAT_RATE_LIMIT_MESSAGES {
+            return Err("Cannot send chat message: too many messages, please wait".to_owned());
+        }
+        send_times.push_back(now);
+
+        let message = ChatMessage{ sender, channel, text };
+        let ev = BughouseServerEvent::ChatMessageReceived{ message: message.clone() };
+        match &recipients {
+            None => self.broadcast(ctx, &ev),
+            Some(names) => {
+                // Team messages are private, so they are not reported to server hooks.
+                for client in ctx.clients.map.values_mut() {
+                    if client.contest_id.as_ref() != Some(&self.contest_id) {
+                        continue;
+                    }
+                    let client_name = client.player_id.map(|id| &self.players[id].name);
+                    if matches!(client_name, Some(name) if names.contains(name)) {
+                        client.send(ev.clone());
+                    }
+                }
+            },
+        }
+        self.chat.push_back(ChatRecord{ message, recipients });
+        while self.chat.len() > MAX_CHAT_HISTORY {
+            self.chat.pop_front();
+        }
+        Ok(())
+    }
+
+    fn send_chat_history(&self, ctx: &mut Context, client_id: ClientId, viewer_name: &str) {
+        let messages = self.chat.iter()
+            .filter(|r| r.visible_to(viewer_name))
+            .map(|r| r.message.clone())
+            .collect();
+        ctx.clients[client_id].send(BughouseServerEvent::ChatHistory{ messages });
+    }
+
+    // Team in the current game or, if the player doesn't participate in it, the fixed team.
+    fn player_team(&self, player_id: PlayerId) -> Option<Team> {
+        let player = &self.players[player_id];
+        let game_team = self.game_state.as_ref()
+            .and_then(|s| s.game.find_player(&player.name))
+            .map(|id| id.team());
+        game_team.or(player.fixed_team)
+    }
+
     fn process_request_export(
         &self, ctx: &mut Context, client_id: ClientId, format: BughouseExportFormat
     ) -> EventResult {
@@ -720,19 +1174,331 @@ impl Contest {
             return Err("Cannot export: no game in progress".to_owned());
         };
         let all_games = self.match_history.iter().chain(iter::once(game));
-        let content = all_games.enumerate().map(|(round, game)| {
-            pgn::export_to_bpgn(format, game, round + 1)
-        }).join("\n");
+        let content = pgn::export_match_to_bpgn(format, all_games);
         ctx.clients[client_id].send(BughouseServerEvent::GameExportReady{ content });
         Ok(())
     }
 
+    fn process_kick_player(
+        &mut self, ctx: &mut Context, client_id: ClientId, player_name: String, ban: bool
+    ) -> EventResult {
+        let Some(player_id) = ctx.clients[client_id].player_id else {
+            return Err("Cannot kick: not joined".to_owned());
+        };
+        if self.owner.as_ref() != Some(&self.players[player_id].name) {
+            return Err("Cannot kick: only contest owner can kick players".to_owned());
+        }
+        let Some(target_id) = self.players.find_by_name(&player_name) else {
+            return Err(format!("Cannot kick: no player \"{}\"", player_name));
+        };
+        if target_id == player_id {
+            return Err("Cannot kick: cannot kick yourself".to_owned());
+        }
+        self.check_can_kick(target_id)?;
+        if ban {
+            self.banned_names.insert(player_name);
+        }
+        self.kick_player(ctx, target_id, ban);
+        Ok(())
+    }
+
+    fn process_transfer_ownership(
+        &mut self, ctx: &mut Context, client_id: ClientId, player_name: String
+    ) -> EventResult {
+        let Some(player_id) = ctx.clients[client_id].player_id else {
+            return Err("Cannot transfer ownership: not joined".to_owned());
+        };
+        if self.owner.as_ref() != Some(&self.players[player_id].name) {
+            return Err("Cannot transfer ownership: not a contest owner".to_owned());
+        }
+        if self.players.find_by_name(&player_name).is_none() {
+            return Err(format!("Cannot transfer ownership: no player \"{}\"", player_name));
+        }
+        info!("Contest {} ownership transferred to {}", self.contest_id.0, player_name);
+        self.owner = Some(player_name);
+        self.send_lobby_updated(ctx);
+        Ok(())
+    }
+
+    fn process_vote_kick(
+        &mut self, ctx: &mut Context, client_id: ClientId, now: Instant, player_name: String
+    ) -> EventResult {
+        let Some(voter_id) = ctx.clients[client_id].player_id else {
+            return Err("Cannot vote to kick: not joined".to_owned());
+        };
+        let Some(target_id) = self.players.find_by_name(&player_name) else {
+            return Err(format!("Cannot vote to kick: no player \"{}\"", player_name));
+        };
+        if target_id == voter_id {
+            return Err("Cannot vote to kick: cannot kick yourself".to_owned());
+        }
+        let offline_for = self.offline_since.get(&target_id).map(|&t| now.saturating_duration_since(t));
+        if !matches!(offline_for, Some(d) if d >= KICK_VOTE_OFFLINE_THRESHOLD) {
+            return Err(format!(
+                "Cannot vote to kick: player must be offline for at least {} seconds",
+                KICK_VOTE_OFFLINE_THRESHOLD.as_secs()
+            ));
+        }
+        self.check_can_kick(target_id)?;
+        let votes = self.kick_votes.entry(target_id).or_default();
+        votes.insert(voter_id);
+        // Only online players are eligible to vote. Kick when they have a strict majority.
+        let num_voters = self.players.map.keys()
+            .filter(|id| **id != target_id && !self.offline_since.contains_key(id))
+            .count();
+        let num_votes = votes.iter().filter(|id| !self.offline_since.contains_key(id)).count();
+        if num_votes * 2 > num_voters {
+            self.kick_player(ctx, target_id, false);
+        }
+        Ok(())
+    }
+
+    fn check_can_kick(&self, target_id: PlayerId) -> EventResult {
+        if let Some(GameState{ ref game, .. }) = self.game_state {
+            // Improvement potential. Allow to replace a player in fixed teams mode.
+            if self.bughouse_rules.teaming == Teaming::FixedTeams {
+                return Err("Cannot kick: teams are fixed after the contest has started".to_owned());
+            }
+            if game.status() == BughouseGameStatus::Active
+                && game.find_player(&self.players[target_id].name).is_some()
+            {
+                return Err("Cannot kick: player participates in the current game".to_owned());
+            }
+        }
+        Ok(())
+    }
+
+    fn kick_player(&mut self, ctx: &mut Context, target_id: PlayerId, banned: bool) {
+        let Some(player) = self.players.map.remove(&target_id) else {
+            return;
+        };
+        info!("Player {} kicked from contest {} (banned: {})", player.name, self.contest_id.0, banned);
+        self.offline_since.remove(&target_id);
+        self.kick_votes.remove(&target_id);
+        for voters in self.kick_votes.values_mut() {
+            voters.remove(&target_id);
+        }
+        for client in ctx.clients.map.values_mut() {
+            if client.contest_id.as_ref() == Some(&self.contest_id) && client.player_id == Some(target_id) {
+                client.contest_id = None;
+                client.player_id = None;
+                client.send(BughouseServerEvent::Kicked{ banned });
+            }
+        }
+        self.send_lobby_updated(ctx);
+    }
+
+    fn update_presence(&mut self, ctx: &mut Context, now: Instant) {
+        let mut presence_by_player = HashMap::new();
+        for client in ctx.clients.map.values() {
+            if client.contest_id.as_ref() != Some(&self.contest_id) {
+                continue;
+            }
+            if let Some(player_id) = client.player_id {
+                let presence = if client.heart.healthy() {
+                    PlayerPresence::Online
+                } else {
+                    PlayerPresence::TemporarilyLost
+                };
+                // A player could have several clients for a short while when reconnecting.
+                let entry = presence_by_player.entry(player_id).or_insert(presence);
+                if presence == PlayerPresence::Online {
+                    *entry = presence;
+                }
+            }
+        }
+        let mut presence_changes = vec![];
+        for (id, player) in self.players.map.iter_mut() {
+            let presence = presence_by_player.get(id).copied().unwrap_or(PlayerPresence::Offline);
+            if player.presence != presence {
+                player.presence = presence;
+                player.is_ready &= presence != PlayerPresence::Offline;
+                presence_changes.push((player.name.clone(), presence));
+            }
+        }
+        for (player_name, presence) in presence_changes {
+            self.broadcast(ctx, &BughouseServerEvent::PlayerPresenceChanged{ player_name, presence });
+        }
+
+        self.offline_since.retain(|id, _| self.players.map.contains_key(id));
+        self.disconnected_since.retain(|id, _| self.players.map.contains_key(id));
+        self.kick_votes.retain(|id, _| self.players.map.contains_key(id));
+        for (id, player) in self.players.map.iter() {
+            if player.is_online() {
+                self.offline_since.remove(id);
+                self.kick_votes.remove(id);
+            } else {
+                self.offline_since.entry(*id).or_insert(now);
+            }
+            if player.presence == PlayerPresence::Offline {
+                self.disconnected_since.entry(*id).or_insert(now);
+            } else {
+                self.disconnected_since.remove(id);
+            }
+        }
+
+        // Pass ownership to the longest-standing online player if the owner is gone.
+        let owner_present = matches!(&self.owner, Some(name) if self.players.find_by_name(name).is_some());
+        if !owner_present {
+            let new_owner = self.players.map.iter()
+                .sorted_by_key(|(id, _)| (self.offline_since.contains_key(id), id.0))
+                .next()
+                .map(|(_, p)| p.name.clone());
+            if new_owner != self.owner {
+                self.owner = new_owner;
+                self.send_lobby_updated(ctx);
+            }
+        }
+    }
+
+    fn apply_disconnect_policy(&mut self, ctx: &mut Context, now: Instant) {
+        let Some(GameState{
+            ref mut game, ref mut time_pair, ref mut disconnect_pause_start,
+            ref mut disconnect_pause_total, ..
+        }) = self.game_state else {
+            return;
+        };
+        if game.status() != BughouseGameStatus::Active {
+            return;
+        }
+        let disconnected_players = game.players().into_iter().filter(|p| {
+            match self.players.find_by_name(&p.name) {
+                Some(id) => self.players[id].presence == PlayerPresence::Offline,
+                None => true,
+            }
+        }).collect_vec();
+        match self.bughouse_rules.disconnect_policy {
+            DisconnectPolicy::Ignore => {},
+            DisconnectPolicy::Pause{ max_pause } => {
+                let Some(pair) = *time_pair else {
+                    // Clocks are not running yet.
+                    return;
+                };
+                if let Some(pause_start) = *disconnect_pause_start {
+                    let pause_duration = now.saturating_duration_since(pause_start);
+                    let budget_exhausted = *disconnect_pause_total + pause_duration >= max_pause;
+                    if disconnected_players.is_empty() || budget_exhausted {
+                        let pair = pair.resumed(now);
+                        *time_pair = Some(pair);
+                        *disconnect_pause_start = None;
+                        *disconnect_pause_total += pause_duration;
+                        let time = GameInstant::from_pair_game_active(pair, now);
+                        self.broadcast(ctx, &BughouseServerEvent::GameResumed{ time });
+                    }
+                } else if !disconnected_players.is_empty() && !pair.is_paused()
+                    && *disconnect_pause_total < max_pause
+                {
+                    let pair = pair.paused(now);
+                    *time_pair = Some(pair);
+                    *disconnect_pause_start = Some(now);
+                    let time = GameInstant::from_pair_game_active(pair, now);
+                    info!(
+                        "Game {} in contest {} paused: {} disconnected",
+                        self.game_progress().unwrap().0, self.contest_id.0,
+                        disconnected_players.iter().map(|p| &p.name).join(", ")
+                    );
+                    self.broadcast(ctx, &BughouseServerEvent::GamePaused{ time });
+                }
+            },
+            DisconnectPolicy::Forfeit{ grace_period } => {
+                let forfeiting_player = disconnected_players.iter().find(|p| {
+                    match self.players.find_by_name(&p.name) {
+                        Some(id) => matches!(
+                            self.disconnected_since.get(&id),
+                            Some(&t) if now.saturating_duration_since(t) >= grace_period
+                        ),
+                        None => true,
+                    }
+                });
+                let Some(forfeiting_player) = forfeiting_player else {
+                    return;
+                };
+                let status = BughouseGameStatus::Victory(
+                    forfeiting_player.id.team().opponent(),
+                    VictoryReason::Forfeit
+                );
+                let game_now = GameInstant::from_pair_game_maybe_active(*time_pair, now);
+                game.set_status(status, game_now);
+                update_score_on_game_over(game, &self.players, &mut self.scores);
+                let ev = BughouseServerEvent::GameOver {
+                    time: game_now,
+                    game_status: status,
+                    scores: self.scores.clone(),
+                };
+                self.broadcast(ctx, &ev);
+                self.reveal_live_chalk(ctx);
+            },
+        }
+    }
+
+    fn end_contest_if_match_over(&mut self, ctx: &mut Context) {
+        if self.contest_result.is_some() {
+            return;
+        }
+        let Some(GameState{ ref game, .. }) = self.game_state else {
+            return;
+        };
+        if game.status() == BughouseGameStatus::Active {
+            return;
+        }
+        let games_played = self.match_history.len() + 1;
+        let Some(winners) = match_winners(
+            self.bughouse_rules.match_length, self.bughouse_rules.teaming, &self.scores,
+            games_played, &self.players
+        ) else {
+            return;
+        };
+        let result = ContestResult{ scores: self.scores.clone(), games_played, winners };
+        info!("Contest {} is over after {} games", self.contest_id.0, games_played);
+        let all_games = self.match_history.iter().chain(iter::once(game)).cloned().collect_vec();
+        ctx.hooks.on_contest_over(&self.contest_id.0, &self.bughouse_rules, &result, &all_games);
+        self.contest_result = Some(result.clone());
+        self.reset_readiness();
+        self.send_lobby_updated(ctx);
+        self.broadcast(ctx, &BughouseServerEvent::ContestOver{ result });
+    }
+
+    fn send_contest_result(&self, ctx: &mut Context, client_id: ClientId) {
+        if let Some(result) = &self.contest_result {
+            ctx.clients[client_id].send(BughouseServerEvent::ContestOver{ result: result.clone() });
+        }
+    }
+
+    // Resumes a paused game as soon as all its players are online.
+    fn resume_game_if_possible(&mut self, ctx: &mut Context, now: Instant) {
+        let Some(GameState{
+            ref game, ref mut time_pair, ref mut disconnect_pause_start,
+            ref mut disconnect_pause_total, ..
+        }) = self.game_state else {
+            return;
+        };
+        let Some(pair) = *time_pair else {
+            return;
+        };
+        if !pair.is_paused() || game.status() != BughouseGameStatus::Active {
+            return;
+        }
+        let all_online = game.players().iter().all(|p| {
+            matches!(self.players.find_by_name(&p.name), Some(id) if !self.offline_since.contains_key(&id))
+        });
+        if !all_online {
+            return;
+        }
+        if let Some(pause_start) = disconnect_pause_start.take() {
+            *disconnect_pause_total += now.saturating_duration_since(pause_start);
+        }
+        let pair = pair.resumed(now);
+        *time_pair = Some(pair);
+        info!("Game {} in contest {} resumed", self.game_progress().unwrap().0, self.contest_id.0);
+        let time = GameInstant::from_pair_game_active(pair, now);
+        self.broadcast(ctx, &BughouseServerEvent::GameResumed{ time });
+    }
+
     fn post_process(&mut self, ctx: &mut Context, now: Instant) {
         // Improvement potential: Collapse `send_lobby_updated` events generated during one event
         //   processing cycle. Right now there could up to three: one from the event (SetTeam/SetReady),
         //   one from here and one from `self.start_game`.
         //   Idea: Add `ctx.should_update_lobby` bit and check it in the end.
-        // TODO: Show lobby players as offline when `!c.heart.is_online()`.
         let active_player_ids: HashSet<_> = ctx.clients.map.values().filter_map(|c| c.player_id).collect();
         if self.game_state.is_none() {
             let mut player_removed = false;
@@ -746,19 +1512,21 @@ impl Contest {
             if player_removed {
                 self.send_lobby_updated(ctx);
             }
-        } else {
-            let mut player_online_status_updated = false;
-            for (id, player) in self.players.map.iter_mut() {
-                let is_online = active_player_ids.contains(id);
-                if player.is_online != is_online {
-                    player.is_online = is_online;
-                    player.is_ready &= is_online;
-                    player_online_status_updated = true;
-                }
-            }
-            if player_online_status_updated {
-                self.send_lobby_updated(ctx);
-            }
+        }
+
+        self.update_presence(ctx, now);
+        self.apply_disconnect_policy(ctx, now);
+        self.resume_game_if_possible(ctx, now);
+        self.end_contest_if_match_over(ctx);
+
+        let mut active_spectators = ctx.clients.map.values()
+            .filter(|c| c.contest_id.as_ref() == Some(&self.contest_id))
+            .filter_map(|c| c.spectator_name.clone())
+            .collect_vec();
+        active_spectators.sort();
+        if active_spectators != self.spectators {
+            self.spectators = active_spectators;
+            self.send_lobby_updated(ctx);
         }
 
         let enough_players = self.players.len() >= TOTAL_PLAYERS;
@@ -779,30 +1547,31 @@ impl Contest {
             },
             Teaming::IndividualMode => true,
         };
-        if enough_players && all_ready && teams_ok {
-            let mut previous_players = None;
+        if enough_players && all_ready && teams_ok && self.contest_result.is_none() {
             if let Some(GameState{ ref game, .. }) = self.game_state {
                 assert!(game.status() != BughouseGameStatus::Active,
                     "Players must not be allowed to set is_ready flag while the game is active");
                 self.match_history.push(game.clone());
-                previous_players = Some(game.players().into_iter().map(|p| p.name.clone()).collect());
             }
-            self.start_game(ctx, now, previous_players);
+            self.start_game(ctx, now);
         }
     }
 
-    fn start_game(&mut self, ctx: &mut Context, now: Instant, previous_players: Option<Vec<String>>) {
+    fn start_game(&mut self, ctx: &mut Context, now: Instant) {
         self.reset_readiness();
-        let players = self.assign_boards(previous_players);
+        let players = self.assign_boards();
         let game = BughouseGame::new(
             self.chess_rules.clone(), self.bughouse_rules.clone(), &players
         );
         self.init_scores();
         self.game_state = Some(GameState {
+            game_id: self.match_history.len() + 1,
             game,
-            game_start: None,
+            time_pair: None,
             preturns: HashMap::new(),
             chalkboard: Chalkboard::new(),
+            disconnect_pause_start: None,
+            disconnect_pause_total: Duration::ZERO,
         });
         self.broadcast(ctx, &self.make_game_start_event(now, None));
         self.send_lobby_updated(ctx);  // update readiness flags
@@ -815,6 +1584,7 @@ impl Contest {
                 assert!(self.scores.per_team.is_empty());
                 for p in self.players.iter() {
                     self.scores.per_player.entry(p.name.clone()).or_insert(0);
+                    self.scores.games_played.entry(p.name.clone()).or_insert(0);
                 }
             }
         }
@@ -834,21 +1604,139 @@ impl Contest {
             panic!("Expected ContestState::Game");
         };
         let player_bughouse_id = player_id.and_then(|id| game_state.game.find_player(&self.players[id].name));
+        let partner_messages = match player_bughouse_id {
+            Some(id) => game_state.game.partner_messages().iter()
+                .filter(|r| r.player_id.team() == id.team())
+                .copied()
+                .collect(),
+            None => Vec::new(),
+        };
         BughouseServerEvent::GameStarted {
+            game_id: game_state.game_id,
             starting_position: game_state.game.starting_position().clone(),
             players: game_state.game.players(),
             time: current_game_time(game_state, now),
             turn_log: game_state.game.turn_log().iter().map(|t| t.trim_for_sending()).collect(),
             preturn: player_bughouse_id.and_then(|id| game_state.preturns.get(&id)).cloned(),
             game_status: game_state.game.status(),
+            game_paused: game_state.is_paused(),
+            partner_messages,
             scores: self.scores.clone(),
         }
     }
 
+    fn game_progress(&self) -> Option<(usize, BughouseGameStatus)> {
+        self.game_state.as_ref().map(|s| (s.game_id, s.game.status()))
+    }
+
+    fn snapshot(&self, now: Instant) -> ContestSnapshot {
+        ContestSnapshot {
+            contest_id: self.contest_id.0.clone(),
+            chess_rules: self.chess_rules.clone(),
+            bughouse_rules: self.bughouse_rules.clone(),
+            players: self.players.map.iter().sorted_by_key(|(id, _)| id.0).map(|(_, p)| p.clone()).collect(),
+            owner: self.owner.clone(),
+            banned_names: self.banned_names.iter().cloned().sorted().collect(),
+            scores: self.scores.clone(),
+            match_history: self.match_history.iter()
+                .map(|game| snapshot_game(game, finished_game_time(game), None))
+                .collect(),
+            current_game: self.game_state.as_ref().map(|s| {
+                snapshot_game(&s.game, current_game_time(s, now), Some(s.chalkboard.clone()))
+            }),
+            chat: self.chat.iter().cloned().collect(),
+            contest_result: self.contest_result.clone(),
+        }
+    }
+
+    // Restored players are offline until they rejoin. The current game is paused until all
+    // its players are back. This counts as a disconnect, so the disconnect policy (pause budget
+    // or forfeit grace period) applies to players who never return.
+    fn restore(snapshot: ContestSnapshot, now: Instant) -> Result<Contest, String> {
+        let ContestSnapshot{
+            contest_id, chess_rules, bughouse_rules, players, owner, banned_names, scores,
+            match_history, current_game, chat, contest_result
+        } = snapshot;
+        let mut restored_players = Players::new();
+        let mut disconnected_since = HashMap::new();
+        for player in players {
+            let id = restored_players.add_player(Player{ presence: PlayerPresence::Offline, is_ready: false, ..player });
+            disconnected_since.insert(id, now);
+        }
+        let match_history = match_history.iter()
+            .map(|game| restore_game(&chess_rules, &bughouse_rules, game))
+            .collect::<Result<Vec<_>, _>>()?;
+        let game_state = match current_game {
+            Some(snapshot) => {
+                let game = restore_game(&chess_rules, &bughouse_rules, &snapshot)?;
+                // Games without turns do not need to be paused: clocks are not running yet.
+                let mut time_pair = None;
+                let mut disconnect_pause_start = None;
+                if !snapshot.turn_log.is_empty() {
+                    let pair = WallGameTimePair::new(now, snapshot.time);
+                    if game.status() == BughouseGameStatus::Active {
+                        time_pair = Some(pair.paused(now));
+                        disconnect_pause_start = Some(now);
+                    } else {
+                        time_pair = Some(pair);
+                    }
+                }
+                Some(GameState {
+                    game_id: match_history.len() + 1,
+                    game,
+                    time_pair,
+                    preturns: HashMap::new(),
+                    chalkboard: snapshot.chalkboard.unwrap_or_else(Chalkboard::new),
+                    disconnect_pause_start,
+                    disconnect_pause_total: Duration::ZERO,
+                })
+            },
+            None => None,
+        };
+        Ok(Contest {
+            contest_id: ContestId(contest_id),
+            chess_rules,
+            bughouse_rules,
+            players: restored_players,
+            spectators: Vec::new(),
+            owner,
+            banned_names: banned_names.into_iter().collect(),
+            offline_since: HashMap::new(),
+            disconnected_since,
+            kick_votes: HashMap::new(),
+            scores,
+            chat: chat.into(),
+            chat_send_times: HashMap::new(),
+            match_history,
+            game_state,
+            contest_result,
+            last_activity: now,
+            snapshot_pending: false,
+            last_snapshot: None,
+            board_assignment_override: None,
+        })
+    }
+
+    fn save_snapshot_if_needed(&mut self, ctx: &mut Context, now: Instant) {
+        let snapshot_due = match self.last_snapshot {
+            Some(t) => now.saturating_duration_since(t) >= CONTEST_SNAPSHOT_INTERVAL,
+            None => true,
+        };
+        if self.snapshot_pending && snapshot_due {
+            ctx.hooks.on_contest_updated(&self.snapshot(now));
+            self.snapshot_pending = false;
+            self.last_snapshot = Some(now);
+        }
+    }
+
     fn send_lobby_updated(&self, ctx: &mut Context) {
         let player_to_send = self.players.iter().cloned().collect();
         self.broadcast(ctx, &BughouseServerEvent::LobbyUpdated {
             players: player_to_send,
+            spectators: self.spectators.clone(),
+            owner: self.owner.clone(),
+            upcoming_seating: self.upcoming_seating(),
+            next_up: self.next_up_queue(),
         });
     }
 
@@ -856,7 +1744,172 @@ impl Contest {
         self.players.iter_mut().for_each(|p| p.is_ready = false);
     }
 
-    fn assign_boards(&self, previous_players: Option<Vec<String>>) -> Vec<PlayerInGame> {
+    // All games of the contest so far, including the current one.
+    fn all_games(&self) -> impl Iterator<Item = &BughouseGame> {
+        // The latest game is added to `match_history` right before starting the next one.
+        let current_game = self.game_state.as_ref()
+            .filter(|s| s.game_id > self.match_history.len())
+            .map(|s| &s.game);
+        self.match_history.iter().chain(current_game)
+    }
+
+    // Seating for the next game if it's known in advance: always in individual mode (see
+    // `next_up_queue`) and with non-random `SeatRotation` in fixed teams mode. Returns `None`
+    // if there are not enough players.
+    fn upcoming_seating(&self) -> Option<Vec<PlayerInGame>> {
+        match self.bughouse_rules.teaming {
+            Teaming::FixedTeams => self.upcoming_fixed_teams_seating(),
+            Teaming::IndividualMode => self.upcoming_individual_mode_seating(),
+        }
+    }
+
+    fn upcoming_fixed_teams_seating(&self) -> Option<Vec<PlayerInGame>> {
+        let seat_rotation = self.bughouse_rules.seat_rotation;
+        if seat_rotation == SeatRotation::Random {
+            return None;
+        }
+        let previous_game = self.game_state.as_ref().map(|s| &s.game);
+        // Whether teams play colors of the opposite team (in terms of `get_bughouse_force`).
+        let previous_colors_swapped = matches!(previous_game, Some(game) if {
+            self.players.iter().any(|p| matches!(
+                (p.fixed_team, game.find_player(&p.name)),
+                (Some(team), Some(id)) if id.team() != team
+            ))
+        });
+        let colors_swapped = match seat_rotation {
+            SeatRotation::AlternateColors => previous_game.is_some() && !previous_colors_swapped,
+            _ => false,
+        };
+        let mut seating = vec![];
+        for team in Team::iter() {
+            let mut team_players = self.players.map.iter()
+                .filter(|(_, p)| p.fixed_team == Some(team))
+                .sorted_by_key(|(id, _)| id.0)
+                .map(|(_, p)| p.name.clone())
+                .collect_vec();
+            if team_players.len() != TOTAL_PLAYERS_PER_TEAM {
+                return None;
+            }
+            if let Some(game) = previous_game {
+                let previous_boards = team_players.iter()
+                    .map(|name| game.find_player(name).map(|id| id.board_idx))
+                    .collect_vec();
+                if let [Some(board0), Some(board1)] = previous_boards[..] {
+                    assert_ne!(board0, board1);
+                    let keep_order = match seat_rotation {
+                        SeatRotation::Random => unreachable!(),
+                        SeatRotation::AlternateBoards => board0 == BughouseBoard::B,
+                        SeatRotation::AlternateColors | SeatRotation::FixedSeats => board0 == BughouseBoard::A,
+                    };
+                    if !keep_order {
+                        team_players.swap(0, 1);
+                    }
+                }
+            }
+            let colors_team = if colors_swapped { team.opponent() } else { team };
+            seating.extend(BughouseBoard::iter().zip_eq(team_players).map(|(board_idx, name)| PlayerInGame {
+                name,
+                id: BughousePlayerId {
+                    board_idx,
+                    force: get_bughouse_force(colors_team, board_idx)
+                }
+            }));
+        }
+        Some(seating)
+    }
+
+    // Individual mode: all players in the order they are scheduled to play. The first
+    // `TOTAL_PLAYERS` play the next game. Players who played fewer games go first; among them
+    // those who have been waiting longer; ties are broken by join order.
+    fn next_up_queue(&self) -> Vec<String> {
+        if self.bughouse_rules.teaming != Teaming::IndividualMode {
+            return vec![];
+        }
+        let mut games_played = HashMap::new();
+        let mut last_game_played = HashMap::new();
+        for (game_idx, game) in self.all_games().enumerate() {
+            for p in game.players() {
+                *games_played.entry(p.name.clone()).or_insert(0) += 1;
+                last_game_played.insert(p.name, game_idx);
+            }
+        }
+        self.players.map.iter()
+            .sorted_by_key(|(id, p)| (
+                games_played.get(&p.name).copied().unwrap_or(0),
+                last_game_played.get(&p.name).copied(),
+                id.0,
+            ))
+            .map(|(_, p)| p.name.clone())
+            .collect()
+    }
+
+    // Chooses teams among the next `TOTAL_PLAYERS` players from `next_up_queue` so that
+    // partners and opponents repeat as rarely as possible, then seats each player so that
+    // they play White and Black equally often.
+    fn upcoming_individual_mode_seating(&self) -> Option<Vec<PlayerInGame>> {
+        let queue = self.next_up_queue();
+        if queue.len() < TOTAL_PLAYERS {
+            return None;
+        }
+        let current_players = &queue[..TOTAL_PLAYERS];
+        let mut partner_count: HashMap<(String, String), u32> = HashMap::new();
+        let mut opponent_count: HashMap<(String, String), u32> = HashMap::new();
+        let mut white_count: HashMap<String, u32> = HashMap::new();
+        let ordered_pair = |a: &str, b: &str| {
+            if a < b { (a.to_owned(), b.to_owned()) } else { (b.to_owned(), a.to_owned()) }
+        };
+        for game in self.all_games() {
+            let players = game.players();
+            for (p1, p2) in players.iter().tuple_combinations() {
+                let counter = if p1.id.team() == p2.id.team() { &mut partner_count } else { &mut opponent_count };
+                *counter.entry(ordered_pair(&p1.name, &p2.name)).or_insert(0) += 1;
+            }
+            for p in players {
+                if p.id.force == Force::White {
+                    *white_count.entry(p.name).or_insert(0) += 1;
+                }
+            }
+        }
+        let pair_cost = |counter: &HashMap<(String, String), u32>, a: &str, b: &str| {
+            counter.get(&ordered_pair(a, b)).copied().unwrap_or(0)
+        };
+        // Player 0 always goes to the first team; try each partner for them.
+        let teams = (1..TOTAL_PLAYERS).map(|partner_idx| {
+            let (first, second): (Vec<_>, Vec<_>) = (0..TOTAL_PLAYERS)
+                .partition(|&idx| idx == 0 || idx == partner_idx);
+            let first = first.iter().map(|&idx| current_players[idx].clone()).collect_vec();
+            let second = second.iter().map(|&idx| current_players[idx].clone()).collect_vec();
+            (first, second)
+        }).min_by_key(|(first, second)| {
+            let partner_cost = pair_cost(&partner_count, &first[0], &first[1])
+                + pair_cost(&partner_count, &second[0], &second[1]);
+            let opponent_cost = first.iter().cartesian_product(second.iter())
+                .map(|(a, b)| pair_cost(&opponent_count, a, b))
+                .sum::<u32>();
+            partner_cost + opponent_cost
+        }).unwrap();
+        let (red, blue) = teams;
+        let mut seating = vec![];
+        for (team, mut team_players) in [(Team::Red, red), (Team::Blue, blue)] {
+            // Put the player who played White less often on the board where the team plays White.
+            team_players.sort_by_key(|name| white_count.get(name).copied().unwrap_or(0));
+            let white_board = BughouseBoard::iter()
+                .find(|&board_idx| get_bughouse_force(team, board_idx) == Force::White)
+                .unwrap();
+            for (name, board_idx) in team_players.into_iter().zip_eq([white_board, white_board.other()]) {
+                seating.push(PlayerInGame {
+                    name,
+                    id: BughousePlayerId {
+                        board_idx,
+                        force: get_bughouse_force(team, board_idx)
+                    }
+                });
+            }
+        }
+        Some(seating)
+    }
+
+    fn assign_boards(&self) -> Vec<PlayerInGame> {
         if let Some(assignment) = &self.board_assignment_override {
             for player_assignment in assignment {
                 if let Some(player) = self.players.iter().find(|p| p.name == player_assignment.name) {
@@ -868,48 +1921,17 @@ impl Contest {
             return assignment.clone();
         }
 
+        if let Some(seating) = self.upcoming_seating() {
+            return seating;
+        }
+
+        // Fixed teams with `SeatRotation::Random`.
+        assert_eq!(self.bughouse_rules.teaming, Teaming::FixedTeams);
         let mut rng = rand::thread_rng();
         let mut players_per_team = enum_map!{ _ => vec![] };
-        match self.bughouse_rules.teaming {
-            Teaming::FixedTeams => {
-                for p in self.players.iter() {
-                    let team = p.fixed_team.unwrap();
-                    players_per_team[team].push(p.name.clone());
-                }
-            },
-            Teaming::IndividualMode => {
-                // Improvement potential. Instead count the number of times each player participated
-                //   and prioritize those who did less than others.
-                let mut rng = rand::thread_rng();
-                let player_names: HashSet<String> = self.players.iter().map(|p| {
-                    assert!(p.fixed_team.is_none());
-                    p.name.clone()
-                }).collect();
-                let high_priority_players: Vec<String>;
-                let low_priority_players: Vec<String>;
-                if let Some(previous_players) = previous_players {
-                    let previous_player_names: HashSet<String> = previous_players.into_iter().collect();
-                    high_priority_players = player_names.difference(&previous_player_names).cloned().collect();
-                    low_priority_players = previous_player_names.into_iter().collect();
-                } else {
-                    high_priority_players = Vec::new();
-                    low_priority_players = player_names.into_iter().collect();
-                }
-                let num_high_priority_players = high_priority_players.len();
-                let mut current_players: Vec<String> = if num_high_priority_players >= TOTAL_PLAYERS {
-                    high_priority_players.choose_multiple(&mut rng, TOTAL_PLAYERS).cloned().collect()
-                } else {
-                    high_priority_players.into_iter().chain(
-                        low_priority_players.choose_multiple(&mut rng, TOTAL_PLAYERS - num_high_priority_players).cloned()
-                    ).collect()
-                };
-                current_players.shuffle(&mut rng);
-                for team in Team::iter() {
-                    for _ in 0..TOTAL_PLAYERS_PER_TEAM {
-                        players_per_team[team].push(current_players.pop().unwrap().clone());
-                    }
-                }
-            },
+        for p in self.players.iter() {
+            let team = p.fixed_team.unwrap();
+            players_per_team[team].push(p.name.clone());
         }
         players_per_team.into_iter().flat_map(|(team, mut team_players)| {
             team_players.shuffle(&mut rng);
@@ -936,37 +1958,148 @@ fn is_valid_player_name(name: &str) -> bool {
 
 fn current_game_time(game_state: &GameState, now: Instant) -> GameInstant {
     if game_state.game.status() == BughouseGameStatus::Active {
-        GameInstant::from_now_game_maybe_active(game_state.game_start, now)
+        GameInstant::from_pair_game_maybe_active(game_state.time_pair, now)
     } else {
-        // Normally `clock().total_time_elapsed()` should be the same on all boards. But
-        // it could differ in case the of a flag defeat. Consider the following situation:
-        // total time is 300 seconds; on board A the game proceeded normally; on board B
-        // white didn't make any turns. In this case board A clock would report real wall
-        // time (e.g. 300.1s), while board B clock would report exactly 300s, because each
-        // player remaining time is always non-negative.
-        // Also this example shows that the best approximation to real game time is the
-        // minimum of all boards. Everything higher than the minimum is an artifact of not
-        // having checked the flags in time.
-        let elapsed_since_start = BughouseBoard::iter()
-            .map(|board_idx| game_state.game.board(board_idx).clock().total_time_elapsed())
-            .min()
-            .unwrap();
-        GameInstant::from_duration(elapsed_since_start)
+        finished_game_time(&game_state.game)
     }
 }
 
+fn finished_game_time(game: &BughouseGame) -> GameInstant {
+    // Normally `clock().total_time_elapsed()` should be the same on all boards. But
+    // it could differ in case the of a flag defeat. Consider the following situation:
+    // total time is 300 seconds; on board A the game proceeded normally; on board B
+    // white didn't make any turns. In this case board A clock would report real wall
+    // time (e.g. 300.1s), while board B clock would report exactly 300s, because each
+    // player remaining time is always non-negative.
+    // Also this example shows that the best approximation to real game time is the
+    // minimum of all boards. Everything higher than the minimum is an artifact of not
+    // having checked the flags in time.
+    let elapsed_since_start = BughouseBoard::iter()
+        .map(|board_idx| game.board(board_idx).clock().total_time_elapsed())
+        .min()
+        .unwrap();
+    GameInstant::from_duration(elapsed_since_start)
+}
+
+pub fn finished_game_record(game: &BughouseGame) -> FinishedGameRecord {
+    FinishedGameRecord {
+        chess_rules: (**game.chess_rules()).clone(),
+        bughouse_rules: (**game.bughouse_rules()).clone(),
+        game: snapshot_game(game, finished_game_time(game), None),
+    }
+}
+
+// Remaining clock time is not stored in the turn log, so it's recomputed by replaying clocks.
+pub fn finished_game_turns(game: &BughouseGame) -> Vec<TurnRow> {
+    let mut clocks = enum_map!{ _ => Clock::new(game.chess_rules().time_control.clone()) };
+    game.turn_log().iter().enumerate().map(|(turn_index, record)| {
+        let BughousePlayerId{ board_idx, force } = record.player_id;
+        // Approximate measurement disables assertions: persisting must never crash the server.
+        let time = record.time.approximate();
+        clocks[board_idx].new_turn(force.opponent(), time);
+        if record.mode == TurnMode::Normal {
+            // Mirrors `BughouseGame::try_turn`: the first turn on one board starts the clock
+            // on the other board. Nobody has moved there yet, so it's white's turn.
+            let other_clock = &mut clocks[board_idx.other()];
+            if !other_clock.is_active() {
+                other_clock.new_turn(Force::White, time);
+            }
+        }
+        let clock = &clocks[board_idx];
+        let turn = &record.turn_expanded;
+        TurnRow {
+            turn_index: turn_index as i64,
+            board: format!("{:?}", board_idx),
+            force: format!("{:?}", force),
+            player_name: game.board(board_idx).player_name(force).to_owned(),
+            algebraic: turn.algebraic.clone(),
+            game_time_ms: record.time.elapsed_since_start().as_millis() as i64,
+            capture: turn.capture.map(|c| c.piece_kind.to_full_algebraic().to_string()),
+            drop_piece: match turn.turn {
+                Turn::Drop(drop) => Some(drop.piece_kind.to_full_algebraic().to_string()),
+                Turn::Move(_) | Turn::Castle(_) => None,
+            },
+            clock_remaining_ms: clock.time_left(force, time).as_millis() as i64,
+        }
+    }).collect()
+}
+
+pub fn contest_row(contest: &ContestSnapshot, create_time: Option<time::OffsetDateTime>) -> ContestRow {
+    let rules = &contest.bughouse_rules;
+    let (disconnect_policy, disconnect_timeout) = match rules.disconnect_policy {
+        DisconnectPolicy::Ignore => ("Ignore", None),
+        DisconnectPolicy::Pause{ max_pause } => ("Pause", Some(max_pause)),
+        DisconnectPolicy::Forfeit{ grace_period } => ("Forfeit", Some(grace_period)),
+    };
+    ContestRow {
+        contest_id: contest.contest_id.clone(),
+        create_time,
+        owner: contest.owner.clone(),
+        starting_position: format!("{:?}", contest.chess_rules.starting_position),
+        starting_time_sec: contest.chess_rules.time_control.starting_time.as_secs() as i64,
+        teaming: format!("{:?}", rules.teaming),
+        seat_rotation: format!("{:?}", rules.seat_rotation),
+        min_pawn_drop_row: rules.min_pawn_drop_row.to_one_based().into(),
+        max_pawn_drop_row: rules.max_pawn_drop_row.to_one_based().into(),
+        drop_aggression: format!("{:?}", rules.drop_aggression),
+        partner_hints: rules.partner_hints,
+        live_chalk: rules.live_chalk,
+        disconnect_policy: disconnect_policy.to_owned(),
+        disconnect_timeout_sec: disconnect_timeout.map(|d| d.as_secs() as i64),
+        match_length: rules.match_length.to_human_readable(),
+    }
+}
+
+fn snapshot_game(game: &BughouseGame, time: GameInstant, chalkboard: Option<Chalkboard>) -> GameSnapshot {
+    GameSnapshot {
+        starting_position: game.starting_position().clone(),
+        players: game.players(),
+        turn_log: game.turn_log().iter().map(|t| t.trim_for_sending()).collect(),
+        partner_messages: game.partner_messages().clone(),
+        status: game.status(),
+        time,
+        chalkboard,
+    }
+}
+
+fn restore_game(
+    chess_rules: &ChessRules, bughouse_rules: &BughouseRules, snapshot: &GameSnapshot
+) -> Result<BughouseGame, String> {
+    let mut game = BughouseGame::new_with_starting_position(
+        chess_rules.clone(), bughouse_rules.clone(), snapshot.starting_position.clone(), &snapshot.players
+    );
+    let mut partner_messages = snapshot.partner_messages.iter().peekable();
+    for (turn_idx, turn) in snapshot.turn_log.iter().enumerate() {
+        while let Some(record) = partner_messages.next_if(|r| r.turn_idx <= turn_idx) {
+            game.add_partner_message(record.player_id, record.message);
+        }
+        let turn_input = TurnInput::Algebraic(turn.turn_algebraic.clone());
+        game.try_turn_by_player(turn.player_id, &turn_input, TurnMode::Normal, turn.time)
+            .map_err(|err| format!("Cannot replay turn {}: {:?}", turn.turn_algebraic, err))?;
+    }
+    for record in partner_messages {
+        game.add_partner_message(record.player_id, record.message);
+    }
+    if game.status() != snapshot.status {
+        // Game ended for a reason unrelated to the last turn (flag, resign). The exact end
+        // time is not known, so make sure clocks do not complain about going back in time.
+        game.set_status(snapshot.status, snapshot.time.approximate());
+    }
+    Ok(game)
+}
+
 fn apply_turn(
     game_now: GameInstant, player_bughouse_id: BughousePlayerId, turn_input: TurnInput,
-    game: &mut BughouseGame, scores: &mut Scores,
+    game: &mut BughouseGame, players: &Players, scores: &mut Scores,
 ) -> Result<TurnRecord, TurnError> {
     game.try_turn_by_player(player_bughouse_id, &turn_input, TurnMode::Normal, game_now)?;
     if game.status() != BughouseGameStatus::Active {
-        update_score_on_game_over(game, scores);
+        update_score_on_game_over(game, players, scores);
     }
     Ok(game.last_turn_record().unwrap().trim_for_sending())
 }
 
-fn update_score_on_game_over(game: &BughouseGame, scores: &mut Scores) {
+fn update_score_on_game_over(game: &BughouseGame, players: &Players, scores: &mut Scores) {
     let team_scores = match game.status() {
         BughouseGameStatus::Active => panic!("It just so happens that the game here is only mostly over"),
         BughouseGameStatus::Victory(team, _) => {
@@ -979,7 +2112,14 @@ fn update_score_on_game_over(game: &BughouseGame, scores: &mut Scores) {
     match game.bughouse_rules().teaming {
         Teaming::FixedTeams => {
             assert!(scores.per_player.is_empty());
+            // Scores are kept for lobby teams. With `SeatRotation::AlternateColors` a lobby
+            // team plays for the opposite team in terms of `get_bughouse_team` every other game.
+            let colors_swapped = game.players().iter().any(|p| matches!(
+                players.find_by_name(&p.name).and_then(|id| players[id].fixed_team),
+                Some(team) if team != p.id.team()
+            ));
             for (team, score) in team_scores {
+                let team = if colors_swapped { team.opponent() } else { team };
                 *scores.per_team.entry(team).or_insert(0) += score;
             }
         },
@@ -987,23 +2127,115 @@ fn update_score_on_game_over(game: &BughouseGame, scores: &mut Scores) {
             assert!(scores.per_team.is_empty());
             for p in game.players() {
                 *scores.per_player.entry(p.name.clone()).or_insert(0) += team_scores[p.id.team()];
+                *scores.games_played.entry(p.name.clone()).or_insert(0) += 1;
             }
         }
     }
 }
 
-fn process_report_error(ctx: &Context, client_id: ClientId, report: &BughouseClientErrorReport) {
-    // TODO: Save errors to DB.
-    let logging_id = &ctx.clients[client_id].logging_id;
-    match report {
+// Returns the winners if the match is over; see `MatchLength`. Scores use internal units,
+// so limits in points are doubled.
+fn match_winners(
+    match_length: MatchLength, teaming: Teaming, scores: &Scores, games_played: usize,
+    players: &Players
+) -> Option<Vec<String>> {
+    let team_members = |team| {
+        players.iter().filter(|p| p.fixed_team == Some(team)).map(|p| p.name.clone()).sorted().collect_vec()
+    };
+    match teaming {
+        Teaming::FixedTeams => {
+            let red = *scores.per_team.get(&Team::Red).unwrap_or(&0);
+            let blue = *scores.per_team.get(&Team::Blue).unwrap_or(&0);
+            let over = match match_length {
+                MatchLength::Unlimited => false,
+                MatchLength::FirstTo{ points } => cmp::max(red, blue) >= points * 2,
+                MatchLength::BestOf{ games } => {
+                    let games_left = (games as usize).saturating_sub(games_played);
+                    games_left == 0 || red.abs_diff(blue) as usize > games_left * 2
+                },
+            };
+            if !over {
+                return None;
+            }
+            Some(match red.cmp(&blue) {
+                cmp::Ordering::Greater => team_members(Team::Red),
+                cmp::Ordering::Less => team_members(Team::Blue),
+                cmp::Ordering::Equal => vec![],
+            })
+        },
+        Teaming::IndividualMode => {
+            let best_score = scores.per_player.values().copied().max().unwrap_or(0);
+            let over = match match_length {
+                MatchLength::Unlimited => false,
+                MatchLength::FirstTo{ points } => best_score >= points * 2,
+                MatchLength::BestOf{ games } => games_played >= games as usize,
+            };
+            if !over {
+                return None;
+            }
+            Some(scores.per_player.iter()
+                .filter(|(_, &score)| score == best_score)
+                .map(|(name, _)| name.clone())
+                .sorted()
+                .collect())
+        },
+    }
+}
+
+fn process_report_error(
+    ctx: &mut Context, client_id: ClientId, now: Instant, contest: Option<&Contest>,
+    report: &BughouseClientErrorReport, git_version: &str, user_agent: &str,
+) {
+    let client = &mut ctx.clients[client_id];
+    let report_times = &mut client.error_report_times;
+    while matches!(report_times.front(), Some(&t) if now.duration_since(t) >= ERROR_REPORT_RATE_LIMIT_WINDOW) {
+        report_times.pop_front();
+    }
+    if report_times.len() >= ERROR_REPORT_RATE_LIMIT_REPORTS {
+        warn!("Client {} sent too many error reports, dropping", client.logging_id);
+        return;
+    }
+    report_times.push_back(now);
+
+    let client = &ctx.clients[client_id];
+    let logging_id = &client.logging_id;
+    let text = |s: &str| truncate_chars(s, MAX_ERROR_REPORT_TEXT_LENGTH);
+    let (kind, message, backtrace) = match report {
         BughouseClientErrorReport::RustPanic{ panic_info, backtrace } => {
+            let (panic_info, backtrace) = (text(panic_info), text(backtrace));
             warn!("Client {logging_id} panicked:\n{panic_info}\nBacktrace: {backtrace}");
+            ("RustPanic", panic_info, Some(backtrace))
         }
         BughouseClientErrorReport::RustError{ message } => {
+            let message = text(message);
             warn!("Client {logging_id} experienced Rust error:\n{message}");
+            ("RustError", message, None)
         }
         BughouseClientErrorReport::UnknownError{ message } => {
+            let message = text(message);
             warn!("Client {logging_id} experienced unknown error:\n{message}");
+            ("UnknownError", message, None)
         }
-    }
+    };
+    let player_name = match (client.player_id, contest) {
+        (Some(player_id), Some(contest)) => Some(contest.players[player_id].name.clone()),
+        _ => client.spectator_name.clone(),
+    };
+    let row = ClientErrorReportRow {
+        report_time: Some(time::OffsetDateTime::now_utc()),
+        git_version: truncate_chars(git_version, MAX_ERROR_REPORT_TAG_LENGTH),
+        server_git_version: crate::my_git_version!().to_owned(),
+        user_agent: truncate_chars(user_agent, MAX_ERROR_REPORT_TAG_LENGTH),
+        kind: kind.to_owned(),
+        message,
+        backtrace,
+        contest_id: contest.map(|c| c.contest_id.0.clone()),
+        game_number: contest.and_then(|c| c.game_state.as_ref()).map(|s| s.game_id as i64),
+        player_name,
+    };
+    ctx.hooks.on_client_error(&row);
+}
+
+fn truncate_chars(s: &str, max_chars: usize) -> String {
+    s.chars().take(max_chars).collect()
 }
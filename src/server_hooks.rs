@@ -1,15 +1,42 @@
 use crate::event::{BughouseClientEvent, BughouseServerEvent};
+use crate::game::BughouseGame;
+use crate::persistence::{ClientErrorReportRow, ContestSnapshot};
+use crate::rules::BughouseRules;
+use crate::scores::ContestResult;
 use crate::server::GameState;
 
 
 pub trait ServerHooks {
     fn on_client_event(&mut self, event: &BughouseClientEvent);
-    fn on_server_broadcast_event(&mut self, event: &BughouseServerEvent, game: Option<&GameState>, round: usize);
+    fn on_client_error(&mut self, report: &ClientErrorReportRow);
+    fn on_server_broadcast_event(
+        &mut self, contest_id: &str, event: &BughouseServerEvent, game: Option<&GameState>, round: usize
+    );
+    // Called whenever contest state might have changed. The latest snapshot for each contest
+    // should be returned from `load_contests` after server restart.
+    fn on_contest_updated(&mut self, contest: &ContestSnapshot);
+    fn on_contest_removed(&mut self, contest_id: &str);
+    // Called once when a contest with limited `MatchLength` ends. `games` are all contest games
+    // in order.
+    fn on_contest_over(
+        &mut self, contest_id: &str, rules: &BughouseRules, result: &ContestResult, games: &[BughouseGame]
+    );
+    // Called once on server start.
+    fn load_contests(&mut self) -> Vec<ContestSnapshot>;
 }
 
 pub struct NoopServerHooks {}
 
 impl ServerHooks for NoopServerHooks {
     fn on_client_event(&mut self, _event: &BughouseClientEvent) {}
-    fn on_server_broadcast_event(&mut self, _event: &BughouseServerEvent, _game: Option<&GameState>, _round: usize) {}
+    fn on_client_error(&mut self, _report: &ClientErrorReportRow) {}
+    fn on_server_broadcast_event(
+        &mut self, _contest_id: &str, _event: &BughouseServerEvent, _game: Option<&GameState>, _round: usize
+    ) {}
+    fn on_contest_updated(&mut self, _contest: &ContestSnapshot) {}
+    fn on_contest_removed(&mut self, _contest_id: &str) {}
+    fn on_contest_over(
+        &mut self, _contest_id: &str, _rules: &BughouseRules, _result: &ContestResult, _games: &[BughouseGame]
+    ) {}
+    fn load_contests(&mut self) -> Vec<ContestSnapshot> { Vec::new() }
 }
@@ -0,0 +1,42 @@
+// Applies schema migrations (see `persistence::apply_migrations`) to an sqlx database. Used by
+// both the game server and the webserver.
+
+use log::warn;
+
+use crate::persistence::{apply_migrations, DatabaseDialect, MigrationDb};
+
+
+pub fn apply_sqlx_migrations<DB: sqlx::Database>(
+    pool: &sqlx::Pool<DB>, dialect: DatabaseDialect
+) -> Result<(), sqlx::Error>
+where
+    for<'c> &'c mut DB::Connection: sqlx::Executor<'c, Database = DB>,
+    for<'a> <DB as sqlx::database::HasArguments<'a>>::Arguments: sqlx::IntoArguments<'a, DB>,
+{
+    let mut db = SqlxMigrationDb { conn: async_std::task::block_on(pool.acquire())? };
+    let applied = apply_migrations(&mut db, dialect)?;
+    if !applied.is_empty() {
+        warn!("Applied schema migrations: {:?}", applied);
+    }
+    Ok(())
+}
+
+// Runs migrations on a single connection, as required by `MigrationDb`.
+struct SqlxMigrationDb<DB: sqlx::Database> {
+    conn: sqlx::pool::PoolConnection<DB>,
+}
+
+impl<DB: sqlx::Database> MigrationDb for SqlxMigrationDb<DB>
+where
+    for<'c> &'c mut DB::Connection: sqlx::Executor<'c, Database = DB>,
+    for<'a> <DB as sqlx::database::HasArguments<'a>>::Arguments: sqlx::IntoArguments<'a, DB>,
+{
+    type Error = sqlx::Error;
+    fn execute(&mut self, sql: &str) -> Result<(), sqlx::Error> {
+        async_std::task::block_on(sqlx::query(sql).execute(&mut *self.conn))?;
+        Ok(())
+    }
+    fn has_rows(&mut self, sql: &str) -> Result<bool, sqlx::Error> {
+        Ok(async_std::task::block_on(sqlx::query(sql).fetch_optional(&mut *self.conn))?.is_some())
+    }
+}
@@ -111,3 +111,88 @@ fn threefold_repetition_draw_ignores_reserve() {
     ").unwrap();
     assert!(game.status() == BughouseGameStatus::Draw(DrawReason::ThreefoldRepetition));
 }
+
+#[test]
+fn drop_mate_piece_needed() {
+    let mut game = bughouse_chess_com();
+    assert_eq!(game.board(BughouseBoard::A).drop_mate_piece_needed(), None);
+    replay_log(&mut game, "
+        1A.e4  1a.f5
+        2A.xf5  2a.a6
+        3A.Bc4  3a.a5
+    ").unwrap();
+    assert_eq!(game.board(BughouseBoard::A).drop_mate_piece_needed(), Some(PieceKind::Pawn));
+    // Not relevant for the player who is not to move.
+    replay_log(&mut game, "1A.d3").unwrap();
+    assert_eq!(game.board(BughouseBoard::A).drop_mate_piece_needed(), None);
+}
+
+#[test]
+fn drop_mate_piece_needed_for_forced_mate() {
+    let mut game = bughouse_chess_com();
+    let board = game.board_mut(BughouseBoard::A);
+    let grid = board.grid_mut();
+    for coord in Coord::all() {
+        grid[coord] = None;
+    }
+    let piece = |kind, force| Some(PieceOnBoard::new(kind, PieceOrigin::Innate, force));
+    grid[Coord::E1] = piece(PieceKind::King, Force::White);
+    grid[Coord::B6] = piece(PieceKind::Pawn, Force::White);
+    grid[Coord::E6] = piece(PieceKind::Bishop, Force::White);
+    grid[Coord::A8] = piece(PieceKind::King, Force::Black);
+    grid[Coord::A7] = piece(PieceKind::Pawn, Force::Black);
+    grid[Coord::B7] = piece(PieceKind::Pawn, Force::Black);
+    assert_eq!(game.board(BughouseBoard::A).drop_mate_piece_needed(), None);
+
+    // No immediate mate, but Nc7+ Kb8 Ra8#.
+    game.board_mut(BughouseBoard::A).reserve_mut(Force::White)[PieceKind::Rook] += 1;
+    assert_eq!(game.board(BughouseBoard::A).drop_mate_piece_needed(), Some(PieceKind::Knight));
+}
+
+#[test]
+fn bpgn_turns_round_trip() {
+    let mut game = bughouse_chess_com();
+    replay_log(&mut game, "1A.e4 1a.d5 1B.Nf3 2A.xd5 1b.e5 2a.Qxd5").unwrap();
+    let bpgn = pgn::export_to_bpgn(pgn::BughouseExportFormat{}, &game, 1);
+    let turns = pgn::parse_bpgn_turns(&bpgn).unwrap();
+    let turn_log = game.turn_log().iter()
+        .map(|t| (t.player_id, t.turn_expanded.algebraic.clone()))
+        .collect::<Vec<_>>();
+    let parsed = turns.iter().map(|t| (t.player_id, t.algebraic.clone())).collect::<Vec<_>>();
+    assert_eq!(parsed, turn_log);
+    assert_eq!(turns.last().unwrap().full_turn_idx, 2);
+    assert!(pgn::parse_bpgn_turns("1A. e4 {unterminated").is_err());
+    assert!(pgn::parse_bpgn_turns("e4").is_err());
+}
+
+#[test]
+fn finished_game_turns_recompute_clocks() {
+    use BughouseBoard::*;
+    let mut game = bughouse_chess_com();
+    let mut expected_clocks = vec![];
+    for (board_idx, turn, t) in [(A, "e4", 1), (B, "d4", 2), (A, "d5", 3), (A, "xd5", 10), (B, "P@e5", 12)] {
+        let now = GameInstant::from_duration(std::time::Duration::from_secs(t));
+        let turn_input = TurnInput::Algebraic(turn.to_owned());
+        game.try_turn(board_idx, &turn_input, TurnMode::Normal, now).unwrap();
+        let force = game.turn_log().last().unwrap().player_id.force;
+        expected_clocks.push(game.board(board_idx).clock().time_left(force, now).as_millis() as i64);
+    }
+    let turns = server::finished_game_turns(&game);
+    let summary = turns.iter()
+        .map(|t| (t.turn_index, t.board.as_str(), t.force.as_str(), t.game_time_ms))
+        .collect::<Vec<_>>();
+    assert_eq!(summary, vec![
+        (0, "A", "White", 1000),
+        (1, "B", "White", 2000),
+        (2, "A", "Black", 3000),
+        (3, "A", "White", 10_000),
+        (4, "B", "Black", 12_000),
+    ]);
+    let clocks = turns.iter().map(|t| t.clock_remaining_ms).collect::<Vec<_>>();
+    assert_eq!(clocks, expected_clocks);
+    assert_eq!(turns[0].player_name, game.board(A).player_name(Force::White));
+    assert_eq!(turns[3].capture.as_deref(), Some("P"));
+    assert_eq!(turns[3].drop_piece, None);
+    assert_eq!(turns[4].capture, None);
+    assert_eq!(turns[4].drop_piece.as_deref(), Some("P"));
+}
@@ -2,12 +2,15 @@
 // In particular, add regression test for trying to make a turn after time ran out
 //   according to the client clock, but the server hasn't confirmed game over yet.
 
-// Improvement potential. Cover all events, including RequestExport and ReportError.
+// Improvement potential. Cover all events, including RequestExport.
 
 mod common;
 
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::iter;
 use std::ops;
+use std::rc::Rc;
 use std::sync::{Arc, Mutex, mpsc};
 use std::time::Duration;
 
@@ -27,9 +30,14 @@ fn default_chess_rules() -> ChessRules {
 fn default_bughouse_rules() -> BughouseRules {
     BughouseRules {
         teaming: Teaming::FixedTeams,
+        seat_rotation: SeatRotation::Random,
         min_pawn_drop_row: SubjectiveRow::from_one_based(2),
         max_pawn_drop_row: SubjectiveRow::from_one_based(6),
         drop_aggression: DropAggression::NoChessMate,
+        partner_hints: false,
+        live_chalk: false,
+        disconnect_policy: DisconnectPolicy::Ignore,
+        match_length: MatchLength::Unlimited,
     }
 }
 
@@ -40,15 +48,18 @@ fn player_in_game(name: &str, id: BughousePlayerId) -> PlayerInGame {
 
 struct Server {
     clients: Arc<Mutex<server::Clients>>,
+    meter_box: meter::SyncMeterBox,
     state: server::ServerState,
 }
 
 impl Server {
-    fn new() -> Self {
+    fn new() -> Self { Self::new_with_hooks(None) }
+    fn new_with_hooks(hooks: Option<Box<dyn server_hooks::ServerHooks>>) -> Self {
         let clients = Arc::new(Mutex::new(server::Clients::new()));
         let clients_copy = Arc::clone(&clients);
-        let state = server::ServerState::new(clients_copy, None);
-        Server{ clients, state }
+        let meter_box = meter::SyncMeterBox::new();
+        let state = server::ServerState::new(clients_copy, hooks, &meter_box);
+        Server{ clients, meter_box, state }
     }
 
     fn add_client(&mut self, events_tx: mpsc::Sender<BughouseServerEvent>) -> server::ClientId {
@@ -64,6 +75,38 @@ impl Server {
 }
 
 
+// Keeps contest snapshots and client error reports in memory. Share `snapshots` between servers
+// to simulate restart.
+struct SnapshotServerHooks {
+    snapshots: Rc<RefCell<HashMap<String, persistence::ContestSnapshot>>>,
+    error_reports: Rc<RefCell<Vec<persistence::ClientErrorReportRow>>>,
+}
+
+impl server_hooks::ServerHooks for SnapshotServerHooks {
+    fn on_client_event(&mut self, _event: &BughouseClientEvent) {}
+    fn on_client_error(&mut self, report: &persistence::ClientErrorReportRow) {
+        self.error_reports.borrow_mut().push(report.clone());
+    }
+    fn on_server_broadcast_event(
+        &mut self, _contest_id: &str, _event: &BughouseServerEvent, _game: Option<&server::GameState>,
+        _round: usize
+    ) {}
+    fn on_contest_updated(&mut self, contest: &persistence::ContestSnapshot) {
+        self.snapshots.borrow_mut().insert(contest.contest_id.clone(), contest.clone());
+    }
+    fn on_contest_removed(&mut self, contest_id: &str) {
+        self.snapshots.borrow_mut().remove(contest_id);
+    }
+    fn on_contest_over(
+        &mut self, _contest_id: &str, _rules: &BughouseRules, _result: &ContestResult,
+        _games: &[BughouseGame]
+    ) {}
+    fn load_contests(&mut self) -> Vec<persistence::ContestSnapshot> {
+        self.snapshots.borrow().values().cloned().collect()
+    }
+}
+
+
 struct Client {
     id: server::ClientId,
     incoming_rx: mpsc::Receiver<BughouseServerEvent>,
@@ -148,6 +191,12 @@ impl World {
             clients: vec![],
         }
     }
+    fn new_with_hooks(hooks: Box<dyn server_hooks::ServerHooks>) -> Self {
+        World {
+            server: Server::new_with_hooks(Some(hooks)),
+            clients: vec![],
+        }
+    }
 
     fn new_contest_with_rules(
         &mut self, client_id: TestClientId, player_name: &str,
@@ -182,9 +231,14 @@ impl World {
     }
 
     fn default_clients(&mut self) -> (String, TestClientId, TestClientId, TestClientId, TestClientId) {
+        self.default_clients_with_rules(default_bughouse_rules())
+    }
+    fn default_clients_with_rules(&mut self, bughouse_rules: BughouseRules)
+        -> (String, TestClientId, TestClientId, TestClientId, TestClientId)
+    {
         let [cl1, cl2, cl3, cl4] = self.new_clients();
 
-        let contest = self.new_contest(cl1, "p1");
+        let contest = self.new_contest_with_rules(cl1, "p1", default_chess_rules(), bughouse_rules);
         self[cl1].state.set_team(Team::Red);
         self.process_all_events();
 
@@ -564,6 +618,73 @@ fn reconnect_lobby() {
     assert!(world[cl1].state.game_state().is_some());
 }
 
+#[test]
+fn owner_kicks_and_bans_players() {
+    let mut world = World::new();
+    let [cl1, cl2, cl3] = world.new_clients();
+
+    let contest = world.new_contest(cl1, "p1");
+    world.join_and_set_team(cl2, &contest, "p2", Team::Red);
+    world.join_and_set_team(cl3, &contest, "p3", Team::Blue);
+    world.process_all_events();
+    assert_eq!(world[cl2].state.contest().unwrap().owner, Some("p1".to_owned()));
+
+    // Only the owner can kick.
+    world[cl2].state.kick_player("p3".to_owned(), false);
+    assert!(matches!(world.process_events_for(cl2), Err(client::EventError::ServerReturnedError(_))));
+    world.process_all_events();
+
+    // Can vote to kick only players who have been offline for some time.
+    world[cl2].state.vote_kick("p3".to_owned());
+    assert!(matches!(world.process_events_for(cl2), Err(client::EventError::ServerReturnedError(_))));
+    world.process_all_events();
+
+    world[cl1].state.kick_player("p3".to_owned(), false);
+    world.process_all_events();
+    assert!(world[cl3].state.contest().is_none());
+    assert_eq!(world[cl1].state.contest().unwrap().players.len(), 2);
+
+    // Kicked players can re-join, banned players cannot.
+    let cl3_new = world.new_client();
+    world.join_and_set_team(cl3_new, &contest, "p3", Team::Blue);
+    world.process_all_events();
+    assert_eq!(world[cl1].state.contest().unwrap().players.len(), 3);
+
+    world[cl1].state.kick_player("p3".to_owned(), true);
+    world.process_all_events();
+    let cl3_banned = world.new_client();
+    world[cl3_banned].join(&contest, "p3");
+    assert!(matches!(world.process_events_for(cl3_banned), Err(client::EventError::ServerReturnedError(_))));
+    world.process_all_events();
+    assert_eq!(world[cl1].state.contest().unwrap().players.len(), 2);
+
+    // Ownership can be transferred.
+    world[cl1].state.transfer_ownership("p2".to_owned());
+    world.process_all_events();
+    assert_eq!(world[cl1].state.contest().unwrap().owner, Some("p2".to_owned()));
+
+    // Ownership passes on when the owner leaves.
+    world[cl2].state.leave();
+    world.process_all_events();
+    assert_eq!(world[cl1].state.contest().unwrap().owner, Some("p1".to_owned()));
+}
+
+#[test]
+fn rejected_contest_creator_does_not_become_owner() {
+    let snapshots = Rc::new(RefCell::new(HashMap::new()));
+    let mut world = World::new_with_hooks(Box::new(SnapshotServerHooks{
+        snapshots: Rc::clone(&snapshots), error_reports: Rc::default()
+    }));
+    let cl1 = world.new_client();
+    world[cl1].state.new_contest(default_chess_rules(), default_bughouse_rules(), "bad name".to_owned());
+    assert!(matches!(world.process_events_for(cl1), Err(client::EventError::ServerReturnedError(_))));
+    world.server.tick();
+    let snapshots = snapshots.borrow();
+    let snapshot = snapshots.values().exactly_one().unwrap();
+    assert!(snapshot.players.is_empty());
+    assert_eq!(snapshot.owner, None);
+}
+
 #[test]
 fn reconnect_game_active() {
     let mut world = World::new();
@@ -652,6 +773,30 @@ fn reconnect_game_over_resignation() {
     );
 }
 
+// Regression test: events from the previous game must not be applied to the new one.
+#[test]
+fn stale_game_events_ignored() {
+    let mut world = World::new();
+    let (_, cl1, cl2, cl3, cl4) = world.default_clients();
+
+    world[cl3].state.resign();
+    world.process_all_events();
+    assert_eq!(world[cl1].state.game_state().unwrap().game_id, 1);
+
+    for cl in [cl1, cl2, cl3, cl4].iter() {
+        world[*cl].state.set_ready(true);
+        world.process_outgoing_events_for(*cl);
+    }
+    // The server has started a new game, but `cl1` does not know about it yet.
+    world[cl1].state.resign();
+    world.process_outgoing_events_for(cl1);
+    world.process_all_events();
+
+    let game_state = world[cl1].state.game_state().unwrap();
+    assert_eq!(game_state.game_id, 2);
+    assert_eq!(game_state.alt_game.status(), BughouseGameStatus::Active);
+}
+
 // Regression test: server should not panic when a client tries to make a turn after the
 // game was over on another board.
 #[test]
@@ -668,6 +813,130 @@ fn turn_after_game_ended_on_another_board() {
     world.process_all_events();
 }
 
+#[test]
+fn partner_messages_visible_only_to_team() {
+    let mut world = World::new();
+    let (contest, cl1, cl2, cl3, cl4) = world.default_clients();
+
+    world[cl1].state.send_partner_message(PartnerMessage::Need(PieceKind::Knight));
+    world.process_all_events();
+    let messages = |world: &World, cl| world[cl].state.game_state().unwrap().partner_messages.clone();
+    assert_eq!(messages(&world, cl1).len(), 1);
+    assert_eq!(messages(&world, cl2)[0].message, PartnerMessage::Need(PieceKind::Knight));
+    assert!(messages(&world, cl3).is_empty());
+    assert!(messages(&world, cl4).is_empty());
+
+    // Messages are restored on reconnect, but only for the team.
+    world[cl3].state.send_partner_message(PartnerMessage::Sit);
+    world[cl2].state.leave();
+    world.process_all_events();
+    let cl2_new = world.new_client();
+    world[cl2_new].join(&contest, "p2");
+    world.process_all_events();
+    assert_eq!(messages(&world, cl2_new).len(), 1);
+}
+
+#[test]
+fn spectator_joins_mid_game() {
+    let mut world = World::new();
+    let (contest, cl1, _cl2, _cl3, _cl4) = world.default_clients();
+
+    world[cl1].make_turn("e4").unwrap();
+    world.process_all_events();
+
+    let cl5 = world.new_client();
+    world[cl5].state.spectate(contest.clone(), "s1".to_owned());
+    world.process_all_events();
+    assert_eq!(world[cl1].state.contest().unwrap().spectators, vec!["s1".to_owned()]);
+    assert!(matches!(world[cl5].my_id(), BughouseParticipantId::Observer(_)));
+    assert!(world[cl5].local_game().board(BughouseBoard::A).grid()[Coord::E4].is(piece!(White Pawn)));
+
+    // Spectator name must not clash with player names.
+    let cl6 = world.new_client();
+    world[cl6].state.spectate(contest.clone(), "p1".to_owned());
+    assert!(matches!(world.process_events_for(cl6), Err(client::EventError::ServerReturnedError(_))));
+
+    world[cl5].state.leave();
+    world.process_all_events();
+    assert!(world[cl1].state.contest().unwrap().spectators.is_empty());
+}
+
+#[test]
+fn live_chalk_visible_only_to_team() {
+    let mut world = World::new();
+    let (contest, cl1, cl2, cl3, _cl4) = world.default_clients_with_rules(BughouseRules {
+        live_chalk: true,
+        .. default_bughouse_rules()
+    });
+    let cl5 = world.new_client();
+    world[cl5].state.spectate(contest, "s1".to_owned());
+    world.process_all_events();
+
+    let has_drawing = |world: &World, client_id, player_name| {
+        world[client_id].state.game_state().unwrap().chalkboard.drawings_by(player_name).is_some()
+    };
+
+    world[cl1].state.add_chalk_mark(DisplayBoard::Primary, ChalkMark::SquareHighlight{ coord: Coord::E4 });
+    world.process_all_events();
+    assert!(has_drawing(&world, cl1, "p1"));
+    assert!(has_drawing(&world, cl2, "p1"));
+    assert!(!has_drawing(&world, cl3, "p1"));
+    assert!(has_drawing(&world, cl5, "p1"));
+
+    // Drawings become public when the game is over.
+    world[cl3].state.resign();
+    world.process_all_events();
+    assert!(has_drawing(&world, cl3, "p1"));
+}
+
+#[test]
+fn live_chalk_hidden_from_non_participating_players() {
+    let mut world = World::new();
+    let [cl1, cl2, cl3, cl4, cl5] = world.new_clients();
+
+    let contest = world.new_contest_with_rules(
+        cl1, "p1",
+        default_chess_rules(),
+        BughouseRules {
+            teaming: Teaming::IndividualMode,
+            live_chalk: true,
+            .. default_bughouse_rules()
+        }
+    );
+    world.server.state.TEST_override_board_assignment(contest.clone(), vec! [
+        player_in_game("p1", seating!(White A)),
+        player_in_game("p2", seating!(Black B)),
+        player_in_game("p3", seating!(Black A)),
+        player_in_game("p4", seating!(White B)),
+    ]);
+    world[cl2].join(&contest, "p2");
+    world[cl3].join(&contest, "p3");
+    world[cl4].join(&contest, "p4");
+    world[cl5].join(&contest, "p5");
+    world.process_all_events();
+    for cl in [cl1, cl2, cl3, cl4, cl5].iter() {
+        world[*cl].state.set_ready(true);
+    }
+    world.process_all_events();
+
+    let has_drawing = |world: &World, client_id, player_name| {
+        world[client_id].state.game_state().unwrap().chalkboard.drawings_by(player_name).is_some()
+    };
+
+    world[cl1].state.add_chalk_mark(DisplayBoard::Primary, ChalkMark::SquareHighlight{ coord: Coord::E4 });
+    world[cl3].state.add_chalk_mark(DisplayBoard::Primary, ChalkMark::SquareHighlight{ coord: Coord::E5 });
+    world.process_all_events();
+    assert!(has_drawing(&world, cl2, "p1"));
+    assert!(has_drawing(&world, cl4, "p3"));
+    assert!(!has_drawing(&world, cl5, "p1"));
+    assert!(!has_drawing(&world, cl5, "p3"));
+
+    world[cl3].state.resign();
+    world.process_all_events();
+    assert!(has_drawing(&world, cl5, "p1"));
+    assert!(has_drawing(&world, cl5, "p3"));
+}
+
 #[test]
 fn five_players() {
     let mut world = World::new();
@@ -706,6 +975,66 @@ fn five_players() {
     assert!(world[cl5].local_game().board(BughouseBoard::A).grid()[Coord::E4].is(piece!(White Pawn)));
 }
 
+#[test]
+fn join_mid_game_individual_mode() {
+    let mut world = World::new();
+    let [cl1, cl2, cl3, cl4, cl5] = world.new_clients();
+
+    let contest = world.new_contest_with_rules(
+        cl1, "p1",
+        default_chess_rules(),
+        BughouseRules {
+            teaming: Teaming::IndividualMode,
+            .. default_bughouse_rules()
+        }
+    );
+    world.server.state.TEST_override_board_assignment(contest.clone(), vec! [
+        player_in_game("p1", seating!(White A)),
+        player_in_game("p2", seating!(Black B)),
+        player_in_game("p3", seating!(Black A)),
+        player_in_game("p4", seating!(White B)),
+    ]);
+    world[cl2].join(&contest, "p2");
+    world[cl3].join(&contest, "p3");
+    world[cl4].join(&contest, "p4");
+    world.process_all_events();
+    for cl in [cl1, cl2, cl3, cl4].iter() {
+        world[*cl].state.set_ready(true);
+    }
+    world.process_all_events();
+
+    world[cl1].make_turn("e4").unwrap();
+    world.process_all_events();
+
+    // A newcomer joins the lobby and observes the current game.
+    world[cl5].join(&contest, "p5");
+    world.process_all_events();
+    assert!(world[cl1].state.contest().unwrap().players.iter().any(|p| p.name == "p5"));
+    assert!(matches!(world[cl5].my_id(), BughouseParticipantId::Observer(_)));
+    assert!(world[cl5].local_game().board(BughouseBoard::A).grid()[Coord::E4].is(piece!(White Pawn)));
+
+    world[cl1].state.resign();
+    world.process_all_events();
+    let scores = world[cl5].state.contest().unwrap().scores.clone();
+    assert_eq!(scores.per_player_average("p3"), Some(2.0));
+    assert_eq!(scores.per_player_average("p1"), Some(0.0));
+    assert_eq!(scores.per_player_average("p5"), None);
+
+    // The newcomer is queued for the next game.
+    world.server.state.TEST_override_board_assignment(contest.clone(), vec! [
+        player_in_game("p5", seating!(White A)),
+        player_in_game("p2", seating!(Black B)),
+        player_in_game("p3", seating!(Black A)),
+        player_in_game("p4", seating!(White B)),
+    ]);
+    for cl in [cl1, cl2, cl3, cl4, cl5].iter() {
+        world[*cl].state.set_ready(true);
+    }
+    world.process_all_events();
+    assert_eq!(world[cl5].my_player_id(), seating!(White A));
+    assert!(matches!(world[cl1].my_id(), BughouseParticipantId::Observer(_)));
+}
+
 #[test]
 fn two_contests() {
     let mut world = World::new();
@@ -752,3 +1081,528 @@ fn two_contests() {
     assert!(world[cl6].local_game().board(BughouseBoard::A).grid()[Coord::E4].is_none());
     assert!(world[cl6].local_game().board(BughouseBoard::A).grid()[Coord::C3].is(piece!(White Knight)));
 }
+
+#[test]
+fn contest_snapshot_saved_on_tick_and_removed_after_inactivity() {
+    let snapshots = Rc::new(RefCell::new(HashMap::new()));
+    let mut world = World::new_with_hooks(Box::new(SnapshotServerHooks{
+        snapshots: Rc::clone(&snapshots), error_reports: Rc::default()
+    }));
+    world.default_clients();
+    assert!(snapshots.borrow().is_empty());
+    world.server.tick();
+    assert_eq!(snapshots.borrow().len(), 1);
+
+    world.server.state.TEST_tick_after(Duration::from_secs(3600));
+    assert_eq!(snapshots.borrow().len(), 1);
+    world.server.state.TEST_tick_after(Duration::from_secs(3600 * 25));
+    assert!(snapshots.borrow().is_empty());
+}
+
+#[test]
+fn contest_restored_after_server_restart() {
+    let snapshots = Rc::new(RefCell::new(HashMap::new()));
+    let mut world = World::new_with_hooks(Box::new(SnapshotServerHooks{
+        snapshots: Rc::clone(&snapshots), error_reports: Rc::default()
+    }));
+    let (contest, cl1, _cl2, cl3, _cl4) = world.default_clients();
+    world[cl1].make_turn("e4").unwrap();
+    world.process_all_events();
+    world[cl3].make_turn("d5").unwrap();
+    world.process_all_events();
+    world.server.tick();
+
+    let mut world = World::new_with_hooks(Box::new(SnapshotServerHooks{ snapshots, error_reports: Rc::default() }));
+    let [cl1, cl2, cl3, cl4] = world.new_clients();
+    world[cl1].join(&contest, "p1");
+    world[cl2].join(&contest, "p2");
+    world[cl3].join(&contest, "p3");
+    world.process_all_events();
+    assert_eq!(world[cl1].local_game().turn_log().len(), 2);
+    assert!(world[cl1].my_board().grid()[Coord::D5].is(piece!(Black Pawn)));
+    assert!(world[cl1].state.is_game_paused());
+    assert_eq!(world[cl1].make_turn("xd5").unwrap_err(), TurnError::GamePaused);
+
+    world[cl4].join(&contest, "p4");
+    world.process_all_events();
+    assert!(!world[cl1].state.is_game_paused());
+    world[cl1].make_turn("xd5").unwrap();
+    world.process_all_events();
+    assert_eq!(world[cl2].my_board().reserve(world[cl2].my_force())[PieceKind::Pawn], 1);
+}
+
+// Starts a contest with the given rules, makes a turn, restarts the server and lets everybody
+// except p4 rejoin.
+fn restart_without_one_player(rules: BughouseRules) -> (World, String, TestClientId) {
+    let snapshots = Rc::new(RefCell::new(HashMap::new()));
+    let mut world = World::new_with_hooks(Box::new(SnapshotServerHooks{
+        snapshots: Rc::clone(&snapshots), error_reports: Rc::default()
+    }));
+    let (contest, cl1, ..) = world.default_clients_with_rules(rules);
+    world[cl1].make_turn("e4").unwrap();
+    world.process_all_events();
+    world.server.tick();
+
+    let mut world = World::new_with_hooks(Box::new(SnapshotServerHooks{ snapshots, error_reports: Rc::default() }));
+    let [cl1, cl2, cl3] = world.new_clients();
+    world[cl1].join(&contest, "p1");
+    world[cl2].join(&contest, "p2");
+    world[cl3].join(&contest, "p3");
+    world.process_all_events();
+    assert!(world[cl1].state.is_game_paused());
+    (world, contest, cl1)
+}
+
+#[test]
+fn restored_game_resumes_when_pause_budget_exhausted() {
+    let (mut world, contest, _cl1) = restart_without_one_player(BughouseRules {
+        disconnect_policy: DisconnectPolicy::Pause{ max_pause: Duration::from_secs(600) },
+        .. default_bughouse_rules()
+    });
+    world.server.state.TEST_tick_after(Duration::from_secs(601));
+    let cl = world.new_client();
+    world[cl].state.spectate(contest, "s".to_owned());
+    world.process_all_events();
+    assert!(!world[cl].state.is_game_paused());
+}
+
+#[test]
+fn restored_game_forfeited_when_player_does_not_return() {
+    let (mut world, _contest, cl1) = restart_without_one_player(BughouseRules {
+        disconnect_policy: DisconnectPolicy::Forfeit{ grace_period: Duration::from_secs(10) },
+        .. default_bughouse_rules()
+    });
+    world.server.state.TEST_tick_after(Duration::from_secs(11));
+    world.process_all_events();
+    assert_eq!(
+        world[cl1].local_game().status(),
+        BughouseGameStatus::Victory(Team::Red, VictoryReason::Forfeit)
+    );
+}
+
+#[test]
+fn client_error_reported_with_context() {
+    let error_reports = Rc::new(RefCell::new(vec![]));
+    let mut world = World::new_with_hooks(Box::new(SnapshotServerHooks{
+        snapshots: Rc::default(), error_reports: Rc::clone(&error_reports)
+    }));
+    let (contest, cl1, ..) = world.default_clients();
+    let client_id = world[cl1].id;
+    world.server.send_network_event(client_id, BughouseClientEvent::ReportErrorWithContext{
+        report: BughouseClientErrorReport::RustError{ message: "oops".to_owned() },
+        git_version: "client-version".to_owned(),
+        user_agent: "Test".to_owned(),
+    });
+    let reports = error_reports.borrow();
+    assert_eq!(reports.len(), 1);
+    let report = &reports[0];
+    assert_eq!(report.kind, "RustError");
+    assert_eq!(report.message, "oops");
+    assert_eq!(report.git_version, "client-version");
+    assert_eq!(report.contest_id, Some(contest));
+    assert_eq!(report.game_number, Some(1));
+    assert_eq!(report.player_name.as_deref(), Some("p1"));
+}
+
+#[test]
+fn client_error_report_legacy_format_accepted() {
+    let error_reports = Rc::new(RefCell::new(vec![]));
+    let mut world = World::new_with_hooks(Box::new(SnapshotServerHooks{
+        snapshots: Rc::default(), error_reports: Rc::clone(&error_reports)
+    }));
+    let (_contest, cl1, ..) = world.default_clients();
+    let client_id = world[cl1].id;
+    world.server.send_network_event(client_id, BughouseClientEvent::ReportError(
+        BughouseClientErrorReport::UnknownError{ message: "oops".to_owned() }
+    ));
+    let reports = error_reports.borrow();
+    assert_eq!(reports.len(), 1);
+    assert_eq!(reports[0].kind, "UnknownError");
+    assert_eq!(reports[0].message, "oops");
+}
+
+#[test]
+fn client_error_reports_truncated_and_rate_limited() {
+    let error_reports = Rc::new(RefCell::new(vec![]));
+    let mut world = World::new_with_hooks(Box::new(SnapshotServerHooks{
+        snapshots: Rc::default(), error_reports: Rc::clone(&error_reports)
+    }));
+    let (_contest, cl1, ..) = world.default_clients();
+    let client_id = world[cl1].id;
+    for _ in 0..20 {
+        world.server.send_network_event(client_id, BughouseClientEvent::ReportErrorWithContext{
+            report: BughouseClientErrorReport::RustPanic{
+                panic_info: "x".repeat(1_000_000),
+                backtrace: "y".repeat(1_000_000),
+            },
+            git_version: "v".repeat(1_000_000),
+            user_agent: "u".repeat(1_000_000),
+        });
+    }
+    let reports = error_reports.borrow();
+    assert!(!reports.is_empty() && reports.len() < 20);
+    for report in reports.iter() {
+        assert!(report.message.len() < 1_000_000);
+        assert!(report.backtrace.as_ref().unwrap().len() < 1_000_000);
+        assert!(report.git_version.len() < 1_000_000);
+        assert!(report.user_agent.len() < 1_000_000);
+    }
+}
+
+#[test]
+fn server_meters_recorded() {
+    let mut world = World::new();
+    let (_contest, cl1, ..) = world.default_clients();
+    world[cl1].make_turn("e4").unwrap();
+    world.process_all_events();
+    world.server.tick();
+    world.server.tick();
+
+    let stats = world.server.meter_box.read_stats();
+    assert!(stats["apply_event_network_us"].num_values > 0);
+    assert_eq!(stats["apply_event_tick_us"].num_values, 2);
+    assert_eq!(stats["tick_lag_us"].num_values, 1);
+    assert_eq!(
+        stats["clients_lock_wait_us"].num_values,
+        stats["apply_event_network_us"].num_values + 2
+    );
+    // The turn is sent to all four players.
+    assert_eq!(stats["broadcast_recipients"].p99, 4);
+
+    let buckets = world.server.meter_box.read_buckets();
+    let tick = &buckets["apply_event_tick_us"];
+    assert_eq!(tick.num_values, 2);
+    assert_eq!(tick.buckets.last().unwrap().1, 2);
+    assert!(tick.buckets.windows(2).all(|w| w[0].0 < w[1].0 && w[0].1 <= w[1].1));
+    // Nobody but the four players is connected.
+    let recipients = &buckets["broadcast_recipients"];
+    assert_eq!(recipients.num_values, stats["broadcast_recipients"].num_values);
+    assert_eq!(recipients.buckets.iter().find(|(bound, _)| *bound == 5).unwrap().1, recipients.num_values);
+    assert!(recipients.sum <= 4 * recipients.num_values);
+
+    let recent = world.server.meter_box.consume_recent_stats();
+    assert_eq!(recent["apply_event_tick_us"].num_values, 2);
+    let recent = world.server.meter_box.consume_recent_stats();
+    assert_eq!(recent["apply_event_tick_us"].num_values, 0);
+    assert_eq!(world.server.meter_box.read_stats()["apply_event_tick_us"].num_values, 2);
+}
+
+#[test]
+fn chat_team_messages_are_private() {
+    let mut world = World::new();
+    let (contest, cl1, cl2, cl3, _cl4) = world.default_clients();
+    world[cl1].state.send_chat_message(ChatChannel::All, "hi all".to_owned());
+    world[cl1].state.send_chat_message(ChatChannel::Team, "hi team".to_owned());
+    world.process_all_events();
+    let texts = |world: &World, cl| {
+        world[cl].state.contest().unwrap().chat.iter().map(|m| m.text.clone()).collect_vec()
+    };
+    assert_eq!(texts(&world, cl2), vec!["hi all", "hi team"]);
+    assert_eq!(texts(&world, cl3), vec!["hi all"]);
+
+    // Newcomers get the history, but only the part they are allowed to see.
+    let cl5 = world.new_client();
+    world[cl5].state.spectate(contest, "s1".to_owned());
+    world.process_all_events();
+    assert_eq!(texts(&world, cl5), vec!["hi all"]);
+}
+
+#[test]
+fn chat_limits() {
+    let mut world = World::new();
+    let (_contest, cl1, _cl2, _cl3, _cl4) = world.default_clients();
+    world[cl1].state.send_chat_message(ChatChannel::All, "x".repeat(MAX_CHAT_MESSAGE_LENGTH + 1));
+    assert!(matches!(world.process_events_for(cl1), Err(client::EventError::ServerReturnedError(_))));
+
+    for _ in 0..5 {
+        world[cl1].state.send_chat_message(ChatChannel::All, "spam".to_owned());
+    }
+    world.process_all_events();
+    world[cl1].state.send_chat_message(ChatChannel::All, "spam".to_owned());
+    assert!(matches!(world.process_events_for(cl1), Err(client::EventError::ServerReturnedError(_))));
+}
+
+#[test]
+fn player_presence_updates() {
+    let mut world = World::new();
+    let (contest, cl1, cl2, _cl3, _cl4) = world.default_clients();
+    let presence = |world: &World, name: &str| {
+        world[cl1].state.contest().unwrap().players.iter().find(|p| p.name == name).unwrap().presence
+    };
+    assert_eq!(presence(&world, "p2"), PlayerPresence::Online);
+
+    world[cl2].state.leave();
+    world.process_all_events();
+    assert_eq!(presence(&world, "p2"), PlayerPresence::Offline);
+
+    let cl2_new = world.new_client();
+    world[cl2_new].join(&contest, "p2");
+    world.process_all_events();
+    assert_eq!(presence(&world, "p2"), PlayerPresence::Online);
+}
+
+#[test]
+fn disconnect_pauses_game() {
+    let mut world = World::new();
+    let (contest, cl1, cl2, cl3, _cl4) = world.default_clients_with_rules(BughouseRules {
+        disconnect_policy: DisconnectPolicy::Pause{ max_pause: Duration::from_secs(600) },
+        .. default_bughouse_rules()
+    });
+    world[cl1].make_turn("e4").unwrap();
+    world.process_all_events();
+
+    world[cl2].state.leave();
+    world.process_all_events();
+    assert!(world[cl1].state.is_game_paused());
+    assert_eq!(world[cl3].make_turn("d5").unwrap_err(), TurnError::GamePaused);
+
+    let cl2_new = world.new_client();
+    world[cl2_new].join(&contest, "p2");
+    world.process_all_events();
+    assert!(!world[cl1].state.is_game_paused());
+    world[cl3].make_turn("d5").unwrap();
+    world.process_all_events();
+}
+
+#[test]
+fn disconnect_forfeits_game() {
+    let mut world = World::new();
+    let (_contest, cl1, cl2, _cl3, _cl4) = world.default_clients_with_rules(BughouseRules {
+        disconnect_policy: DisconnectPolicy::Forfeit{ grace_period: Duration::ZERO },
+        .. default_bughouse_rules()
+    });
+    world[cl1].make_turn("e4").unwrap();
+    world.process_all_events();
+
+    world[cl2].state.leave();
+    world.process_all_events();
+    assert_eq!(
+        world[cl1].local_game().status(),
+        BughouseGameStatus::Victory(Team::Blue, VictoryReason::Forfeit)
+    );
+}
+
+// Forfeit grace period starts when the connection is considered permanently lost, not when
+// heartbeats stop arriving.
+#[test]
+fn connection_problems_do_not_count_towards_forfeit() {
+    let mut world = World::new();
+    let (contest, cl1, ..) = world.default_clients_with_rules(BughouseRules {
+        disconnect_policy: DisconnectPolicy::Forfeit{ grace_period: Duration::from_secs(10) },
+        .. default_bughouse_rules()
+    });
+    world[cl1].make_turn("e4").unwrap();
+    world.process_all_events();
+    let game_status_for_spectator = |world: &mut World, spectator_name: &str| {
+        let cl = world.new_client();
+        world[cl].state.spectate(contest.clone(), spectator_name.to_owned());
+        world.process_all_events();
+        world[cl].local_game().status()
+    };
+
+    // All clients stop sending heartbeats: connection is temporarily lost for much longer
+    // than the grace period, and then permanently lost.
+    world.server.state.TEST_tick_after(Duration::from_secs(30));
+    world.server.state.TEST_tick_after(Duration::from_secs(65));
+    world.server.state.TEST_tick_after(Duration::from_secs(70));
+    assert_eq!(game_status_for_spectator(&mut world, "s1"), BughouseGameStatus::Active);
+
+    world.server.state.TEST_tick_after(Duration::from_secs(80));
+    assert!(matches!(
+        game_status_for_spectator(&mut world, "s2"),
+        BughouseGameStatus::Victory(_, VictoryReason::Forfeit)
+    ));
+}
+
+#[test]
+fn contest_over_when_match_length_reached() {
+    let mut world = World::new();
+    let (_contest, cl1, cl2, cl3, _cl4) = world.default_clients_with_rules(BughouseRules {
+        match_length: MatchLength::FirstTo{ points: 1 },
+        .. default_bughouse_rules()
+    });
+    world.replay_white_checkmates_black(cl1, cl3);
+    let result = world[cl2].state.contest().unwrap().result.clone().unwrap();
+    assert_eq!(result.games_played, 1);
+    assert_eq!(result.winners, vec!["p1".to_owned(), "p2".to_owned()]);
+    assert_eq!(result.scores.to_human_readable(), "Red 1 : Blue 0");
+
+    // No new games after the contest is over.
+    world[cl1].state.set_ready(true);
+    assert!(matches!(world.process_events_for(cl1), Err(client::EventError::ServerReturnedError(_))));
+}
+
+#[test]
+fn seat_rotation_alternate_boards() {
+    let mut world = World::new();
+    let [cl1, cl2, cl3, cl4] = world.new_clients();
+    let contest = world.new_contest_with_rules(
+        cl1, "p1",
+        default_chess_rules(),
+        BughouseRules {
+            seat_rotation: SeatRotation::AlternateBoards,
+            .. default_bughouse_rules()
+        }
+    );
+    world[cl1].state.set_team(Team::Red);
+    world.join_and_set_team(cl2, &contest, "p2", Team::Red);
+    world.join_and_set_team(cl3, &contest, "p3", Team::Blue);
+    world.join_and_set_team(cl4, &contest, "p4", Team::Blue);
+    world.process_all_events();
+    let upcoming_board = |world: &World, name: &str| {
+        world[cl1].state.contest().unwrap().upcoming_seating.as_ref().unwrap()
+            .iter().find(|p| p.name == name).unwrap().id.board_idx
+    };
+    assert_eq!(upcoming_board(&world, "p1"), BughouseBoard::A);
+    assert_eq!(upcoming_board(&world, "p3"), BughouseBoard::A);
+
+    for cl in [cl1, cl2, cl3, cl4].iter() {
+        world[*cl].state.set_ready(true);
+    }
+    world.process_all_events();
+    assert_eq!(world[cl1].my_player_id(), seating!(White A));
+    assert_eq!(world[cl4].my_player_id(), seating!(White B));
+    assert_eq!(upcoming_board(&world, "p1"), BughouseBoard::B);
+
+    world[cl1].state.resign();
+    world.process_all_events();
+    for cl in [cl1, cl2, cl3, cl4].iter() {
+        world[*cl].state.set_ready(true);
+    }
+    world.process_all_events();
+    assert_eq!(world[cl1].my_player_id(), seating!(Black B));
+    assert_eq!(world[cl2].my_player_id(), seating!(White A));
+    assert_eq!(world[cl3].my_player_id(), seating!(White B));
+}
+
+#[test]
+fn seat_rotation_alternate_colors() {
+    let mut world = World::new();
+    let [cl1, cl2, cl3, cl4] = world.new_clients();
+    let contest = world.new_contest_with_rules(
+        cl1, "p1",
+        default_chess_rules(),
+        BughouseRules {
+            seat_rotation: SeatRotation::AlternateColors,
+            .. default_bughouse_rules()
+        }
+    );
+    world[cl1].state.set_team(Team::Red);
+    world.join_and_set_team(cl2, &contest, "p2", Team::Red);
+    world.join_and_set_team(cl3, &contest, "p3", Team::Blue);
+    world.join_and_set_team(cl4, &contest, "p4", Team::Blue);
+    world.process_all_events();
+    let all_ready = |world: &mut World| {
+        for cl in [cl1, cl2, cl3, cl4].iter() {
+            world[*cl].state.set_ready(true);
+        }
+        world.process_all_events();
+    };
+
+    all_ready(&mut world);
+    assert_eq!(world[cl1].my_player_id(), seating!(White A));
+    assert_eq!(world[cl2].my_player_id(), seating!(Black B));
+    assert_eq!(world[cl3].my_player_id(), seating!(Black A));
+    assert_eq!(world[cl4].my_player_id(), seating!(White B));
+    let upcoming_id = |world: &World, name: &str| {
+        world[cl1].state.contest().unwrap().upcoming_seating.as_ref().unwrap()
+            .iter().find(|p| p.name == name).unwrap().id
+    };
+    assert_eq!(upcoming_id(&world, "p1"), seating!(Black A));
+    assert_eq!(upcoming_id(&world, "p3"), seating!(White A));
+
+    world[cl3].state.resign();
+    world.process_all_events();
+    all_ready(&mut world);
+    assert_eq!(world[cl1].my_player_id(), seating!(Black A));
+    assert_eq!(world[cl2].my_player_id(), seating!(White B));
+    assert_eq!(world[cl3].my_player_id(), seating!(White A));
+    assert_eq!(world[cl4].my_player_id(), seating!(Black B));
+
+    // Scores are kept for lobby teams even when they play the other team's colors.
+    world[cl1].state.resign();
+    world.process_all_events();
+    assert_eq!(world[cl1].state.contest().unwrap().scores.to_human_readable(), "Red 1 : Blue 1");
+
+    all_ready(&mut world);
+    assert_eq!(world[cl1].my_player_id(), seating!(White A));
+    assert_eq!(world[cl3].my_player_id(), seating!(Black A));
+}
+
+#[test]
+fn fair_scheduling_individual_mode() {
+    let mut world = World::new();
+    let [cl1, cl2, cl3, cl4, cl5] = world.new_clients();
+    let contest = world.new_contest_with_rules(
+        cl1, "p1",
+        default_chess_rules(),
+        BughouseRules {
+            teaming: Teaming::IndividualMode,
+            .. default_bughouse_rules()
+        }
+    );
+    world[cl2].join(&contest, "p2");
+    world[cl3].join(&contest, "p3");
+    world[cl4].join(&contest, "p4");
+    world[cl5].join(&contest, "p5");
+    world.process_all_events();
+    let next_up = |world: &World| world[cl1].state.contest().unwrap().next_up.clone();
+    assert_eq!(next_up(&world), ["p1", "p2", "p3", "p4", "p5"]);
+
+    for cl in [cl1, cl2, cl3, cl4, cl5].iter() {
+        world[*cl].state.set_ready(true);
+    }
+    world.process_all_events();
+    assert_eq!(world[cl1].my_player_id(), seating!(White A));
+    assert_eq!(world[cl2].my_player_id(), seating!(Black B));
+    assert!(matches!(world[cl5].my_id(), BughouseParticipantId::Observer(_)));
+
+    world[cl1].state.resign();
+    world.process_all_events();
+    // The player who sat out goes first; previous partners are split up.
+    assert_eq!(next_up(&world), ["p5", "p1", "p2", "p3", "p4"]);
+    for cl in [cl1, cl2, cl3, cl4, cl5].iter() {
+        world[*cl].state.set_ready(true);
+    }
+    world.process_all_events();
+    assert_eq!(world[cl5].my_player_id(), seating!(White A));
+    assert_eq!(world[cl1].my_player_id(), seating!(Black B));
+    assert_eq!(world[cl2].my_player_id(), seating!(White B));
+    assert_eq!(world[cl3].my_player_id(), seating!(Black A));
+    assert!(matches!(world[cl4].my_id(), BughouseParticipantId::Observer(_)));
+    assert_eq!(next_up(&world), ["p4", "p5", "p1", "p2", "p3"]);
+}
+
+#[test]
+fn game_record_replay() {
+    let mut world = World::new();
+    let (_, cl1, _cl2, cl3, _cl4) = world.default_clients();
+    world.replay_white_checkmates_black(cl1, cl3);
+    let seconds = |s| GameInstant::from_duration(Duration::from_secs(s));
+    let mut record = server::finished_game_record(&world[cl1].local_game());
+    for (idx, turn) in record.game.turn_log.iter_mut().enumerate() {
+        turn.time = seconds(idx as u64 * 10);
+    }
+    record.game.time = seconds(80);
+
+    let (viewer_tx, viewer_rx) = mpsc::channel();
+    let mut viewer = client::ClientState::new("Test".to_owned(), "?".to_owned(), viewer_tx);
+    let game = |viewer: &client::ClientState| viewer.game_state().unwrap().alt_game.local_game();
+
+    viewer.show_game_record(&record, seconds(25)).unwrap();
+    assert!(viewer.contest().unwrap().is_replay);
+    assert_eq!(game(&viewer).turn_log().len(), 3);
+    assert_eq!(game(&viewer).status(), BughouseGameStatus::Active);
+
+    viewer.show_game_record(&record, seconds(1000)).unwrap();
+    assert_eq!(game(&viewer).turn_log().len(), 9);
+    assert_eq!(game(&viewer).status(), BughouseGameStatus::Victory(Team::Red, VictoryReason::Checkmate));
+
+    // Going back in time is allowed.
+    viewer.show_game_record(&record, GameInstant::game_start()).unwrap();
+    assert_eq!(game(&viewer).turn_log().len(), 1);
+
+    viewer.refresh();
+    assert!(viewer.next_notable_event().is_none());
+    assert!(viewer_rx.try_recv().is_err());
+}
@@ -0,0 +1,66 @@
+use bughouse_chess::persistence::GameResultRow;
+use bughouse_chess::rating::*;
+
+
+fn game(red: [&str; 2], blue: [&str; 2], result: &str) -> GameResultRow {
+    GameResultRow {
+        git_version: String::new(),
+        invocation_id: String::new(),
+        game_start_time: None,
+        game_end_time: None,
+        player_red_a: red[0].to_owned(),
+        player_red_b: red[1].to_owned(),
+        player_blue_a: blue[0].to_owned(),
+        player_blue_b: blue[1].to_owned(),
+        result: result.to_owned(),
+        game_pgn: String::new(),
+        contest_id: None,
+        game_record: None,
+        variant: None,
+        starting_time_sec: None,
+        drop_aggression: None,
+    }
+}
+
+fn assert_close(a: f64, b: f64) {
+    assert!((a - b).abs() < 1e-9, "{a} != {b}");
+}
+
+#[test]
+fn equal_teams() {
+    let (ratings, history) = compute_ratings(&[game(["p1", "p2"], ["p3", "p4"], "VICTORY_RED")]);
+    assert_eq!(history.len(), 4);
+    assert_close(ratings.get("p1").rating, INITIAL_RATING + 20.0);
+    assert_close(ratings.get("p2").rating, INITIAL_RATING + 20.0);
+    assert_close(ratings.get("p3").rating, INITIAL_RATING - 20.0);
+    assert_eq!(ratings.get("p4").games_played, 1);
+    assert_eq!(ratings.get("p5").games_played, 0);
+}
+
+#[test]
+fn draw_between_equal_teams_changes_nothing() {
+    let (ratings, _) = compute_ratings(&[game(["p1", "p2"], ["p3", "p4"], "DRAW")]);
+    assert_close(ratings.get("p1").rating, INITIAL_RATING);
+    assert_close(ratings.get("p4").rating, INITIAL_RATING);
+}
+
+#[test]
+fn strong_partner_reduces_gain() {
+    let (ratings, _) = compute_ratings(&[
+        game(["p1", "p2"], ["p3", "p4"], "VICTORY_RED"),
+        game(["p1", "p2"], ["p3", "p4"], "VICTORY_RED"),
+        // p5 is a newcomer. Winning with p1 is expected, so p5 gains less than 20.
+        game(["p5", "p1"], ["p6", "p3"], "VICTORY_RED"),
+    ]);
+    let p5_gain = ratings.get("p5").rating - INITIAL_RATING;
+    let p6_loss = INITIAL_RATING - ratings.get("p6").rating;
+    assert!(p5_gain > 0.0 && p5_gain < 20.0);
+    assert_close(p5_gain, p6_loss);
+}
+
+#[test]
+fn unknown_result_ignored() {
+    let (ratings, history) = compute_ratings(&[game(["p1", "p2"], ["p3", "p4"], "")]);
+    assert!(history.is_empty());
+    assert!(ratings.per_player.is_empty());
+}
@@ -62,8 +62,11 @@ const Storage = {
 const SearchParams = {
     contest_id: 'contest-id',
     server: 'server',
+    replay: 'replay',  // game ID (see webserver "/dyn/games"); opens the game viewer
 };
 
+const replay_game_id = new URLSearchParams(window.location.search).get(SearchParams.replay);
+
 const git_version = document.getElementById('git-version');
 const info_string = document.getElementById('info-string');
 
@@ -83,6 +86,13 @@ const jc_contest_id = document.getElementById('jc-contest-id');
 
 const ready_button = document.getElementById('ready-button');
 
+const replay_start_button = document.getElementById('replay-start-button');
+const replay_prev_button = document.getElementById('replay-prev-button');
+const replay_next_button = document.getElementById('replay-next-button');
+const replay_end_button = document.getElementById('replay-end-button');
+const replay_time = document.getElementById('replay-time');
+const replay_time_label = document.getElementById('replay-time-label');
+
 const svg_defs = document.getElementById('svg-defs');
 
 const loading_status = new class {
@@ -160,7 +170,8 @@ set_up_chalk_drawing();
 
 let wasm_client_object = make_wasm_client();
 let wasm_client_panicked = false;
-let socket = make_socket();
+// There is no server connection in the game viewer.
+let socket = replay_game_id ? null : make_socket();
 
 // Parameters and data structures for the audio logic. Our goal is to make short and
 // important sounds (like turn sound) as clear as possible when several events occur
@@ -175,6 +186,11 @@ let audio_muted = false;
 
 let drag_element = null;
 
+// Game viewer state. Times are in milliseconds since game start.
+let replay_turn_times = [];
+let replay_duration = 0;
+let replay_current_time = 0;
+
 const Meter = make_meters();
 
 document.addEventListener('keydown', on_document_keydown);
@@ -192,6 +208,16 @@ jc_back_button.addEventListener('click', show_start_page);
 menu_create_contest_page.addEventListener('submit', on_create_contest_confirm);
 menu_join_contest_page.addEventListener('submit', on_join_contest_confirm);
 
+replay_start_button.addEventListener('click', function() { show_replay_at(0); });
+replay_prev_button.addEventListener('click', show_replay_prev_turn);
+replay_next_button.addEventListener('click', show_replay_next_turn);
+replay_end_button.addEventListener('click', function() { show_replay_at(replay_duration); });
+replay_time.addEventListener('input', function() { show_replay_at(Number(replay_time.value)); });
+
+if (replay_game_id) {
+    init_replay(replay_game_id);
+}
+
 let on_tick_interval_id = setInterval(on_tick, 100);
 
 
@@ -213,7 +239,7 @@ function with_error_handling(f) {
         } else if (e?.constructor?.name == 'RustError') {
             const msg = `Internal Rust error: ${e.message()}`;
             info_string.innerText = msg;
-            if (socket.readyState == WebSocket.OPEN) {
+            if (socket?.readyState == WebSocket.OPEN) {
                 socket.send(wasm.make_rust_error_event(e));
             }
             throw msg;
@@ -222,7 +248,7 @@ function with_error_handling(f) {
             if (rust_panic) {
                 wasm_client_panicked = true;
                 let reported = '';
-                if (socket.readyState == WebSocket.OPEN) {
+                if (socket?.readyState == WebSocket.OPEN) {
                     socket.send(rust_panic);
                     reported = 'The error has been reported (unless that failed too).';
                 } else {
@@ -236,13 +262,13 @@ function with_error_handling(f) {
                     clearInterval(on_tick_interval_id);
                     on_tick_interval_id = null;
                 }
-            } else if (e.name === 'InvalidStateError' && socket.readyState == WebSocket.CONNECTING) {
+            } else if (e.name === 'InvalidStateError' && socket?.readyState == WebSocket.CONNECTING) {
                 info_string.innerText = 'Still connecting to the server... Please try again later.';
             } else {
                 console.log(log_time(), 'Unknown error: ', e);
                 const msg = `Unknown error: ${e}`;
                 info_string.innerText = msg;
-                if (socket.readyState == WebSocket.OPEN) {
+                if (socket?.readyState == WebSocket.OPEN) {
                     // Improvement potential. Include stack trace.
                     socket.send(wasm.make_unknown_error_event(e.toString()));
                 }
@@ -314,7 +340,13 @@ function get_args(args_array, expected_args) {
 }
 
 function on_document_keydown(event) {
-    if (menu_dialog.open) {
+    if (replay_game_id) {
+        if (event.key === 'ArrowLeft') {
+            show_replay_prev_turn();
+        } else if (event.key === 'ArrowRight') {
+            show_replay_next_turn();
+        }
+    } else if (menu_dialog.open) {
         if (event.key === 'Escape') {
             show_start_page();
         }
@@ -388,6 +420,42 @@ function execute_command(input) {
                     get_args(args, []);
                     wasm_client().leave();
                     break;
+                case 'need':
+                case 'dontgive': {
+                    const [piece] = get_args(args, ['p:n:b:r:q']);
+                    wasm_client().send_partner_message(args[0], piece);
+                    break;
+                }
+                case 'sit':
+                case 'go':
+                    get_args(args, []);
+                    wasm_client().send_partner_message(args[0], null);
+                    break;
+                case 'kick':
+                case 'ban': {
+                    const [name] = get_args(args, ['player']);
+                    wasm_client().kick_player(name, args[0] == 'ban');
+                    break;
+                }
+                case 'owner': {
+                    const [name] = get_args(args, ['player']);
+                    wasm_client().transfer_ownership(name);
+                    break;
+                }
+                case 'votekick': {
+                    const [name] = get_args(args, ['player']);
+                    wasm_client().vote_kick(name);
+                    break;
+                }
+                case 'chat':
+                case 'teamchat': {
+                    const text = input.slice(1 + args[0].length).trim();
+                    if (!text) {
+                        throw usage_error(args, ['message']);
+                    }
+                    wasm_client().send_chat_message(args[0] == 'chat' ? 'all' : 'team', text);
+                    break;
+                }
                 case 'save':
                     get_args(args, []);
                     wasm_client().request_export();
@@ -734,6 +802,9 @@ function show_start_page() {
 }
 
 function init_menu() {
+    if (replay_game_id) {
+        return;
+    }
     const search_params = new URLSearchParams(window.location.search);
     const contest_id = search_params.get(SearchParams.contest_id);
     hide_menu_pages(false);
@@ -748,6 +819,46 @@ function init_menu() {
     }
 }
 
+async function init_replay(game_id) {
+    document.body.classList.add('replay');
+    const response = await fetch(`/dyn/game-record/${encodeURIComponent(game_id)}`);
+    if (!response.ok) {
+        info_string.innerText = `Cannot load game ${game_id}: ${await response.text()}`;
+        return;
+    }
+    const record = await response.text();
+    with_error_handling(function() {
+        wasm_client().load_game_record(record);
+        replay_turn_times = Array.from(wasm_client().game_record_turn_times());
+        replay_duration = wasm_client().game_record_duration();
+        replay_time.max = replay_duration;
+        loading_status.connected();
+        show_replay_at(0);
+    });
+}
+
+function show_replay_at(time_ms) {
+    with_error_handling(function() {
+        const t = Math.min(Math.max(time_ms, 0), replay_duration);
+        wasm_client().show_game_record_at(t);
+        replay_current_time = t;
+        replay_time.value = t;
+        const seconds = Math.floor(t / 1000);
+        replay_time_label.innerText = `${Math.floor(seconds / 60)}:${String(seconds % 60).padStart(2, '0')}`;
+        update();
+    });
+}
+
+function show_replay_prev_turn() {
+    const prev = replay_turn_times.filter(t => t < replay_current_time);
+    show_replay_at(prev.length > 0 ? prev[prev.length - 1] : 0);
+}
+
+function show_replay_next_turn() {
+    const next = replay_turn_times.find(t => t > replay_current_time);
+    show_replay_at(next ?? replay_duration);
+}
+
 function make_piece_image(symbol_id) {
     const SVG_NS = 'http://www.w3.org/2000/svg';
     const symbol = document.createElementNS(SVG_NS, 'symbol');
@@ -828,10 +939,15 @@ function on_create_contest_confirm(event) {
         wasm_client().new_contest(
             data.get('player-name'),
             data.get('teaming'),
+            data.get('seat-rotation'),
             data.get('starting-position'),
             data.get('starting-time'),
             data.get('drop-aggression'),
             data.get('pawn-drop-rows'),
+            data.get('partner-hints'),
+            data.get('live-chalk'),
+            data.get('disconnect-policy'),
+            data.get('match-length'),
         );
         update();
         close_menu();
@@ -841,10 +957,13 @@ function on_create_contest_confirm(event) {
 function on_join_contest_confirm(event) {
     with_error_handling(function() {
         const data = new FormData(event.target);
-        wasm_client().join(
-            data.get('contest-id').toUpperCase(),
-            data.get('player-name'),
-        );
+        const contest_id = data.get('contest-id').toUpperCase();
+        const player_name = data.get('player-name');
+        if (data.get('join-mode') == 'spectator') {
+            wasm_client().spectate(contest_id, player_name);
+        } else {
+            wasm_client().join(contest_id, player_name);
+        }
         update();
         close_menu();
     });
@@ -29,4 +29,11 @@ module.exports = {
   experiments: {
     asyncWebAssembly: true,
   },
+  devServer: {
+    // Webserver pages and data, e.g. game records for the game viewer. In production both
+    // are served from the same origin.
+    proxy: {
+      '/dyn': 'http://localhost:14362',
+    },
+  },
 };